@@ -0,0 +1,70 @@
+/* the lyza engine over a C ABI. build with `cargo build --release -p lyza-ffi`
+ * and link against target/release/liblyza_ffi.so (or .dylib / .dll).
+ *
+ *     lyza_context *ctx = lyza_new(32, 16);
+ *     lyza_set(ctx, 0, 0, 'E');
+ *     lyza_tick(ctx);
+ *
+ *     lyza_event events[64];
+ *     size_t n = lyza_drain(ctx, events, 64);
+ *     ...
+ *     lyza_free(ctx);
+ */
+
+#ifndef LYZA_H
+#define LYZA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct LyzaContext lyza_context;
+
+typedef enum {
+    LYZA_NOTE_ON,   /* a channel + 256 * port, b note, c velocity */
+    LYZA_NOTE_OFF,  /* a channel + 256 * port, b note */
+    LYZA_CC,        /* a channel + 256 * port, b control, c value */
+    LYZA_OSC,       /* a value count, text "address v1 v2 ..." */
+    LYZA_UDP,       /* text payload */
+    LYZA_LOG,       /* text message */
+    LYZA_BANG,      /* a x, b y */
+    LYZA_MQTT,      /* a topic index, text payload */
+    LYZA_DMX,       /* a universe, b channel, c value */
+    LYZA_SC_NEW,    /* a synthdef index, b node, c value or -1 */
+    LYZA_SC_SET,    /* a param index, b node, c value */
+    LYZA_COMMAND,   /* text command line from `$` */
+} lyza_event_kind;
+
+typedef struct {
+    lyza_event_kind kind;
+    int32_t a;
+    int32_t b;
+    int32_t c;
+    /* null when unused, valid until the next lyza_drain or lyza_free */
+    const char *text;
+} lyza_event;
+
+lyza_context *lyza_new(size_t width, size_t height);
+void lyza_free(lyza_context *ctx);
+
+/* glyphs are unicode code points, 0 or '.' clears. 0 on success, -1 if the
+ * point is off the field or the glyph can't be written. */
+int lyza_set(lyza_context *ctx, int32_t x, int32_t y, uint32_t glyph);
+/* 0 when empty or off the field */
+uint32_t lyza_get(const lyza_context *ctx, int32_t x, int32_t y);
+
+/* runs a frame. 1 if a cell held an unknown operator, which was skipped. */
+int lyza_tick(lyza_context *ctx);
+uint32_t lyza_frame(const lyza_context *ctx);
+
+/* moves up to cap queued events into out, returning how many */
+size_t lyza_drain(lyza_context *ctx, lyza_event *out, size_t cap);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
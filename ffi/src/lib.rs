@@ -0,0 +1,201 @@
+//! a C interface to the lyza engine, for hosts that aren't written in rust.
+//! the matching declarations are in `include/lyza.h`.
+//!
+//! a context is created with the built in operators, fed cells and ticked
+//! a frame at a time, and the events each frame emits are drained into an
+//! array the host owns. every function takes the handle `lyza_new` returned
+//! and none of them keeps a pointer the host passed in.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use lyza::{Context, Event, Field, OpdefTable, Point};
+
+/// the handle C hosts hold
+pub struct LyzaContext {
+    ctx: Context,
+    // emitted by frames so far and not drained yet
+    events: Vec<Event>,
+    // the text of the last drained events, kept alive until the next drain
+    texts: Vec<CString>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LyzaEventKind {
+    NoteOn,
+    NoteOff,
+    Cc,
+    Osc,
+    Udp,
+    Log,
+    Bang,
+    Mqtt,
+    Dmx,
+    ScNew,
+    ScSet,
+    Command,
+}
+
+/// an event flattened for C. what `a`, `b`, `c` and `text` hold depends on
+/// the kind, see `include/lyza.h`. unused fields are 0 and `text` is null
+/// for kinds without any.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct LyzaEvent {
+    pub kind: LyzaEventKind,
+    pub a: i32,
+    pub b: i32,
+    pub c: i32,
+    pub text: *const c_char,
+}
+
+impl LyzaEvent {
+    fn new(kind: LyzaEventKind, a: i32, b: i32, c: i32) -> Self {
+        LyzaEvent { kind, a, b, c, text: ptr::null() }
+    }
+}
+
+/// a context over an empty `width`×`height` field with the built in
+/// operators. free it with `lyza_free`.
+#[no_mangle]
+pub extern "C" fn lyza_new(width: usize, height: usize) -> *mut LyzaContext {
+    let ctx = Context::new(OpdefTable::default(), Field::new(width, height));
+    Box::into_raw(Box::new(LyzaContext { ctx, events: Vec::new(), texts: Vec::new() }))
+}
+
+/// # Safety
+///
+/// `handle` must come from `lyza_new` and not have been freed. null is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn lyza_free(handle: *mut LyzaContext) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// writes a glyph, given as a unicode code point, with 0 or `.` clearing
+/// the cell. returns 0, or -1 for a point off the field or a glyph that
+/// can't be written.
+///
+/// # Safety
+///
+/// `handle` must be a live context from `lyza_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lyza_set(handle: *mut LyzaContext, x: i32, y: i32, glyph: u32) -> c_int {
+    let handle = &mut *handle;
+    match char::from_u32(glyph).map(|ch| handle.ctx.field.set(Point::new(x, y), ch)) {
+        Some(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// the glyph at a point as a code point, 0 when empty or off the field
+///
+/// # Safety
+///
+/// `handle` must be a live context from `lyza_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lyza_get(handle: *const LyzaContext, x: i32, y: i32) -> u32 {
+    (*handle).ctx.field.get(Point::new(x, y)).map_or(0, |ch| ch as u32)
+}
+
+/// runs a frame. returns 0, or 1 if a cell held a glyph with no operator or
+/// an operator failed, which the frame skipped.
+///
+/// # Safety
+///
+/// `handle` must be a live context from `lyza_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lyza_tick(handle: *mut LyzaContext) -> c_int {
+    let handle = &mut *handle;
+    let frame = handle.ctx.step();
+    handle.events.extend_from_slice(&frame.events);
+    if frame.warnings.is_empty() { 0 } else { 1 }
+}
+
+/// the frames run so far
+///
+/// # Safety
+///
+/// `handle` must be a live context from `lyza_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lyza_frame(handle: *const LyzaContext) -> u32 {
+    (*handle).ctx.frame_ct
+}
+
+/// moves up to `cap` queued events into `out`, oldest first, returning how
+/// many. anything that didn't fit stays queued for the next call. the
+/// `text` pointers stay valid until the next drain or `lyza_free`.
+///
+/// # Safety
+///
+/// `handle` must be a live context from `lyza_new` and `out` must have
+/// room for `cap` events.
+#[no_mangle]
+pub unsafe extern "C" fn lyza_drain(handle: *mut LyzaContext, out: *mut LyzaEvent, cap: usize) -> usize {
+    let handle = &mut *handle;
+    handle.texts.clear();
+
+    let n = cap.min(handle.events.len());
+    for (i, event) in handle.events.drain(..n).enumerate() {
+        let (mut flat, text) = flatten(&event);
+        if let Some(text) = text {
+            // interior nuls can't cross into c, the text stops there
+            let text = text.split('\0').next().unwrap_or("");
+            let text = CString::new(text).unwrap();
+            flat.text = text.as_ptr();
+            handle.texts.push(text);
+        }
+        *out.add(i) = flat;
+    }
+    n
+}
+
+// the port rides above the channel, so hosts with one device read the
+// channel as ever
+fn midi_a(port: u8, channel: u8) -> i32 {
+    (port as i32) << 8 | channel as i32
+}
+
+fn flatten(event: &Event) -> (LyzaEvent, Option<String>) {
+    use LyzaEventKind as K;
+
+    match event {
+        Event::NoteOn { port, channel, note, velocity } => {
+            (LyzaEvent::new(K::NoteOn, midi_a(*port, *channel), *note as i32, *velocity as i32), None)
+        }
+        Event::NoteOff { port, channel, note } => {
+            (LyzaEvent::new(K::NoteOff, midi_a(*port, *channel), *note as i32, 0), None)
+        }
+        Event::Cc { port, channel, control, value } => {
+            (LyzaEvent::new(K::Cc, midi_a(*port, *channel), *control as i32, *value as i32), None)
+        }
+        Event::Osc { address, values } => {
+            let mut text = address.clone();
+            for v in values {
+                text.push_str(&format!(" {}", v));
+            }
+            (LyzaEvent::new(K::Osc, values.len() as i32, 0, 0), Some(text))
+        }
+        Event::Udp(payload) => (LyzaEvent::new(K::Udp, 0, 0, 0), Some(payload.clone())),
+        Event::Log(msg) => (LyzaEvent::new(K::Log, 0, 0, 0), Some(msg.clone())),
+        Event::Bang(pt) => (LyzaEvent::new(K::Bang, pt.x, pt.y, 0), None),
+        Event::Mqtt { topic, payload } => {
+            (LyzaEvent::new(K::Mqtt, *topic as i32, 0, 0), Some(payload.clone()))
+        }
+        Event::Dmx { universe, channel, value } => {
+            (LyzaEvent::new(K::Dmx, *universe as i32, *channel as i32, *value as i32), None)
+        }
+        Event::ScNew { synthdef, node, value } => {
+            let value = value.map_or(-1, |v| v as i32);
+            (LyzaEvent::new(K::ScNew, *synthdef as i32, *node as i32, value), None)
+        }
+        Event::ScSet { param, node, value } => {
+            (LyzaEvent::new(K::ScSet, *param as i32, *node as i32, *value as i32), None)
+        }
+        Event::Command(line) => (LyzaEvent::new(K::Command, 0, 0, 0), Some(line.clone())),
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use lyza::{codec, Field, Matrix, Point, Stamp};
+
+// a song: named patterns taking turns in one region of the field, each for
+// so many bars, round and round. the rest of the field plays on as usual,
+// so the region is where the parts that change between verse and chorus
+// go. in a .lyza header:
+//
+//     region 0,0 8x4
+//     pattern verse 8x4:E...
+//     pattern chorus 8x4:S...
+//     section verse 4
+//     section chorus 2
+//
+// patterns are stored in the same code `share` prints.
+
+// four beats of four frames
+pub const FRAMES_PER_BAR: u32 = 16;
+
+#[derive(Clone, Default)]
+pub struct Arrangement {
+    // top left corner and size
+    pub region: Option<(Point, usize, usize)>,
+    pub patterns: BTreeMap<String, Field>,
+    // pattern names and how many bars each plays for
+    pub sections: Vec<(String, u32)>,
+    // the section in the region now, None until one's been put there
+    playing: Option<usize>,
+    // the bar line the song started on
+    start: u32,
+}
+
+impl Arrangement {
+    fn bars(&self) -> u32 {
+        self.sections.iter().map(|&(_, bars)| bars).sum()
+    }
+
+    // which section the song is in at a frame
+    pub fn section_at(&self, frame: u32) -> Option<usize> {
+        let total = self.bars();
+        if total == 0 {
+            return None;
+        }
+        let mut bar = frame.saturating_sub(self.start) / FRAMES_PER_BAR % total;
+        for (i, &(_, bars)) in self.sections.iter().enumerate() {
+            if bar < bars {
+                return Some(i);
+            }
+            bar -= bars;
+        }
+        None
+    }
+
+    pub fn playing(&self) -> Option<&str> {
+        self.playing.and_then(|i| self.sections.get(i)).map(|(name, _)| name.as_str())
+    }
+
+    // puts the pattern for the frame's section into the region when the
+    // section changes, which only happens on a bar line. the first one
+    // goes in straight away, wherever the song starts. true when the field
+    // was written to.
+    pub fn update(&mut self, field: &mut Field, frame: u32) -> bool {
+        let (origin, width, height) = match self.region {
+            Some(region) => region,
+            None => return false,
+        };
+        if self.playing.is_some() && !frame.is_multiple_of(FRAMES_PER_BAR) {
+            return false;
+        }
+        let section = self.section_at(frame);
+        if section == self.playing {
+            return false;
+        }
+        self.playing = section;
+        let pattern = match section.and_then(|i| self.patterns.get(&self.sections[i].0)) {
+            Some(pattern) => pattern,
+            None => return false,
+        };
+        let cells = Matrix::from_fn(width, height, |pt| pattern.get(pt).unwrap_or('\0'));
+        field.stamp(&cells, origin, Stamp::default()).is_ok()
+    }
+
+    // keeps what's in the region now as a pattern
+    pub fn store(&mut self, name: &str, field: &Field) -> Result<(), String> {
+        let (origin, width, height) = self.region.ok_or_else(|| "no region, set one with :region".to_string())?;
+        let mut pattern = Field::new(width, height);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let glyph = field.get(origin.translate(x, y)).unwrap_or('\0');
+                pattern.set(Point::new(x, y), glyph)?;
+            }
+        }
+        self.patterns.insert(name.to_string(), pattern);
+        Ok(())
+    }
+
+    // starts the song over from its first section, counting bars from the
+    // bar line `frame` is in
+    pub fn restart(&mut self, frame: u32) {
+        self.playing = None;
+        self.start = frame - frame % FRAMES_PER_BAR;
+    }
+
+    // the header lines for a .lyza file
+    pub fn header(&self) -> String {
+        let mut ret = String::new();
+        if let Some((origin, width, height)) = self.region {
+            ret.push_str(&format!("region {} {}x{}\n", origin, width, height));
+        }
+        for (name, pattern) in &self.patterns {
+            ret.push_str(&format!("pattern {} {}\n", name, codec::encode_field(pattern)));
+        }
+        for (name, bars) in &self.sections {
+            ret.push_str(&format!("section {} {}\n", name, bars));
+        }
+        ret
+    }
+
+    // takes a header line if it's one of ours, false if it isn't
+    pub fn parse_header(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["region", origin, size] => self.region = Some(region(origin, size)?),
+            ["pattern", name, code] => {
+                self.patterns.insert(name.to_string(), codec::decode_field(code)?);
+            }
+            ["section", name, bars] => self.sections.push((name.to_string(), self::bars(bars)?)),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+pub fn region(origin: &str, size: &str) -> Result<(Point, usize, usize), String> {
+    let origin: Point = origin.parse()?;
+    let invalid = || format!("invalid size `{}`, expected <width>x<height>", size);
+    let (w, h) = size.split_once('x').ok_or_else(invalid)?;
+    match (w.parse(), h.parse()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 && origin.x >= 0 && origin.y >= 0 => Ok((origin, w, h)),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn bars(arg: &str) -> Result<u32, String> {
+    match arg.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid bar count `{}`", arg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `a` for two bars then `b` for one, in the field's top left corner
+    fn song() -> Arrangement {
+        let mut song = Arrangement::default();
+        for line in ["region 0,0 2x1", "pattern a 2x1:A.", "pattern b 2x1:B.", "section a 2", "section b 1"] {
+            assert_eq!(song.parse_header(line), Ok(true), "{}", line);
+        }
+        song
+    }
+
+    fn corner(field: &Field) -> (char, char) {
+        (field.get(Point::new(0, 0)).unwrap(), field.get(Point::new(1, 0)).unwrap())
+    }
+
+    #[test]
+    fn sections_loop() {
+        let song = song();
+        let at = |bar: u32| song.section_at(bar * FRAMES_PER_BAR);
+        assert_eq!([at(0), at(1), at(2), at(3), at(4), at(5)], [Some(0), Some(0), Some(1), Some(0), Some(0), Some(1)]);
+        assert_eq!(song.section_at(2 * FRAMES_PER_BAR - 1), Some(0));
+        assert_eq!(Arrangement::default().section_at(0), None);
+    }
+
+    #[test]
+    fn bars_count_from_the_restart() {
+        let mut song = song();
+        song.restart(2 * FRAMES_PER_BAR + 5);
+        assert_eq!(song.section_at(2 * FRAMES_PER_BAR), Some(0));
+        assert_eq!(song.section_at(4 * FRAMES_PER_BAR), Some(1));
+        // frames before it are the first bar
+        assert_eq!(song.section_at(0), Some(0));
+    }
+
+    #[test]
+    fn sections_switch_on_the_bar_line() {
+        let (mut song, mut field) = (song(), Field::new(4, 2));
+        field.set(Point::new(1, 0), '*').unwrap();
+        assert!(song.update(&mut field, 0));
+        assert_eq!((corner(&field), song.playing()), (('A', '\0'), Some("a")));
+        field.set(Point::new(1, 0), '*').unwrap();
+
+        // nothing between bar lines, nor on ones within the section
+        for frame in 1..2 * FRAMES_PER_BAR {
+            assert!(!song.update(&mut field, frame), "{}", frame);
+        }
+        assert_eq!(corner(&field), ('A', '*'));
+        assert!(song.update(&mut field, 2 * FRAMES_PER_BAR));
+        assert_eq!((corner(&field), song.playing()), (('B', '\0'), Some("b")));
+        for frame in 2 * FRAMES_PER_BAR + 1..3 * FRAMES_PER_BAR {
+            assert!(!song.update(&mut field, frame), "{}", frame);
+        }
+        assert!(song.update(&mut field, 3 * FRAMES_PER_BAR));
+        assert_eq!((corner(&field), song.playing()), (('A', '\0'), Some("a")));
+    }
+
+    #[test]
+    fn songs_start_mid_bar() {
+        let (mut song, mut field) = (song(), Field::new(2, 1));
+        assert!(song.update(&mut field, 2 * FRAMES_PER_BAR + 3));
+        assert_eq!((corner(&field), song.playing()), (('B', '\0'), Some("b")));
+        song.restart(2 * FRAMES_PER_BAR + 7);
+        assert!(song.update(&mut field, 2 * FRAMES_PER_BAR + 7));
+        assert_eq!(song.playing(), Some("a"));
+    }
+
+    #[test]
+    fn missing_patterns_leave_the_region_alone() {
+        let (mut song, mut field) = (song(), Field::new(2, 1));
+        song.patterns.remove("b");
+        field.set(Point::new(0, 0), '*').unwrap();
+        assert!(!song.update(&mut field, 2 * FRAMES_PER_BAR));
+        assert_eq!((corner(&field), song.playing()), (('*', '\0'), Some("b")));
+        assert!(!Arrangement::default().update(&mut field, 0));
+    }
+
+    #[test]
+    fn headers_round_trip() {
+        let mut song = song();
+        let mut field = Field::new(3, 2);
+        field.set(Point::new(2, 1), 'E').unwrap();
+        song.region = Some((Point::new(0, 0), 3, 2));
+        song.store("c", &field).unwrap();
+        song.sections.push(("c".to_string(), 12));
+
+        let mut read = Arrangement::default();
+        for line in song.header().lines() {
+            assert_eq!(read.parse_header(line), Ok(true), "{}", line);
+        }
+        assert_eq!(read.header(), song.header());
+        assert_eq!((read.region, &read.sections), (song.region, &song.sections));
+        assert!(read.patterns == song.patterns);
+        assert!(read.patterns["c"] == field);
+    }
+
+    #[test]
+    fn bad_headers_are_refused() {
+        let mut song = Arrangement::default();
+        assert_eq!(song.parse_header("bpm 120"), Ok(false));
+        assert_eq!(song.parse_header("section a"), Ok(false));
+        assert!(song.parse_header("region 0,0 0x4").is_err());
+        assert!(song.parse_header("region -1,0 2x2").is_err());
+        assert!(song.parse_header("region 0,0 2by2").is_err());
+        assert_eq!(song.parse_header("section a 0"), Err("invalid bar count `0`".to_string()));
+        assert!(song.parse_header("pattern a 2x1:A.B").is_err());
+        assert_eq!(song.header(), "");
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::UdpSocket;
+
+// art-net ArtDmx sender. channel values are buffered per universe and a
+// universe is only resent when something in it changed.
+
+const ART_DMX: u16 = 0x5000;
+const PROTOCOL_VERSION: u16 = 14;
+pub const UNIVERSE_SIZE: usize = 512;
+
+pub struct ArtNet {
+    socket: UdpSocket,
+    dest: String,
+    sequence: u8,
+    universes: HashMap<u16, [u8; UNIVERSE_SIZE]>,
+    dirty: Vec<u16>,
+}
+
+impl ArtNet {
+    pub fn open(dest: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_broadcast(true)?;
+        Ok(Self {
+            socket,
+            dest: dest.to_string(),
+            sequence: 0,
+            universes: HashMap::new(),
+            dirty: Vec::new(),
+        })
+    }
+
+    // channels are 1-based, as on every lighting desk
+    pub fn set(&mut self, universe: u16, channel: u16, value: u8) {
+        if channel == 0 || channel as usize > UNIVERSE_SIZE {
+            return;
+        }
+        let data = self.universes.entry(universe).or_insert([0; UNIVERSE_SIZE]);
+        let slot = &mut data[channel as usize - 1];
+        if *slot != value {
+            *slot = value;
+            if !self.dirty.contains(&universe) {
+                self.dirty.push(universe);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        for universe in self.dirty.drain(..) {
+            self.sequence = self.sequence.wrapping_add(1).max(1);
+            let packet = art_dmx(self.sequence, universe, &self.universes[&universe]);
+            self.socket.send_to(&packet, &self.dest)?;
+        }
+        Ok(())
+    }
+}
+
+fn art_dmx(sequence: u8, universe: u16, data: &[u8; UNIVERSE_SIZE]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(18 + UNIVERSE_SIZE);
+    ret.extend_from_slice(b"Art-Net\0");
+    ret.extend_from_slice(&ART_DMX.to_le_bytes());
+    ret.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    ret.push(sequence);
+    ret.push(0);                                // physical
+    ret.push((universe & 0xff) as u8);          // sub-net and universe
+    ret.push(((universe >> 8) & 0x7f) as u8);   // net
+    ret.extend_from_slice(&(UNIVERSE_SIZE as u16).to_be_bytes());
+    ret.extend_from_slice(data);
+    ret
+}
@@ -0,0 +1,504 @@
+use std::convert::TryInto;
+use std::io::{self, IsTerminal};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::allocs;
+use crate::commands::Request;
+use crate::config::Config;
+use crate::conformance::{self, Case, Outcome};
+use crate::control;
+use crate::http;
+use crate::render::{Changes, Renderer};
+use crate::replay::{self, Recorder, Replay};
+use crate::theme::Theme;
+use crate::project::{self, Format, Project};
+use crate::session::{self, Session};
+use crate::trace::Tracer;
+use crate::tui;
+use crate::websocket;
+use lyza::{codec, testing, xorshift, Context, Field, LyzaError, OpdefTable};
+
+static USAGE: &str = "\
+usage: lyza [options] <command> [args]
+
+commands:
+    edit <file> [--record <path>]      open a patch in the editor, recording edits to <path>
+    run <file> [run options]           play a patch, printing every frame
+    serve <file> [run options]         play a patch with no output, for remote control
+    convert <in> <out>                 convert between .orca and .lyza
+    share <file>                       print a patch as a single line to paste elsewhere
+    import <code> <out>                save a line printed by share as a patch
+    bench <file> [--frames <n>]        run a patch flat out and report how fast it goes
+    fuzz [--runs <n>] [--seed <n>]     run random fields looking for operators that panic
+    conformance [<dir>] [--json]       compare the orca rules against frames from orca, with
+                                       the cases shipped with lyza or the ones in <dir>
+    operators                          list the operators
+
+run options:
+    --frames <n>          stop after n frames
+    --control <fifo>      read control commands from a named pipe
+    --http <addr>         serve the http control api on addr, e.g. 127.0.0.1:7777
+    --ws <addr>           accept websocket control connections on addr
+    --osc-listen <addr>   take osc control messages on a udp addr
+    --color <when>        color the output: auto, always or never (default auto)
+    --border              draw a frame around the field
+    --guides <n>          draw guide lines every n cells
+    --diff                highlight cells that changed in the last frame
+    --stats               report frame timing and allocations on stderr every few beats
+    --check-invariants    check the engine's bookkeeping after every frame (slow)
+    --trace <path>        write what every operator read and wrote to <path>, as json lines
+    --seed <n>            seed the random operators
+    --record <path>       write every control command to <path> with the frame it came in
+    --replay <path>       send the commands in a recording again, at the same frames
+
+options:
+    --config <path>       read settings from <path>
+    --bpm <n>             tempo in beats per minute
+    --theme <name>        display theme: default, mono or dots
+    --midi-device <path>  raw midi device to write to, e.g. /dev/snd/midiC1D0, or named
+                          for routing, e.g. drums=/dev/snd/midiC1D0 (repeatable)
+    --midi-route <name>   the device the next note port goes to, from port 0 (repeatable)
+    --humanize-velocity <n>
+                          vary note velocities by up to n either way, at random
+    --humanize-timing <n> send notes up to n percent of a 16th late, at random, at most 50
+    --tuning <spec>       send midi notes as steps of edo:<n> or a .scl file, over mpe
+    --osc <host:port>     osc destination (repeatable)
+    --mqtt <host:port>    mqtt broker for the `&` operator
+    --artnet <host:port>  art-net node for the `^` operator
+    --scsynth <host:port> supercollider server for the `~` operator
+    --udp <host:port>     destination for raw udp messages
+    --dry-run             connect to nothing and report the events that would have gone out
+    -h, --help            show this message";
+
+pub fn main(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "-h" || a == "--help") || args.is_empty() {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let (config, rest) = Config::load(args)?;
+    let bpm_given = args.iter().any(|a| a == "--bpm");
+
+    let (command, rest) = rest.split_first()
+                              .ok_or_else(|| "missing command".to_string())?;
+
+    match command.as_str() {
+        "edit" => {
+            let mut rest = rest.to_vec();
+            let record = match rest.iter().position(|a| a == "--record") {
+                Some(i) if i + 1 < rest.len() => {
+                    let path = rest.remove(i + 1);
+                    rest.remove(i);
+                    Some(PathBuf::from(path))
+                }
+                Some(_) => return Err("--record needs a value".to_string()),
+                None => None,
+            };
+            let [file] = positional::<1>(command, &rest)?;
+            edit(&config, bpm_given, Path::new(&file), record.as_deref())
+        }
+        "run" => {
+            let (opts, rest) = RunOptions::parse(rest)?;
+            let [file] = positional::<1>(command, &rest)?;
+            run(&config, bpm_given, Path::new(&file), &opts)
+        }
+        "serve" => {
+            let (opts, rest) = RunOptions::parse(rest)?;
+            let [file] = positional::<1>(command, &rest)?;
+            run(&config, bpm_given, Path::new(&file), &RunOptions { headless: true, ..opts })
+        }
+        "convert" => {
+            let [input, output] = positional::<2>(command, rest)?;
+            convert(Path::new(&input), Path::new(&output))
+        }
+        "share" => {
+            let [file] = positional::<1>(command, rest)?;
+            println!("{}", codec::encode_field(&Project::load(Path::new(&file))?.field));
+            Ok(())
+        }
+        "import" => {
+            let [code, output] = positional::<2>(command, rest)?;
+            Project::new(codec::decode_field(&code)?).save(Path::new(&output))
+        }
+        "bench" => {
+            let (opts, rest) = RunOptions::parse(rest)?;
+            let [file] = positional::<1>(command, &rest)?;
+            bench(Path::new(&file), opts.frames.unwrap_or(BENCH_FRAMES))
+        }
+        "fuzz" => {
+            let (opts, rest) = RunOptions::parse(rest)?;
+            positional::<0>(command, &rest)?;
+            fuzz(opts.runs.unwrap_or(FUZZ_RUNS), opts.seed.unwrap_or_else(replay::clock_seed))
+        }
+        "conformance" => {
+            let json = rest.iter().any(|a| a == "--json");
+            let rest: Vec<String> = rest.iter().filter(|a| *a != "--json").cloned().collect();
+            let cases = match rest.len() {
+                0 => conformance::builtin()?,
+                _ => {
+                    let [dir] = positional::<1>(command, &rest)?;
+                    conformance::load_dir(Path::new(&dir))?
+                }
+            };
+            conformance(&cases, json);
+            Ok(())
+        }
+        "operators" => {
+            positional::<0>(command, rest)?;
+            operators();
+            Ok(())
+        }
+        _ => Err(format!("unknown command `{}`, see --help", command)),
+    }
+}
+
+#[derive(Default)]
+struct RunOptions {
+    frames: Option<u64>,
+    runs: Option<u64>,
+    fifo: Option<PathBuf>,
+    http: Option<String>,
+    ws: Option<String>,
+    osc_listen: Option<String>,
+    headless: bool,
+    color: bool,
+    border: bool,
+    guides: Option<usize>,
+    diff: bool,
+    stats: bool,
+    seed: Option<u64>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    check_invariants: bool,
+    trace: Option<PathBuf>,
+}
+
+impl RunOptions {
+    fn parse(args: &[String]) -> Result<(Self, Vec<String>), String> {
+        let mut ret = RunOptions {
+            color: io::stdout().is_terminal(),
+            ..Default::default()
+        };
+        let mut rest = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--frames" => {
+                    let v = value()?;
+                    ret.frames = Some(v.parse().map_err(|_| format!("invalid frame count `{}`", v))?);
+                }
+                "--runs" => {
+                    let v = value()?;
+                    ret.runs = Some(v.parse().map_err(|_| format!("invalid run count `{}`", v))?);
+                }
+                "--control" => ret.fifo = Some(PathBuf::from(value()?)),
+                "--http" => ret.http = Some(value()?.clone()),
+                "--ws" => ret.ws = Some(value()?.clone()),
+                "--osc-listen" => ret.osc_listen = Some(value()?.clone()),
+                "--color" => {
+                    ret.color = match value()?.as_str() {
+                        "always" => true,
+                        "never" => false,
+                        "auto" => io::stdout().is_terminal(),
+                        other => return Err(format!("invalid --color `{}`", other)),
+                    };
+                }
+                "--border" => ret.border = true,
+                "--diff" => ret.diff = true,
+                "--stats" => ret.stats = true,
+                "--check-invariants" => ret.check_invariants = true,
+                "--seed" => {
+                    let v = value()?;
+                    ret.seed = Some(v.parse().map_err(|_| format!("invalid seed `{}`", v))?);
+                }
+                "--record" => ret.record = Some(PathBuf::from(value()?)),
+                "--replay" => ret.replay = Some(PathBuf::from(value()?)),
+                "--trace" => ret.trace = Some(PathBuf::from(value()?)),
+                "--guides" => {
+                    let v = value()?;
+                    match v.parse() {
+                        Ok(n) if n > 0 => ret.guides = Some(n),
+                        _ => return Err(format!("invalid guide spacing `{}`", v)),
+                    }
+                }
+                _ => rest.push(arg.clone()),
+            }
+        }
+
+        Ok((ret, rest))
+    }
+}
+
+fn positional<const N: usize>(command: &str, args: &[String]) -> Result<[String; N], String> {
+    if let Some(flag) = args.iter().find(|a| a.starts_with("--")) {
+        return Err(format!("{}: unknown option `{}`", command, flag));
+    }
+    args.to_vec()
+        .try_into()
+        .map_err(|_| format!("{}: expected {} argument(s), see --help", command, N))
+}
+
+fn edit(config: &Config, bpm_given: bool, path: &Path, record: Option<&Path>) -> Result<(), String> {
+    let recovered = project::recovery_path(path).exists() && ask_restore(path)?;
+    let project = if recovered {
+        project::load_recovery(path)?
+    } else if path.exists() {
+        Project::load(path)?
+    } else {
+        Format::from_path(path)?;
+        Project::new(Field::new(32, 16))
+    };
+    let bpm = match project.header.bpm {
+        Some(bpm) if !bpm_given => bpm,
+        _ => config.bpm,
+    };
+    let recorder = record.map(|p| Recorder::create(p, None)).transpose()?;
+    tui::run(config, path, project, bpm, recovered, recorder)
+}
+
+// only asks when there's someone to answer
+fn ask_restore(path: &Path) -> Result<bool, String> {
+    let recovery = project::recovery_path(path);
+    if !io::stdin().is_terminal() {
+        eprintln!("lyza: ignoring unsaved changes in {}", recovery.display());
+        return Ok(false);
+    }
+    eprint!("lyza: {} has unsaved changes from a previous session in {}\nrestore them? [y/N] ",
+            path.display(), recovery.display());
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(|e| e.to_string())?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+// frames between --stats reports, four beats
+const STATS_EVERY: u64 = 16;
+
+fn run(config: &Config, bpm_given: bool, path: &Path, opts: &RunOptions) -> Result<(), String> {
+    let project = Project::load(path)?;
+    let bpm = match project.header.bpm {
+        Some(bpm) if !bpm_given => bpm,
+        _ => config.bpm,
+    };
+
+    let mut session = Session::new(config, project, bpm)?;
+    // a replay runs on the seed it was recorded with, and a recording
+    // always has one, so it can be
+    let mut replay = opts.replay.as_deref().map(Replay::load).transpose()?;
+    let seed = opts.seed.or(replay.as_ref().map(|r| r.seed));
+    let mut recorder = opts.record.as_deref().map(|p| Recorder::create(p, seed)).transpose()?;
+    if let Some(seed) = recorder.as_ref().map(|r| r.seed).or(seed) {
+        session.ctx.reseed(seed);
+    }
+    session.ctx.check_invariants = opts.check_invariants;
+    let mut tracer = opts.trace.as_deref().map(Tracer::create).transpose()?;
+    session.ctx.trace = tracer.is_some();
+    let mut renderer = Renderer::new(Theme::from_config(config)?);
+    renderer.color = opts.color;
+    renderer.border = opts.border;
+    renderer.guides = opts.guides;
+    renderer.changes = opts.diff.then(|| Changes::new(&session.ctx.field));
+    let status = Arc::new(Mutex::new(session.status()));
+
+    let (tx, commands) = mpsc::channel();
+    if let Some(fifo) = &opts.fifo {
+        control::spawn_fifo(fifo, tx.clone())?;
+    }
+    if let Some(addr) = &opts.http {
+        http::spawn(addr, status.clone(), tx.clone())?;
+    }
+    if let Some(addr) = &opts.ws {
+        websocket::spawn(addr, status.clone(), tx.clone())?;
+    }
+    if let Some(addr) = &opts.osc_listen {
+        control::spawn_osc(addr, tx.clone())?;
+    }
+    drop(tx);
+
+    let mut next = Instant::now();
+    let print = |renderer: &mut Renderer, ctx: &Context| {
+        if !opts.headless {
+            println!("{}", renderer.render(&ctx.field, ctx.rules.opdefs()));
+        }
+    };
+
+    print(&mut renderer, &session.ctx);
+    let mut ran = 0;
+    let mut allocated = allocs::count();
+    // frames where a `?` failed, for the exit status
+    let mut failing = 0;
+    'frames: while opts.frames.is_none_or(|n| ran < n) {
+        let replayed = replay.as_mut().map_or_else(Vec::new, |r| r.due(session.ctx.frame_ct));
+        for cmd in replayed.into_iter().chain(commands.try_iter()) {
+            if let Err(e) = session.apply(&cmd) {
+                eprintln!("lyza: {}", e);
+                continue;
+            }
+            if let Some(recorder) = &mut recorder {
+                if let Err(e) = recorder.log(session.ctx.frame_ct, &cmd) {
+                    eprintln!("lyza: {}", e);
+                }
+            }
+        }
+        session.update_status(&mut status.lock().unwrap());
+
+        next += session.frame_duration();
+        if let Some(wait) = next.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        if session.transport.paused {
+            continue;
+        }
+        session.ctx.stats.late(Instant::now().saturating_duration_since(next));
+        for e in session.tick() {
+            eprintln!("lyza: {}", e);
+        }
+        if let Some(tracer) = &mut tracer {
+            if let Err(e) = tracer.write(session.ctx.last_frame()) {
+                eprintln!("lyza: {}", e);
+            }
+        }
+        let assertion = |w: &LyzaError| match w {
+            LyzaError::OperatorFailed { error, .. } => matches!(**error, LyzaError::Assertion { .. }),
+            _ => false,
+        };
+        if session.ctx.last_frame().warnings.iter().any(assertion) {
+            failing += 1;
+        }
+        renderer.update(&session.ctx.field);
+        print(&mut renderer, &session.ctx);
+        ran += 1;
+        if opts.stats && ran % STATS_EVERY == 0 {
+            eprintln!("lyza: {}, {} allocations", session.ctx.stats, allocs::count() - allocated);
+            allocated = allocs::count();
+        }
+
+        for req in std::mem::take(&mut session.requests) {
+            match req {
+                Request::Write(to) => {
+                    let to = to.map_or_else(|| path.to_owned(), PathBuf::from);
+                    if let Err(e) = project::save(&to, &session.ctx.field, &session.header()) {
+                        eprintln!("lyza: {}", e);
+                    }
+                }
+                Request::Quit { .. } => break 'frames,
+                // no cursor to move
+                Request::Goto(_) => {}
+            }
+        }
+    }
+
+    if opts.stats && ran % STATS_EVERY != 0 {
+        eprintln!("lyza: {}, {} allocations", session.ctx.stats, allocs::count() - allocated);
+    }
+    if failing > 0 {
+        return Err(format!("assertions failed in {} of {} frames", failing, ran));
+    }
+    Ok(())
+}
+
+// frames `bench` runs when not told
+const BENCH_FRAMES: u64 = 1000;
+
+// the operators that ran most, with how often, in `bench`'s report
+const BENCH_HOT_OPS: usize = 10;
+
+// runs frames back to back with nothing connected, so what's measured is
+// the engine alone. events are dropped and `$` commands don't run.
+fn bench(path: &Path, frames: u64) -> Result<(), String> {
+    let project = Project::load(path)?;
+    let mut ctx = Context::with_rules(session::rule_set(project.header.rules.as_deref())?, project.field);
+
+    let allocated = allocs::count();
+    let started = Instant::now();
+    for _ in 0..frames {
+        ctx.step();
+    }
+    let took = started.elapsed();
+    let allocations = allocs::count() - allocated;
+
+    let per_frame = |n: u64| if frames == 0 { 0.0 } else { n as f64 / frames as f64 };
+    println!("{} frames in {:?}, {:.0} frames/sec", frames, took, frames as f64 / took.as_secs_f64());
+    println!("{} allocations, {:.2} a frame", allocations, per_frame(allocations));
+    println!("{:?} a frame on average, {:?} at most", ctx.stats.mean_busy(), ctx.stats.max_busy);
+    println!("{:.1} ops and {:.1} events a frame", per_frame(ctx.stats.ops), per_frame(ctx.stats.events));
+
+    let mut hot: Vec<(char, u64)> = ctx.stats.by_op.iter().map(|(&op, &n)| (op, n)).collect();
+    hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    for (op, n) in hot.into_iter().take(BENCH_HOT_OPS) {
+        println!("  {} {:>10} runs {:>5.1}%  {:.2} a frame", op, n,
+                 n as f64 * 100.0 / ctx.stats.ops as f64, per_frame(n));
+    }
+    Ok(())
+}
+
+// fields `fuzz` tries when not told
+const FUZZ_RUNS: u64 = 10_000;
+
+// what the generated cells are mostly made of, so fields hold working
+// patches rather than noise. the rest are any byte at all.
+const FUZZ_GLYPHS: &[u8] = b"......0123456789abcdefz*#?EHNSW$&^~:ABCDFGg";
+
+// feeds `testing::fuzz_tick` random bytes until one panics, then prints
+// them as hex so the field can be looked at. the panic message itself
+// comes out through the usual hook.
+fn fuzz(runs: u64, seed: u64) -> Result<(), String> {
+    println!("seed {}", seed);
+    let mut state = seed.max(1);
+    let mut next = move || xorshift(&mut state);
+    let mut data = Vec::new();
+    for run in 0..runs {
+        data.clear();
+        let (width, height) = (next() % 17, next() % 17);
+        data.extend([width as u8, height as u8]);
+        for _ in 0..width * height {
+            let r = next();
+            data.push(if r % 4 == 0 { (r >> 8) as u8 } else { FUZZ_GLYPHS[(r >> 8) as usize % FUZZ_GLYPHS.len()] });
+        }
+        if panic::catch_unwind(|| testing::fuzz_tick(&data)).is_err() {
+            let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+            return Err(format!("run {} panicked on {}", run, hex));
+        }
+    }
+    println!("{} runs, no panics", runs);
+    Ok(())
+}
+
+fn conformance(cases: &[Case], json: bool) {
+    let mut passed = 0;
+    for case in cases {
+        let outcome = case.run();
+        if let Outcome::Pass = outcome {
+            passed += 1;
+        }
+        if json {
+            println!("{}", conformance::json(case, &outcome));
+        } else {
+            println!("{}", conformance::describe(case, &outcome));
+        }
+    }
+    if !json {
+        println!("{} of {} cases match orca", passed, cases.len());
+    }
+}
+
+fn operators() {
+    let table: OpdefTable = OpdefTable::default();
+    for glyph in table.glyphs() {
+        if let Some(opd) = table.find(glyph) {
+            let aliases: String = opd.aliases.iter().collect();
+            let line = format!("{} {:<3} {:<10} {:<9} {:<24} {}", glyph, aliases, opd.long_name,
+                               opd.category.name(), opd.port_names(), opd.tags.join(" "));
+            println!("{}", line.trim_end());
+        }
+    }
+}
+
+fn convert(input: &Path, output: &Path) -> Result<(), String> {
+    Project::load(input)?.save(output)
+}
@@ -0,0 +1,212 @@
+//! glyphs as numbers. values run 0-35 through the digits and then the
+//! letters, and a letter means the same in either case: `b` and `B` are
+//! both 11. operators that write a value back keep the case of the input it
+//! came from, so uppercase patches stay uppercase.
+//!
+//! the base 64 functions are a wider alphabet, where uppercase letters are
+//! 36-61 and `?` and `!` come last. operators read values as above; base
+//! 64 is for counts written into field codes.
+//!
+//! whole fields encode to a single line too, for sharing patches.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{CellLike, Field, LyzaError, Point};
+
+/// how many values a single glyph holds
+pub const BASE: u8 = 36;
+
+static ALPHABET: &[u8] = "0123456789abcdefghijklmnopqrstuvwxyz".as_bytes();
+
+/// the value of a glyph, or None if it isn't a digit or a letter
+pub fn decode(ch: char) -> Option<u8> {
+    match ch {
+        '0'..='9' => Some(ch as u8 - b'0'),
+        'a'..='z' => Some(ch as u8 - b'a' + 10),
+        'A'..='Z' => Some(ch as u8 - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// the lowercase glyph for a value, or None from 36 up
+pub fn encode(value: u8) -> Option<char> {
+    ALPHABET.get(value as usize).map(|&byte| byte as char)
+}
+
+/// the glyph for a value in the case of `like`, for writing a result back
+/// next to the input it came from. digits have no case.
+pub fn encode_like(value: u8, like: char) -> Option<char> {
+    let ch = encode(value)?;
+    Some(if is_upper(like) { ch.to_ascii_uppercase() } else { ch })
+}
+
+pub fn is_upper(ch: char) -> bool {
+    ch.is_ascii_uppercase()
+}
+
+/// pins a result into 0-35
+pub fn clamp(value: i64) -> u8 {
+    value.clamp(0, BASE as i64 - 1) as u8
+}
+
+/// wraps a result around into 0-35, so 36 is 0 and -1 is 35
+pub fn wrap(value: i64) -> u8 {
+    value.rem_euclid(BASE as i64) as u8
+}
+
+//
+
+static ENCODE_TABLE: &[u8] = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ?!".as_bytes();
+// built at compile time, so there's nothing to initialise or share
+static DECODE_TABLE: [u8; 256] = decode_table();
+
+const fn decode_table() -> [u8; 256] {
+    let mut ret = [0; 256];
+    let mut i = 0;
+    while i < ENCODE_TABLE.len() {
+        ret[ENCODE_TABLE[i] as usize] = i as u8;
+        i += 1;
+    }
+    ret
+}
+
+/// the value of a glyph, 0-63. glyphs outside the alphabet read as 0.
+pub fn decode_base64(ch: char) -> u8 {
+    DECODE_TABLE.get(ch as usize).copied().unwrap_or(0)
+}
+
+/// like `decode_base64`, with None for glyphs outside the alphabet
+pub fn try_decode_base64(ch: char) -> Option<u8> {
+    ENCODE_TABLE.iter().position(|&byte| byte as char == ch).map(|i| i as u8)
+}
+
+/// the glyph for a value, 0-63
+pub fn encode_base64(int: u8) -> Result<char, LyzaError> {
+    match ENCODE_TABLE.get(int as usize) {
+        Some(&byte) => Ok(byte as char),
+        None => Err(LyzaError::Invalid(format!("{} is out of range 0-63", int))),
+    }
+}
+
+//
+
+// starts a run in a compact field: the marker, the length less one as a
+// base 64 glyph, then the glyph repeated
+const RUN: char = '/';
+
+/// the widest or tallest field a code can give, which is as far as
+/// frontends let a field be resized. codes come from anywhere, and one
+/// asking for more would take all the memory there is.
+pub const MAX_SIDE: usize = 1024;
+// shorter runs are cheaper written out
+const MIN_RUN: usize = 4;
+
+/// a whole field on one line, small enough to paste into a chat message or
+/// a url: `WxH:` and then the cells row by row with `.` for empty ones.
+/// runs of the same glyph shrink to `/`, the length and the glyph, and
+/// empty cells at the end are left off.
+///
+/// ```
+/// use lyza::{codec, Field, Point};
+///
+/// let mut field = Field::new(8, 2);
+/// field.set(Point::new(1, 0), 'E').unwrap();
+/// assert_eq!(codec::encode_field(&field), "8x2:.E");
+/// assert!(codec::decode_field("8x2:.E").unwrap() == field);
+/// ```
+pub fn encode_field<C: CellLike>(field: &Field<C>) -> String {
+    let mut cells: Vec<char> = field.slots.as_slice().iter().map(|slot| match slot.glyph() {
+        '\0' => '.',
+        ch => ch,
+    }).collect();
+    while cells.last() == Some(&'.') {
+        cells.pop();
+    }
+
+    let mut ret = format!("{}x{}:", field.slots.width, field.slots.height);
+    let mut rest = &cells[..];
+    while let Some(&ch) = rest.first() {
+        let len = rest.iter().take(64).take_while(|&&c| c == ch).count();
+        if len >= MIN_RUN || ch == RUN {
+            ret.push(RUN);
+            ret.push(encode_base64(len as u8 - 1).unwrap());
+            ret.push(ch);
+        } else {
+            ret.extend(&rest[..len]);
+        }
+        rest = &rest[len..];
+    }
+    ret
+}
+
+/// reads back what `encode_field` wrote
+pub fn decode_field(src: &str) -> Result<Field, LyzaError> {
+    let invalid = |msg: &str| LyzaError::Parse(format!("invalid field code: {}", msg));
+
+    let (size, cells) = src.trim().split_once(':').ok_or_else(|| invalid("missing size"))?;
+    let (width, height) = size.split_once('x').ok_or_else(|| invalid("size should be WxH"))?;
+    let width: usize = width.parse().map_err(|_| invalid("bad width"))?;
+    let height: usize = height.parse().map_err(|_| invalid("bad height"))?;
+    if !(1..=MAX_SIDE).contains(&width) || !(1..=MAX_SIDE).contains(&height) {
+        return Err(invalid(&format!("width and height should be 1-{}", MAX_SIDE)));
+    }
+    let area = width.checked_mul(height).ok_or_else(|| invalid("size too large"))?;
+
+    let mut field = Field::new(width, height);
+    let mut idx = 0;
+    let mut chars = cells.chars();
+    while let Some(ch) = chars.next() {
+        let (len, ch) = if ch == RUN {
+            match (chars.next().and_then(try_decode_base64), chars.next()) {
+                (Some(len), Some(ch)) => (len as usize + 1, ch),
+                _ => return Err(invalid("unfinished run")),
+            }
+        } else {
+            (1, ch)
+        };
+        for _ in 0..len {
+            if idx >= area {
+                return Err(invalid("more cells than the size allows"));
+            }
+            let pt = Point::new((idx % width) as i32, (idx / width) as i32);
+            field.set(pt, ch)?;
+            idx += 1;
+        }
+    }
+    Ok(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_round_trips() {
+        let mut field = Field::new(12, 3);
+        for (i, ch) in "E..*....SSSSSS/W".chars().enumerate() {
+            let pt = Point::new((i % 12) as i32, (i / 12) as i32);
+            field.set(pt, if ch == '.' { '\0' } else { ch }).unwrap();
+        }
+        let code = encode_field(&field);
+        assert!(decode_field(&code).unwrap() == field, "{}", code);
+        assert!(decode_field(&encode_field(&Field::new(1, 1))).unwrap() == Field::new(1, 1));
+    }
+
+    #[test]
+    fn oversized_fields_are_refused() {
+        for code in ["99999999x99999999:", "1025x1:", "1x1025:", "0x4:", "4x0:",
+                     "18446744073709551615x2:E"] {
+            assert!(matches!(decode_field(code), Err(LyzaError::Parse(_))), "{}", code);
+        }
+        assert!(decode_field("1024x1024:").is_ok());
+    }
+
+    #[test]
+    fn garbled_codes_are_refused() {
+        for code in ["", "8x2", "8by2:E", "x2:E", "8x:E", "-1x2:", "2x2:EEEEE", "8x2:/", "8x2:/3"] {
+            assert!(matches!(decode_field(code), Err(LyzaError::Parse(_))), "{}", code);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use crate::arrange;
+use crate::config::check_bpm;
+use crate::groove::{self, Groove};
+use crate::session::Session;
+use lyza::{codec, Point};
+
+// named commands, typed on the editor's `:` line or spelled out on the grid
+// after a `$` operator, where `:` stands in for the spaces a grid can't hold:
+//
+//     :resize 40 20        $resize:40:20
+//     :bpm 120             $bpm:120
+//     :seed 7              $seed:7
+//     :w [path]            $w, which can't name a path
+//     :q, :q!              $q
+//     :goto 3,5
+//     :clear               $clear
+//     :region 0,0 8x4      $region:0,0:8x4
+//     :pattern verse       $pattern:verse
+//     :song verse 4 chorus 2
+//     :groove swing58      $groove:swing58
+//
+// `pattern` keeps what's in the region under a name, and `song` plays
+// patterns there in turn for so many bars each, or stops with no names.
+//
+// frontends and plugins can register more.
+
+// things only the frontend can do, handed back to it by the commands
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Write(Option<String>),
+    Quit { force: bool },
+    Goto(Point),
+}
+
+pub type Handler = fn(&mut Session, &[&str]) -> Result<Option<Request>, String>;
+
+#[derive(Clone)]
+pub struct Commands(HashMap<String, Handler>);
+
+impl Commands {
+    pub fn new() -> Self {
+        Commands(HashMap::new())
+    }
+
+    pub fn register(&mut self, name: &str, handler: Handler) {
+        self.0.insert(name.to_string(), handler);
+    }
+
+    pub fn find(&self, name: &str) -> Option<Handler> {
+        self.0.get(name).copied()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        let mut ret = Commands::new();
+        ret.register("w", |_, args| match args {
+            [] => Ok(Some(Request::Write(None))),
+            [path] => Ok(Some(Request::Write(Some(path.to_string())))),
+            _ => Err("usage: w [path]".to_string()),
+        });
+        ret.register("q", |_, _| Ok(Some(Request::Quit { force: false })));
+        ret.register("q!", |_, _| Ok(Some(Request::Quit { force: true })));
+        ret.register("goto", |_, args| match args {
+            [pt] => Ok(Some(Request::Goto(pt.parse()?))),
+            _ => Err("usage: goto <x>,<y>".to_string()),
+        });
+        ret.register("clear", |session, _| {
+            session.ctx.field.clear_all();
+            Ok(None)
+        });
+        ret.register("resize", |session, args| {
+            let (w, h) = match args {
+                [w, h] => (size(w)?, size(h)?),
+                _ => return Err("usage: resize <width> <height>".to_string()),
+            };
+            session.ctx.field = session.ctx.field.resized(w, h);
+            Ok(None)
+        });
+        ret.register("bpm", |session, args| {
+            let bpm = match args {
+                [bpm] => bpm.parse().map_err(|_| format!("invalid bpm `{}`", bpm))?,
+                _ => return Err("usage: bpm <n>".to_string()),
+            };
+            session.transport.bpm = check_bpm(bpm)?;
+            Ok(None)
+        });
+        ret.register("seed", |session, args| {
+            let seed = match args {
+                [seed] => seed.parse().map_err(|_| format!("invalid seed `{}`", seed))?,
+                _ => return Err("usage: seed <n>".to_string()),
+            };
+            session.ctx.reseed(seed);
+            Ok(None)
+        });
+        ret.register("groove", |session, args| match args {
+            [name] => {
+                session.groove = Groove::named(name)?;
+                Ok(None)
+            }
+            _ => Err(format!("usage: groove <name>, one of {}", groove::names().join(", "))),
+        });
+        ret.register("region", |session, args| match args {
+            [origin, size] => {
+                session.arrangement.region = Some(arrange::region(origin, size)?);
+                Ok(None)
+            }
+            _ => Err("usage: region <x>,<y> <width>x<height>".to_string()),
+        });
+        ret.register("pattern", |session, args| match args {
+            [name] => {
+                session.arrangement.store(name, &session.ctx.field)?;
+                Ok(None)
+            }
+            _ => Err("usage: pattern <name>".to_string()),
+        });
+        ret.register("song", |session, args| {
+            if args.len() % 2 != 0 {
+                return Err("usage: song [<pattern> <bars>]...".to_string());
+            }
+            let mut sections = Vec::new();
+            for pair in args.chunks(2) {
+                if !session.arrangement.patterns.contains_key(pair[0]) {
+                    return Err(format!("no pattern `{}`", pair[0]));
+                }
+                sections.push((pair[0].to_string(), arrange::bars(pair[1])?));
+            }
+            session.arrangement.sections = sections;
+            session.arrangement.restart(session.ctx.frame_ct);
+            Ok(None)
+        });
+        ret
+    }
+}
+
+fn size(arg: &str) -> Result<usize, String> {
+    match arg.parse() {
+        Ok(n) if n > 0 && n <= codec::MAX_SIDE => Ok(n),
+        _ => Err(format!("invalid size `{}`, expected 1-{}", arg, codec::MAX_SIDE)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::project::Project;
+    use lyza::Field;
+
+    fn session() -> Session {
+        let config = Config { dry_run: true, ..Config::default() };
+        Session::new(&config, Project::new(Field::new(4, 3)), 120).unwrap()
+    }
+
+    fn glyph(session: &Session, x: i32, y: i32) -> Option<char> {
+        session.ctx.field.get(Point::new(x, y))
+    }
+
+    #[test]
+    fn resizing_keeps_what_fits() {
+        let mut session = session();
+        session.ctx.field.set(Point::new(1, 1), 'E').unwrap();
+        session.ctx.field.set(Point::new(3, 2), 'W').unwrap();
+        assert_eq!(session.run_command("resize 2 2"), Ok(None));
+        assert_eq!((session.ctx.field.slots.width, session.ctx.field.slots.height), (2, 2));
+        assert_eq!(glyph(&session, 1, 1), Some('E'));
+        assert_eq!(session.run_command("resize 1024 1"), Ok(None));
+        assert_eq!(session.ctx.field.slots.width, codec::MAX_SIDE);
+    }
+
+    #[test]
+    fn sizes_are_bounded() {
+        let mut session = session();
+        for (line, err) in [("resize 0 4", "invalid size `0`, expected 1-1024"),
+                            ("resize 4 1025", "invalid size `1025`, expected 1-1024"),
+                            ("resize -1 4", "invalid size `-1`, expected 1-1024"),
+                            ("resize 4", "usage: resize <width> <height>"),
+                            ("resize 4 4 4", "usage: resize <width> <height>")] {
+            assert_eq!(session.run_command(line), Err(err.to_string()), "{}", line);
+        }
+        assert_eq!((session.ctx.field.slots.width, session.ctx.field.slots.height), (4, 3));
+    }
+
+    #[test]
+    fn tempos_are_parsed() {
+        let mut session = session();
+        assert_eq!(session.run_command("bpm 140"), Ok(None));
+        assert_eq!(session.transport.bpm, 140);
+        for (line, err) in [("bpm fast", "invalid bpm `fast`"),
+                            ("bpm 1000", "bpm 1000 out of range 1-999"),
+                            ("bpm 0", "bpm 0 out of range 1-999"),
+                            ("bpm", "usage: bpm <n>")] {
+            assert_eq!(session.run_command(line), Err(err.to_string()), "{}", line);
+        }
+        assert_eq!(session.transport.bpm, 140);
+    }
+
+    #[test]
+    fn seeds_are_parsed() {
+        let mut session = session();
+        assert_eq!(session.run_command("seed 7"), Ok(None));
+        let mut expected = 7;
+        assert_eq!(session.ctx.random(), lyza::xorshift(&mut expected));
+        assert_eq!(session.run_command("seed 0"), Ok(None));
+        assert_ne!(session.ctx.random(), 0);
+        for (line, err) in [("seed -1", "invalid seed `-1`"),
+                            ("seed x", "invalid seed `x`"),
+                            ("seed 1 2", "usage: seed <n>")] {
+            assert_eq!(session.run_command(line), Err(err.to_string()), "{}", line);
+        }
+    }
+
+    #[test]
+    fn frontend_requests_are_handed_back() {
+        let mut session = session();
+        assert_eq!(session.run_command("goto 3,2"), Ok(Some(Request::Goto(Point::new(3, 2)))));
+        assert!(session.run_command("goto 3").is_err());
+        assert_eq!(session.run_command("goto"), Err("usage: goto <x>,<y>".to_string()));
+        assert_eq!(session.run_command("w"), Ok(Some(Request::Write(None))));
+        assert_eq!(session.run_command("w out.lyza"), Ok(Some(Request::Write(Some("out.lyza".to_string())))));
+        assert_eq!(session.run_command("q!"), Ok(Some(Request::Quit { force: true })));
+        assert_eq!(session.run_command("nope"), Err("unknown command `nope`".to_string()));
+        assert_eq!(session.run_command("  "), Err("empty command".to_string()));
+    }
+
+    #[test]
+    fn clearing_empties_the_field() {
+        let mut session = session();
+        session.ctx.field.set(Point::new(0, 0), 'E').unwrap();
+        session.ctx.field.set(Point::new(3, 2), '5').unwrap();
+        assert_eq!(session.run_command("clear"), Ok(None));
+        assert!(session.ctx.field == Field::new(4, 3));
+    }
+
+    #[test]
+    fn regions_are_checked() {
+        let mut session = session();
+        assert_eq!(session.run_command("region 1,1 2x2"), Ok(None));
+        assert_eq!(session.arrangement.region, Some((Point::new(1, 1), 2, 2)));
+        for line in ["region 1,1 0x2", "region -1,0 2x2", "region 1,1", "region x 2x2"] {
+            assert!(session.run_command(line).is_err(), "{}", line);
+        }
+        assert_eq!(session.arrangement.region, Some((Point::new(1, 1), 2, 2)));
+    }
+}
@@ -0,0 +1,602 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::default;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::humanize::{self, Humanize};
+
+//
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn as_str_list(&self) -> Option<Vec<String>> {
+        match self {
+            Value::Array(items) => items.iter()
+                                        .map(|v| v.as_str().map(str::to_string))
+                                        .collect(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    line: Option<usize>,
+    msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.msg),
+            None => write!(f, "{}", self.msg),
+        }
+    }
+}
+
+// a small subset of toml: [tables], key = value, strings, integers,
+// booleans and single-line arrays. keys come back flattened as "table.key".
+pub fn parse_toml(src: &str) -> Result<HashMap<String, Value>, ParseError> {
+    let mut ret = HashMap::new();
+    let mut table = String::new();
+
+    for (i, raw) in src.lines().enumerate() {
+        let err = |msg: &str| ParseError { line: Some(i + 1), msg: msg.to_string() };
+        let line = strip_comment(raw).trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(err("unterminated table header"));
+            }
+            table = line[1..line.len() - 1].trim().to_string();
+            continue;
+        }
+
+        let eq = line.find('=').ok_or_else(|| err("expected `key = value`"))?;
+        let key = line[..eq].trim().trim_matches('"');
+        if key.is_empty() {
+            return Err(err("empty key"));
+        }
+
+        let value = parse_value(line[eq + 1..].trim()).map_err(|m| err(&m))?;
+        let full_key = if table.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", table, key)
+        };
+        ret.insert(full_key, value);
+    }
+
+    Ok(ret)
+}
+
+fn strip_comment(line: &str) -> &str {
+    outside_strings(line).find(|&(_, ch)| ch == '#').map_or(line, |(i, _)| &line[..i])
+}
+
+// the characters of `src` that aren't inside a string, quotes included,
+// with where they are. `\"` inside a string doesn't end it.
+fn outside_strings(src: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let (mut in_str, mut escaped) = (false, false);
+    src.char_indices().filter(move |&(_, ch)| {
+        let outside = !in_str;
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            _ => {}
+        }
+        outside || (!in_str && ch == '"')
+    })
+}
+
+fn parse_value(src: &str) -> Result<Value, String> {
+    if src.starts_with('"') {
+        // the quote closing the string, which has to be the last thing
+        let end = outside_strings(src).nth(1).map(|(i, _)| i);
+        return match end {
+            Some(end) if end == src.len() - 1 => Ok(Value::Str(unescape(&src[1..end]))),
+            Some(_) => Err(format!("unexpected text after string `{}`", src)),
+            None => Err("unterminated string".to_string()),
+        };
+    }
+
+    if src.starts_with('[') {
+        if !src.ends_with(']') {
+            return Err("unterminated array".to_string());
+        }
+        let inner = src[1..src.len() - 1].trim();
+        let mut items = Vec::new();
+        for item in split_array(inner) {
+            let item = item.trim();
+            if !item.is_empty() {
+                items.push(parse_value(item)?);
+            }
+        }
+        return Ok(Value::Array(items));
+    }
+
+    match src {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => src.replace('_', "")
+                .parse()
+                .map(Value::Int)
+                .map_err(|_| format!("invalid value `{}`", src)),
+    }
+}
+
+fn split_array(src: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut start = 0;
+    for (i, _) in outside_strings(src).filter(|&(_, ch)| ch == ',') {
+        ret.push(&src[start..i]);
+        start = i + 1;
+    }
+    ret.push(&src[start..]);
+    ret
+}
+
+fn unescape(src: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = src.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            ret.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => ret.push('\n'),
+            Some('t') => ret.push('\t'),
+            Some(other) => ret.push(other),
+            None => {}
+        }
+    }
+    ret
+}
+
+//
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    // paths, or `<name>=<path>` to route ports to by name
+    pub midi_devices: Vec<String>,
+    // the device names the note ports go to, port 0 first. with none every
+    // device gets every port.
+    pub midi_routes: Vec<String>,
+    // `edo:<n>` or a .scl file to send notes in, over mpe
+    pub midi_tuning: Option<String>,
+    // random variation on the notes sent, see humanize.rs
+    pub humanize: Humanize,
+    pub osc_destinations: Vec<String>,
+    pub bpm: u32,
+    pub theme: String,
+    pub theme_overrides: HashMap<String, String>,
+    pub keymap: String,
+    pub keybindings: HashMap<String, String>,
+    // frames between beat grid columns in the editor, 0 for none
+    pub beat_grid: usize,
+    // seconds between writes of the editor's recovery file, 0 for never
+    pub autosave: u64,
+    // snapshots of the field the editor keeps to step back through
+    pub history: usize,
+    // hold edits made while playing until the next bar line
+    pub quantize: bool,
+    pub mqtt_broker: Option<String>,
+    pub mqtt_client_id: String,
+    pub mqtt_topics: Vec<String>,
+    pub artnet_dest: Option<String>,
+    pub udp_dest: Option<String>,
+    pub scsynth_dest: Option<String>,
+    pub scsynth_synthdefs: Vec<String>,
+    pub scsynth_params: Vec<String>,
+    // log events rather than connect to anything, only ever set on the
+    // command line
+    pub dry_run: bool,
+}
+
+impl default::Default for Config {
+    fn default() -> Self {
+        Self {
+            midi_devices: Vec::new(),
+            midi_tuning: None,
+            humanize: Humanize::default(),
+            midi_routes: Vec::new(),
+            osc_destinations: Vec::new(),
+            bpm: 120,
+            theme: "default".to_string(),
+            theme_overrides: HashMap::new(),
+            keymap: "default".to_string(),
+            keybindings: HashMap::new(),
+            beat_grid: 4,
+            autosave: 30,
+            // five minutes of frames at 120 bpm
+            history: 2400,
+            quantize: false,
+            mqtt_broker: None,
+            mqtt_client_id: "lyza".to_string(),
+            mqtt_topics: Vec::new(),
+            artnet_dest: None,
+            udp_dest: None,
+            scsynth_dest: None,
+            scsynth_synthdefs: vec!["default".to_string()],
+            scsynth_params: vec!["freq".to_string(), "amp".to_string(), "pan".to_string()],
+            dry_run: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+            return Some(PathBuf::from(dir).join("lyza/config.toml"));
+        }
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/lyza/config.toml"))
+    }
+
+    // a missing file is not an error unless it was asked for, it just
+    // means running on defaults
+    pub fn from_file(path: &Path, explicit: bool) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(src) => Self::from_toml(&src)
+                           .map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound && !explicit => Ok(Default::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn from_toml(src: &str) -> Result<Self, ParseError> {
+        let table = parse_toml(src)?;
+        let mut ret: Config = Default::default();
+
+        let mismatch = |key: &str, want: &str| ParseError {
+            line: None,
+            msg: format!("`{}` should be {}", key, want),
+        };
+
+        for (key, value) in table.iter() {
+            match key.as_str() {
+                "bpm" => {
+                    let bpm = value.as_int().ok_or_else(|| mismatch(key, "an integer"))?;
+                    ret.bpm = check_bpm(bpm).map_err(|msg| ParseError { line: None, msg })?;
+                }
+                "theme" => {
+                    ret.theme = value.as_str().ok_or_else(|| mismatch(key, "a string"))?
+                                     .to_string();
+                }
+                "keymap" => {
+                    ret.keymap = value.as_str().ok_or_else(|| mismatch(key, "a string"))?
+                                      .to_string();
+                }
+                "editor.beat_grid" => {
+                    let n = value.as_int().ok_or_else(|| mismatch(key, "an integer"))?;
+                    ret.beat_grid = n.try_into().map_err(|_| mismatch(key, "zero or more"))?;
+                }
+                "editor.autosave" => {
+                    let n = value.as_int().ok_or_else(|| mismatch(key, "an integer"))?;
+                    ret.autosave = n.try_into().map_err(|_| mismatch(key, "zero or more"))?;
+                }
+                "editor.history" => {
+                    let n = value.as_int().ok_or_else(|| mismatch(key, "an integer"))?;
+                    ret.history = n.try_into().map_err(|_| mismatch(key, "zero or more"))?;
+                }
+                "editor.quantize" => {
+                    ret.quantize = value.as_bool().ok_or_else(|| mismatch(key, "true or false"))?;
+                }
+                "midi.devices" => {
+                    ret.midi_devices = value.as_str_list()
+                                            .ok_or_else(|| mismatch(key, "a list of strings"))?;
+                }
+                "midi.routes" => {
+                    ret.midi_routes = value.as_str_list()
+                                           .ok_or_else(|| mismatch(key, "a list of strings"))?;
+                }
+                "midi.humanize_velocity" => {
+                    let n = value.as_int().ok_or_else(|| mismatch(key, "an integer"))?;
+                    ret.humanize.velocity = check_humanize_velocity(n).map_err(|msg| ParseError { line: None, msg })?;
+                }
+                "midi.humanize_timing" => {
+                    let n = value.as_int().ok_or_else(|| mismatch(key, "an integer"))?;
+                    ret.humanize.timing = check_humanize_timing(n).map_err(|msg| ParseError { line: None, msg })?;
+                }
+                "midi.tuning" => {
+                    ret.midi_tuning = Some(value.as_str().ok_or_else(|| mismatch(key, "a string"))?
+                                                .to_string());
+                }
+                "osc.destinations" => {
+                    ret.osc_destinations = value.as_str_list()
+                                                .ok_or_else(|| mismatch(key, "a list of strings"))?;
+                }
+                "mqtt.broker" => {
+                    ret.mqtt_broker = Some(value.as_str().ok_or_else(|| mismatch(key, "a string"))?
+                                                .to_string());
+                }
+                "mqtt.client_id" => {
+                    ret.mqtt_client_id = value.as_str().ok_or_else(|| mismatch(key, "a string"))?
+                                              .to_string();
+                }
+                "mqtt.topics" => {
+                    ret.mqtt_topics = value.as_str_list()
+                                           .ok_or_else(|| mismatch(key, "a list of strings"))?;
+                }
+                "artnet.destination" => {
+                    ret.artnet_dest = Some(value.as_str().ok_or_else(|| mismatch(key, "a string"))?
+                                                .to_string());
+                }
+                "udp.destination" => {
+                    ret.udp_dest = Some(value.as_str().ok_or_else(|| mismatch(key, "a string"))?
+                                             .to_string());
+                }
+                "scsynth.destination" => {
+                    ret.scsynth_dest = Some(value.as_str().ok_or_else(|| mismatch(key, "a string"))?
+                                                 .to_string());
+                }
+                "scsynth.synthdefs" => {
+                    ret.scsynth_synthdefs = value.as_str_list()
+                                                 .ok_or_else(|| mismatch(key, "a list of strings"))?;
+                }
+                "scsynth.params" => {
+                    ret.scsynth_params = value.as_str_list()
+                                              .ok_or_else(|| mismatch(key, "a list of strings"))?;
+                }
+                _ if key.starts_with("theme.") => {
+                    let setting = key["theme.".len()..].to_string();
+                    let value = value.as_str().ok_or_else(|| mismatch(key, "a string"))?;
+                    ret.theme_overrides.insert(setting, value.to_string());
+                }
+                _ if key.starts_with("keybindings.") => {
+                    let action = key["keybindings.".len()..].to_string();
+                    let binding = value.as_str().ok_or_else(|| mismatch(key, "a string"))?;
+                    ret.keybindings.insert(action, binding.to_string());
+                }
+                // most likely a typo, which would otherwise quietly leave
+                // the setting on its default
+                _ => {
+                    return Err(ParseError { line: None, msg: format!("unknown setting `{}`", key) });
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    // command line flags take precedence over whatever the file said.
+    // returns the arguments that weren't config flags.
+    pub fn apply_args(&mut self, args: &[String]) -> Result<Vec<String>, String> {
+        let mut rest = Vec::new();
+        let mut midi_devices = Vec::new();
+        let mut midi_routes = Vec::new();
+        let mut osc_destinations = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            let mut value = |flag: &str| {
+                iter.next().cloned().ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match arg.as_str() {
+                "--bpm" => {
+                    let v = value(arg)?;
+                    let bpm = v.parse().map_err(|_| format!("invalid bpm `{}`", v))?;
+                    self.bpm = check_bpm(bpm)?;
+                }
+                "--theme" => self.theme = value(arg)?,
+                "--midi-device" => midi_devices.push(value(arg)?),
+                "--midi-route" => midi_routes.push(value(arg)?),
+                "--humanize-velocity" => {
+                    let v = value(arg)?;
+                    let n = v.parse().map_err(|_| format!("invalid velocity amount `{}`", v))?;
+                    self.humanize.velocity = check_humanize_velocity(n)?;
+                }
+                "--humanize-timing" => {
+                    let v = value(arg)?;
+                    let n = v.parse().map_err(|_| format!("invalid timing amount `{}`", v))?;
+                    self.humanize.timing = check_humanize_timing(n)?;
+                }
+                "--tuning" => self.midi_tuning = Some(value(arg)?),
+                "--osc" => osc_destinations.push(value(arg)?),
+                "--mqtt" => self.mqtt_broker = Some(value(arg)?),
+                "--artnet" => self.artnet_dest = Some(value(arg)?),
+                "--scsynth" => self.scsynth_dest = Some(value(arg)?),
+                "--udp" => self.udp_dest = Some(value(arg)?),
+                "--dry-run" => self.dry_run = true,
+                "--config" => {
+                    value(arg)?;
+                }
+                _ => rest.push(arg.clone()),
+            }
+        }
+
+        if !midi_devices.is_empty() {
+            self.midi_devices = midi_devices;
+        }
+        if !midi_routes.is_empty() {
+            self.midi_routes = midi_routes;
+        }
+        if !osc_destinations.is_empty() {
+            self.osc_destinations = osc_destinations;
+        }
+
+        Ok(rest)
+    }
+
+    pub fn load(args: &[String]) -> Result<(Self, Vec<String>), String> {
+        let explicit = args.iter()
+                           .position(|a| a == "--config")
+                           .and_then(|i| args.get(i + 1))
+                           .map(PathBuf::from);
+
+        let mut ret = match (explicit, Self::default_path()) {
+            (Some(path), _) => Self::from_file(&path, true)?,
+            (None, Some(path)) => Self::from_file(&path, false)?,
+            (None, None) => Default::default(),
+        };
+        let rest = ret.apply_args(args)?;
+        Ok((ret, rest))
+    }
+}
+
+pub fn check_bpm(bpm: i64) -> Result<u32, String> {
+    if !(1..=999).contains(&bpm) {
+        Err(format!("bpm {} out of range 1-999", bpm))
+    } else {
+        Ok(bpm as u32)
+    }
+}
+
+fn check_humanize_velocity(n: i64) -> Result<u8, String> {
+    match n.try_into() {
+        Ok(n) if n <= 127 => Ok(n),
+        _ => Err(format!("humanize velocity {} out of range 0-127", n)),
+    }
+}
+
+fn check_humanize_timing(n: i64) -> Result<u32, String> {
+    match n.try_into() {
+        Ok(n) if n <= humanize::MAX_TIMING => Ok(n),
+        _ => Err(format!("humanize timing {} out of range 0-{}", n, humanize::MAX_TIMING)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> HashMap<String, Value> {
+        parse_toml(src).unwrap()
+    }
+
+    fn str(s: &str) -> Value {
+        Value::Str(s.to_string())
+    }
+
+    #[test]
+    fn strings_unescape() {
+        let table = parse(r##"
+            plain = "a b"
+            quoted = "say \"hi\""
+            escapes = "tab\tnew\nslash\\"
+            empty = ""
+        "##);
+        assert_eq!(table["plain"], str("a b"));
+        assert_eq!(table["quoted"], str("say \"hi\""));
+        assert_eq!(table["escapes"], str("tab\tnew\nslash\\"));
+        assert_eq!(table["empty"], str(""));
+    }
+
+    #[test]
+    fn comments_stop_outside_strings() {
+        let table = parse(r##"
+            # a whole line
+            a = "x\"#y" # after
+            b = "#" # after
+            c = ["#", "\"", "d,e"] # after
+            d = 1_000 # after
+        "##);
+        assert_eq!(table["a"], str("x\"#y"));
+        assert_eq!(table["b"], str("#"));
+        assert_eq!(table["c"], Value::Array(vec![str("#"), str("\""), str("d,e")]));
+        assert_eq!(table["d"], Value::Int(1000));
+        assert_eq!(table.len(), 4);
+    }
+
+    #[test]
+    fn tables_prefix_their_keys() {
+        let table = parse("
+            top = true
+            [midi]
+            devices = []
+            [ keybindings ]
+            \"quit\" = \"C-q\"
+        ");
+        assert_eq!(table["top"], Value::Bool(true));
+        assert_eq!(table["midi.devices"], Value::Array(Vec::new()));
+        assert_eq!(table["keybindings.quit"], str("C-q"));
+    }
+
+    #[test]
+    fn bad_toml_is_refused() {
+        for src in ["[midi", "a", "= 1", "a = \"x", "a = \"x\\\"", "a = \"x\" y", "a = [1", "a = nope"] {
+            assert!(parse_toml(src).is_err(), "{}", src);
+        }
+        assert_eq!(parse_toml("\n\nb = x").unwrap_err().line, Some(3));
+    }
+
+    #[test]
+    fn settings_are_read() {
+        let config = Config::from_toml(r#"
+            bpm = 90
+            theme = "mono"
+            [editor]
+            quantize = true
+            history = 10
+            [midi]
+            devices = ["drums=/dev/a", "/dev/b"]
+            humanize_timing = 20
+            [theme]
+            cursor = "red"
+            [keybindings]
+            quit = "C-x"
+        "#).unwrap();
+        assert_eq!((config.bpm, config.theme.as_str()), (90, "mono"));
+        assert!(config.quantize);
+        assert_eq!(config.history, 10);
+        assert_eq!(config.midi_devices, ["drums=/dev/a", "/dev/b"]);
+        assert_eq!(config.humanize.timing, 20);
+        assert_eq!(config.theme_overrides["cursor"], "red");
+        assert_eq!(config.keybindings["quit"], "C-x");
+    }
+
+    #[test]
+    fn mismatched_settings_are_refused() {
+        let msg = |src| Config::from_toml(src).unwrap_err().msg;
+        assert_eq!(msg("bpm = \"fast\""), "`bpm` should be an integer");
+        assert_eq!(msg("bpm = 0"), "bpm 0 out of range 1-999");
+        assert_eq!(msg("[editor]\nhistory = -1"), "`editor.history` should be zero or more");
+        assert_eq!(msg("[editor]\nquantize = 1"), "`editor.quantize` should be true or false");
+        assert_eq!(msg("[midi]\ndevices = [1]"), "`midi.devices` should be a list of strings");
+        assert_eq!(msg("[theme]\ncursor = 1"), "`theme.cursor` should be a string");
+        assert_eq!(msg("[keybindings]\nquit = true"), "`keybindings.quit` should be a string");
+    }
+
+    #[test]
+    fn unknown_settings_are_refused() {
+        assert_eq!(Config::from_toml("bmp = 90").unwrap_err().msg, "unknown setting `bmp`");
+        assert_eq!(Config::from_toml("[midi]\ndevice = []").unwrap_err().msg, "unknown setting `midi.device`");
+    }
+}
@@ -0,0 +1,326 @@
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::net::UdpSocket;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::osc::{self, OscArg};
+use crate::project;
+use lyza::{Context, Point};
+
+// commands that external control surfaces can send to a running instance,
+// one per line:
+//
+//     bpm 140
+//     write 3 4 E          or  write 3,4 E
+//     pause
+//     play
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Bpm(u32),
+    Write(Point, char),
+    Pause,
+    Play,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let num = |s: &str| s.parse::<i32>().map_err(|_| format!("invalid number `{}`", s));
+
+        match words.as_slice() {
+            ["bpm", bpm] => {
+                let bpm = num(bpm)?;
+                if !(1..=999).contains(&bpm) {
+                    return Err(format!("bpm {} out of range 1-999", bpm));
+                }
+                Ok(Command::Bpm(bpm as u32))
+            }
+            ["write", x, y, glyph] => Ok(Command::Write(Point::new(num(x)?, num(y)?), write_glyph(glyph)?)),
+            ["write", pt, glyph] => Ok(Command::Write(pt.parse()?, write_glyph(glyph)?)),
+            ["pause"] => Ok(Command::Pause),
+            ["play"] => Ok(Command::Play),
+            [] => Err("empty command".to_string()),
+            _ => Err(format!("unknown command `{}`", line.trim())),
+        }
+    }
+}
+
+// the line that parses back to the command
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Command::Bpm(bpm) => write!(f, "bpm {}", bpm),
+            Command::Write(pt, '\0') => write!(f, "write {} {} .", pt.x, pt.y),
+            Command::Write(pt, ch) => write!(f, "write {} {} {}", pt.x, pt.y, ch),
+            Command::Pause => write!(f, "pause"),
+            Command::Play => write!(f, "play"),
+        }
+    }
+}
+
+// `.` clears the cell
+fn write_glyph(glyph: &str) -> Result<char, String> {
+    let mut chars = glyph.chars();
+    match (chars.next(), chars.next()) {
+        (Some('.'), None) => Ok('\0'),
+        (Some(ch), None) => Ok(ch),
+        _ => Err(format!("expected a single glyph, got `{}`", glyph)),
+    }
+}
+
+pub struct Transport {
+    pub bpm: u32,
+    pub paused: bool,
+}
+
+impl Transport {
+    pub fn new(bpm: u32) -> Self {
+        Self { bpm, paused: false }
+    }
+}
+
+// what control surfaces get to see of the running instance. the engine
+// isn't Sync, so the run loop publishes a copy after every frame.
+#[derive(Clone, Default)]
+pub struct Status {
+    pub frame: u32,
+    pub bpm: u32,
+    pub paused: bool,
+    pub width: usize,
+    pub height: usize,
+    pub grid: String,
+}
+
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+impl Status {
+    pub fn capture(ctx: &Context, transport: &Transport) -> Self {
+        Self {
+            frame: ctx.frame_ct,
+            bpm: transport.bpm,
+            paused: transport.paused,
+            width: ctx.field.slots.width,
+            height: ctx.field.slots.height,
+            grid: project::grid_text(&ctx.field),
+        }
+    }
+
+    // like capture, rewriting the grid text in place so frames don't
+    // allocate a new one
+    pub fn update(&mut self, ctx: &Context, transport: &Transport) {
+        self.frame = ctx.frame_ct;
+        self.bpm = transport.bpm;
+        self.paused = transport.paused;
+        self.width = ctx.field.slots.width;
+        self.height = ctx.field.slots.height;
+        self.grid.clear();
+        project::write_grid(&mut self.grid, &ctx.field);
+    }
+
+    pub fn json(&self) -> String {
+        format!("{{\"frame\":{},\"bpm\":{},\"paused\":{},\"width\":{},\"height\":{}}}\n",
+                self.frame, self.bpm, self.paused, self.width, self.height)
+    }
+
+    // catches writes outside the field before they're queued, so remote
+    // surfaces can report the error to whoever sent them
+    pub fn check(&self, cmd: &Command) -> Result<(), String> {
+        if let Command::Write(pt, _) = *cmd {
+            if pt.x < 0 || pt.y < 0 || pt.x as usize >= self.width || pt.y as usize >= self.height {
+                return Err(format!("{} is outside the field", pt));
+            }
+        }
+        Ok(())
+    }
+}
+
+// a string as a json string literal
+pub fn json_string(s: &str) -> String {
+    let mut ret = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            ch if ch.is_control() => ret.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => ret.push(ch),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+pub fn apply(cmd: &Command, ctx: &mut Context, transport: &mut Transport) -> Result<(), String> {
+    match *cmd {
+        Command::Bpm(bpm) => transport.bpm = bpm,
+        Command::Write(pt, ch) => ctx.field.set(pt, ch)?,
+        Command::Pause => transport.paused = true,
+        Command::Play => transport.paused = false,
+    }
+    Ok(())
+}
+
+//
+
+// creates the fifo if needed and forwards every command written to it.
+// opening blocks until a writer shows up, and each writer closing the pipe
+// just sends us back around to wait for the next one. anything else at the
+// path is refused: a plain file would be read to its end over and over.
+pub fn spawn_fifo(path: &Path, tx: Sender<Command>) -> Result<(), String> {
+    match fs::metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => {}
+        Ok(_) => return Err(format!("{}: not a fifo", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            make_fifo(path).map_err(|e| format!("{}: could not create fifo: {}", path.display(), e))?;
+        }
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    }
+
+    let path: PathBuf = path.to_owned();
+    thread::spawn(move || loop {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("lyza: {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(cmd) => {
+                    if tx.send(cmd).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => eprintln!("lyza: {}: {}", path.display(), e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// mode_t, which is narrower on the bsds
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+type Mode = u16;
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd")))]
+type Mode = u32;
+
+// from the c library std already links
+extern "C" {
+    fn mkfifo(path: *const c_char, mode: Mode) -> c_int;
+}
+
+fn make_fifo(path: &Path) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())
+                   .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // readable and writable by the owner only
+    match unsafe { mkfifo(path.as_ptr(), 0o600) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+// listens for osc messages on a udp address:
+//
+//     /lyza/bpm    i       set the tempo
+//     /lyza/write  i i s   write a glyph at x,y (`.` clears)
+//     /lyza/write  s s     the same with the point as a string, e.g. "3,4"
+//     /lyza/pause
+//     /lyza/play
+pub fn spawn_osc(addr: &str, tx: Sender<Command>) -> Result<(), String> {
+    let socket = UdpSocket::bind(addr).map_err(|e| format!("osc {}: {}", addr, e))?;
+
+    thread::spawn(move || {
+        let mut buf = [0; 1024];
+        loop {
+            let n = match socket.recv_from(&mut buf) {
+                Ok((n, _)) => n,
+                Err(_) => continue,
+            };
+            let cmd = match osc::decode(&buf[..n]) {
+                Some((address, args)) => osc_command(&address, &args),
+                None => Err("malformed osc message".to_string()),
+            };
+            match cmd {
+                Ok(cmd) => {
+                    if tx.send(cmd).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => eprintln!("lyza: osc: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn osc_command(address: &str, args: &[OscArg]) -> Result<Command, String> {
+    let line = match (address, args) {
+        ("/lyza/bpm", [OscArg::Int(bpm)]) => format!("bpm {}", bpm),
+        ("/lyza/bpm", [OscArg::Float(bpm)]) => format!("bpm {}", bpm.round() as i32),
+        ("/lyza/write", [OscArg::Int(x), OscArg::Int(y), OscArg::Str(g)]) => {
+            format!("write {} {} {}", x, y, g)
+        }
+        ("/lyza/write", [OscArg::Str(pt), OscArg::Str(g)]) => format!("write {} {}", pt, g),
+        ("/lyza/pause", []) => "pause".to_string(),
+        ("/lyza/play", []) => "play".to_string(),
+        _ => return Err(format!("unknown message {}", address)),
+    };
+    line.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn temp(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lyza-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn plain_files_are_not_fifos() {
+        let path = temp("plain");
+        fs::write(&path, "bpm 120\n").unwrap();
+        let err = spawn_fifo(&path, mpsc::channel().0).unwrap_err();
+        assert_eq!(err, format!("{}: not a fifo", path.display()));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_fifos_are_made() {
+        let path = temp("fifo");
+        make_fifo(&path).unwrap();
+        assert!(fs::metadata(&path).unwrap().file_type().is_fifo());
+        fs::remove_file(path).unwrap();
+
+        let path = temp("nowhere").join("fifo");
+        let err = spawn_fifo(&path, mpsc::channel().0).unwrap_err();
+        assert!(err.starts_with(&format!("{}: could not create fifo", path.display())), "{}", err);
+    }
+}
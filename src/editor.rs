@@ -0,0 +1,316 @@
+use lyza::{Axis, CellLike, Field, Matrix, Point, Stamp};
+
+// cursor and editing state for the interactive frontends. it only ever
+// touches the field it's handed, so the engine knows nothing about it.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    Normal,
+    // typing overwrites the cell and advances right
+    Replace,
+    // typing pushes the rest of the row right / the rest of the column down
+    InsertRow,
+    InsertColumn,
+    // a block from the anchor to the cursor is selected
+    Visual,
+    // the clipboard follows the cursor until it's committed or cancelled
+    Paste,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Replace => "REPLACE",
+            Mode::InsertRow => "INSERT ROW",
+            Mode::InsertColumn => "INSERT COLUMN",
+            Mode::Visual => "VISUAL",
+            Mode::Paste => "PASTE",
+        }
+    }
+
+    fn step(&self) -> Point {
+        match self {
+            Mode::InsertColumn => Point::new(0, 1),
+            _ => Point::new(1, 0),
+        }
+    }
+}
+
+pub struct Editor {
+    pub cursor: Point,
+    pub mode: Mode,
+    anchor: Point,
+    clipboard: Option<Matrix<char>>,
+    // extra cursors dropped with `toggle_cursor`. they stay put while moving
+    // around in normal mode, and mirror every keystroke in the typing modes.
+    extra: Vec<Point>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            cursor: Point::zero(),
+            mode: Mode::Normal,
+            anchor: Point::zero(),
+            clipboard: None,
+            extra: Vec::new(),
+        }
+    }
+
+    pub fn move_by(&mut self, field: &Field, dx: i32, dy: i32) {
+        if self.is_typing() {
+            self.mirror(|ed| ed.move_one(field, dx, dy));
+        } else {
+            self.move_one(field, dx, dy);
+        }
+    }
+
+    fn move_one(&mut self, field: &Field, dx: i32, dy: i32) {
+        let next = self.cursor.translate(dx, dy);
+        if field.point_in_bounds(next) {
+            self.cursor = next;
+        }
+    }
+
+    // keeps the cursors valid after the field changes size underneath them
+    pub fn clamp(&mut self, field: &Field) {
+        let clamp = |pt: &mut Point| {
+            pt.x = pt.x.min(field.slots.width as i32 - 1).max(0);
+            pt.y = pt.y.min(field.slots.height as i32 - 1).max(0);
+        };
+        clamp(&mut self.cursor);
+        self.extra.iter_mut().for_each(clamp);
+        self.extra.dedup();
+    }
+
+    fn is_typing(&self) -> bool {
+        matches!(self.mode, Mode::Replace | Mode::InsertRow | Mode::InsertColumn)
+    }
+
+    // drops a cursor where the main one is, or picks it back up
+    pub fn toggle_cursor(&mut self) {
+        match self.extra.iter().position(|&pt| pt == self.cursor) {
+            Some(i) => {
+                self.extra.remove(i);
+            }
+            None => self.extra.push(self.cursor),
+        }
+    }
+
+    pub fn clear_cursors(&mut self) {
+        self.extra.clear();
+    }
+
+    // the main cursor first, then the extra ones
+    pub fn cursors(&self) -> impl Iterator<Item = Point> + '_ {
+        std::iter::once(self.cursor).chain(self.extra.iter().copied())
+    }
+
+    // runs an edit at every cursor in turn, each one moving as it would alone
+    fn mirror(&mut self, mut edit: impl FnMut(&mut Self)) {
+        let main = self.cursor;
+        let mut extra = std::mem::take(&mut self.extra);
+        for pt in extra.iter_mut() {
+            self.cursor = *pt;
+            edit(self);
+            *pt = self.cursor;
+        }
+        self.cursor = main;
+        edit(self);
+        self.extra = extra;
+    }
+
+    pub fn type_glyph(&mut self, field: &mut Field, ch: char) {
+        self.mirror(|ed| ed.type_one(field, ch));
+    }
+
+    fn type_one(&mut self, field: &mut Field, ch: char) {
+        let step = self.mode.step();
+
+        if self.mode == Mode::InsertRow || self.mode == Mode::InsertColumn {
+            shift_toward_end(field, self.cursor, step);
+        }
+        if field.set(self.cursor, ch).is_err() {
+            return;
+        }
+
+        if self.mode != Mode::Normal {
+            self.move_one(field, step.x, step.y);
+        }
+    }
+
+    // clears the cell before the cursor, closing the gap in insert modes
+    pub fn backspace(&mut self, field: &mut Field) {
+        self.mirror(|ed| ed.backspace_one(field));
+    }
+
+    fn backspace_one(&mut self, field: &mut Field) {
+        let step = self.mode.step();
+        let prev = self.cursor - step;
+        if !field.point_in_bounds(prev) {
+            return;
+        }
+        self.cursor = prev;
+        match self.mode {
+            Mode::InsertRow | Mode::InsertColumn => shift_toward_start(field, prev, step),
+            _ => field[prev].clear(),
+        }
+    }
+
+    pub fn delete(&mut self, field: &mut Field) {
+        self.mirror(|ed| ed.delete_one(field));
+    }
+
+    fn delete_one(&mut self, field: &mut Field) {
+        match self.mode {
+            Mode::InsertRow | Mode::InsertColumn => {
+                shift_toward_start(field, self.cursor, self.mode.step());
+            }
+            _ => field[self.cursor].clear(),
+        }
+    }
+}
+
+impl Editor {
+    pub fn start_selection(&mut self) {
+        self.anchor = self.cursor;
+        self.mode = Mode::Visual;
+    }
+
+    // top left and bottom right corners, inclusive
+    pub fn selection(&self) -> Option<(Point, Point)> {
+        if self.mode != Mode::Visual {
+            return None;
+        }
+        Some((Point::new(self.anchor.x.min(self.cursor.x), self.anchor.y.min(self.cursor.y)),
+              Point::new(self.anchor.x.max(self.cursor.x), self.anchor.y.max(self.cursor.y))))
+    }
+
+    pub fn is_selected(&self, pt: Point) -> bool {
+        self.selection().is_some_and(|(min, max)| {
+            pt.x >= min.x && pt.x <= max.x && pt.y >= min.y && pt.y <= max.y
+        })
+    }
+
+    pub fn copy(&mut self, field: &Field) {
+        if let Some((min, max)) = self.selection() {
+            let (w, h) = selection_size(min, max);
+            let clip = Matrix::from_fn(w, h, |pt| field[min + pt].glyph());
+            self.clipboard = Some(clip);
+            self.cursor = min;
+            self.mode = Mode::Normal;
+        }
+    }
+
+    pub fn cut(&mut self, field: &mut Field) {
+        if let Some((min, max)) = self.selection() {
+            self.copy(field);
+            field.clear_region(min, selection_size(min, max), false);
+        }
+    }
+
+    // the selection follows the rotated cells, so it can be turned again
+    pub fn rotate(&mut self, field: &mut Field) {
+        if let Some((min, max)) = self.selection() {
+            let (w, h) = selection_size(min, max);
+            field.rotate_region(min, (w, h));
+            self.anchor = min;
+            self.cursor = min.translate(h as i32 - 1, w as i32 - 1);
+            self.clamp(field);
+        }
+    }
+
+    pub fn flip(&mut self, field: &mut Field, axis: Axis) {
+        if let Some((min, max)) = self.selection() {
+            field.flip_region(min, selection_size(min, max), axis);
+        }
+    }
+
+    // like cut, leaving the clipboard alone
+    pub fn erase(&mut self, field: &mut Field) {
+        if let Some((min, max)) = self.selection() {
+            field.clear_region(min, selection_size(min, max), false);
+            self.cursor = min;
+            self.mode = Mode::Normal;
+        }
+    }
+
+    // returns false if there's nothing to paste
+    pub fn start_paste(&mut self) -> bool {
+        if self.clipboard.is_some() {
+            self.mode = Mode::Paste;
+        }
+        self.clipboard.is_some()
+    }
+
+    // what the pending paste would put at pt, if it covers it
+    pub fn preview_at(&self, pt: Point) -> Option<char> {
+        if self.mode != Mode::Paste {
+            return None;
+        }
+        let clip = self.clipboard.as_ref()?;
+        let rel = pt - self.cursor;
+        if clip.in_bounds(rel) {
+            Some(clip[rel])
+        } else {
+            None
+        }
+    }
+
+    // anything hanging off the edge of the field is dropped
+    pub fn commit_paste(&mut self, field: &mut Field) {
+        if let (Mode::Paste, Some(clip)) = (self.mode, &self.clipboard) {
+            // overwriting can't fail
+            let _ = field.stamp(clip, self.cursor, Stamp::default());
+        }
+        self.mode = Mode::Normal;
+    }
+}
+
+impl Editor {
+    // moves the cursor to the next cell holding one of `glyphs`, in scan
+    // order after the cursor and wrapping around. false if there's none.
+    pub fn find_next(&mut self, field: &Field, glyphs: &[char]) -> bool {
+        let (w, h) = (field.slots.width as i32, field.slots.height as i32);
+        let start = self.cursor.y * w + self.cursor.x;
+        for i in 1..=w * h {
+            let at = (start + i) % (w * h);
+            let pt = Point::new(at % w, at / w);
+            if glyphs.contains(&field[pt].glyph()) {
+                self.cursor = pt;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// moves every cell from `from` onwards one step along, dropping the last
+fn shift_toward_end(field: &mut Field, from: Point, step: Point) {
+    let mut end = from;
+    while field.point_in_bounds(end + step) {
+        end += step;
+    }
+    let mut pt = end;
+    while pt != from {
+        let prev = pt - step;
+        let _ = field.put(pt, field[prev].content());
+        pt = prev;
+    }
+    field[from].clear();
+}
+
+// removes the cell at `at`, pulling the rest of the line back over it
+fn shift_toward_start(field: &mut Field, at: Point, step: Point) {
+    let mut pt = at;
+    while field.point_in_bounds(pt + step) {
+        let _ = field.put(pt, field[pt + step].content());
+        pt += step;
+    }
+    field[pt].clear();
+}
+
+fn selection_size(min: Point, max: Point) -> (usize, usize) {
+    ((max.x - min.x) as usize + 1, (max.y - min.y) as usize + 1)
+}
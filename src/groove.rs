@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+// a feel for the midi going out: every 16th, which is a frame, is held back
+// by so much of a 16th, in a pattern that repeats. swing holds back the
+// second 16th of each pair, by as much as an mpc's swing setting of the
+// same number, where 50 is straight and 66 is close to triplets. notes are
+// only ever late, never early, so nothing has to be sent ahead.
+
+// name, then the delay for each 16th in percent of a 16th
+static GROOVES: &[(&str, &[u32])] = &[
+    ("straight", &[0]),
+    ("swing54", &[0, 8]),
+    ("swing58", &[0, 16]),
+    ("swing62", &[0, 24]),
+    ("swing66", &[0, 32]),
+    ("swing71", &[0, 42]),
+    // the off 16ths dragging by different amounts across the beat
+    ("lazy", &[0, 12, 4, 20]),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Groove {
+    pub name: &'static str,
+    delays: &'static [u32],
+}
+
+impl Groove {
+    pub fn named(name: &str) -> Result<Self, String> {
+        GROOVES.iter()
+               .find(|(n, _)| *n == name)
+               .map(|&(name, delays)| Self { name, delays })
+               .ok_or_else(|| format!("unknown groove `{}`, expected one of {}", name, names().join(", ")))
+    }
+
+    // how late the midi from a frame goes out, at a frame's length
+    pub fn delay(&self, frame: u32, frame_duration: Duration) -> Duration {
+        let percent = self.delays[frame as usize % self.delays.len()];
+        frame_duration * percent / 100
+    }
+}
+
+impl Default for Groove {
+    fn default() -> Self {
+        Self { name: GROOVES[0].0, delays: GROOVES[0].1 }
+    }
+}
+
+pub fn names() -> Vec<&'static str> {
+    GROOVES.iter().map(|&(name, _)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn swing_holds_back_the_even_16ths() {
+        for percent in [54, 58, 62, 66, 71] {
+            let groove = Groove::named(&format!("swing{}", percent)).unwrap();
+            // the second 16th of the pair starts `percent` of the way
+            // through the 8th, which is two frames
+            let late = FRAME * (2 * percent - 100) / 100;
+            for frame in [0, 2, 4, 98] {
+                assert_eq!(groove.delay(frame, FRAME), Duration::ZERO, "swing{} {}", percent, frame);
+                assert_eq!(groove.delay(frame + 1, FRAME), late, "swing{} {}", percent, frame + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn grooves_repeat() {
+        let lazy = Groove::named("lazy").unwrap();
+        let delays: Vec<_> = (0..8).map(|frame| lazy.delay(frame, FRAME).as_millis()).collect();
+        assert_eq!(delays, [0, 12, 4, 20, 0, 12, 4, 20]);
+        assert_eq!(lazy.delay(u32::MAX, FRAME), Duration::from_millis(20));
+        let straight = Groove::default();
+        assert!((0..8).all(|frame| straight.delay(frame, FRAME) == Duration::ZERO));
+    }
+
+    #[test]
+    fn nothing_is_held_into_the_next_16th() {
+        for name in names() {
+            let groove = Groove::named(name).unwrap();
+            assert!((0..8).all(|frame| groove.delay(frame, FRAME) < FRAME), "{}", name);
+        }
+    }
+
+    #[test]
+    fn unknown_grooves_are_refused() {
+        assert_eq!(Groove::named("straight"), Ok(Groove::default()));
+        for name in ["", "swing", "swing50", "Straight"] {
+            assert_eq!(Groove::named(name),
+                       Err(format!("unknown groove `{}`, expected one of straight, swing54, swing58, \
+                                    swing62, swing66, swing71, lazy", name)));
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! the past of a field, for stepping back through edits and frames.
+//!
+//! a [`Snapshot`] keeps a field's contents in fixed size pages and shares
+//! every page that didn't change with the snapshot taken before it, so a
+//! frame that moved a handful of operators costs a page or two rather than
+//! a copy of the whole grid. that's what makes minutes of history on a big
+//! field affordable.
+
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+
+use crate::{CellLike, Content, Field, Point};
+
+/// cells to a page. pages run along rows, so a change touches few of them.
+pub const PAGE_SIZE: usize = 256;
+
+/// a field's contents at one frame
+#[derive(Clone)]
+pub struct Snapshot {
+    pub width: usize,
+    pub height: usize,
+    pub frame: u32,
+    pages: Vec<Rc<[Content]>>,
+}
+
+impl Snapshot {
+    /// the contents of `field` as of `frame`, sharing what's unchanged with
+    /// `prev`
+    pub fn take<C: CellLike>(field: &Field<C>, frame: u32, prev: Option<&Snapshot>) -> Self {
+        let (width, height) = (field.slots.width, field.slots.height);
+        let prev = prev.filter(|prev| prev.width == width && prev.height == height);
+        let pages = field.slots.as_slice()
+                         .chunks(PAGE_SIZE)
+                         .enumerate()
+                         .map(|(i, cells)| {
+                             match prev.map(|prev| &prev.pages[i]) {
+                                 Some(page) if page.iter().zip(cells).all(|(c, s)| *c == s.content()) => {
+                                     page.clone()
+                                 }
+                                 _ => cells.iter().map(C::content).collect(),
+                             }
+                         })
+                         .collect();
+        Self { width, height, frame, pages }
+    }
+
+    /// writes the contents back, resizing the field to fit. anything else
+    /// the cells carry is left as it was, and every cell ends up unlocked.
+    pub fn restore<C: CellLike>(&self, field: &mut Field<C>) {
+        if field.slots.width != self.width || field.slots.height != self.height {
+            *field = Field::empty(self.width, self.height);
+        }
+        let contents = self.pages.iter().flat_map(|page| page.iter());
+        for (slot, &content) in field.slots.as_mut_slice().iter_mut().zip(contents) {
+            slot.set_content(content);
+        }
+        field.unlock_all();
+        field.reindex();
+    }
+
+    /// what was in a cell, None outside the field
+    pub fn get(&self, pt: Point) -> Option<Content> {
+        if pt.x < 0 || pt.y < 0 || pt.x as usize >= self.width || pt.y as usize >= self.height {
+            return None;
+        }
+        let i = pt.y as usize * self.width + pt.x as usize;
+        self.pages.get(i / PAGE_SIZE).map(|page| page[i % PAGE_SIZE])
+    }
+
+    /// the cells whose contents `field` has changed since, with what's in
+    /// them now. nothing for a field of another size.
+    pub fn changes<'a, C: CellLike>(&'a self, field: &'a Field<C>) -> impl Iterator<Item = (Point, Content)> + 'a {
+        let same_size = field.slots.width == self.width && field.slots.height == self.height;
+        let width = self.width.max(1);
+        self.pages.iter()
+            .flat_map(|page| page.iter())
+            .zip(field.slots.as_slice())
+            .enumerate()
+            .filter(move |(_, (&then, slot))| same_size && slot.content() != then)
+            .map(move |(i, (_, slot))| (Point::new((i % width) as i32, (i / width) as i32), slot.content()))
+    }
+
+    /// pages this snapshot doesn't share with `other`, roughly what keeping
+    /// it costs on top
+    pub fn pages_apart(&self, other: &Snapshot) -> usize {
+        self.pages.iter()
+            .enumerate()
+            .filter(|&(i, page)| other.pages.get(i).is_none_or(|o| !Rc::ptr_eq(page, o)))
+            .count()
+    }
+}
+
+/// the last so many snapshots of a field, oldest first
+pub struct History {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+}
+
+impl History {
+    /// keeps at most `capacity` snapshots, dropping the oldest past that
+    pub fn new(capacity: usize) -> Self {
+        Self { snapshots: VecDeque::new(), capacity: capacity.max(1) }
+    }
+
+    /// snapshots `field` unless it's unchanged since the latest one
+    pub fn record<C: CellLike>(&mut self, field: &Field<C>, frame: u32) {
+        let snap = Snapshot::take(field, frame, self.snapshots.back());
+        if let Some(latest) = self.snapshots.back() {
+            if (latest.width, latest.height) == (snap.width, snap.height) && snap.pages_apart(latest) == 0 {
+                return;
+            }
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snap);
+    }
+
+    /// drops the latest snapshot and returns the one before it, which is
+    /// what to restore. the first snapshot stays.
+    pub fn step_back(&mut self) -> Option<&Snapshot> {
+        if self.snapshots.len() < 2 {
+            return None;
+        }
+        self.snapshots.pop_back();
+        self.snapshots.back()
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // three pages of eight rows
+    fn field() -> Field {
+        Field::new(32, 24)
+    }
+
+    fn shared(a: &Snapshot, b: &Snapshot) -> Vec<bool> {
+        a.pages.iter().zip(&b.pages).map(|(a, b)| Rc::ptr_eq(a, b)).collect()
+    }
+
+    #[test]
+    fn unchanged_pages_are_shared() {
+        let mut field = field();
+        let first = Snapshot::take(&field, 0, None);
+        field.set(Point::new(12, 9), 'E').unwrap();
+        let second = Snapshot::take(&field, 1, Some(&first));
+        assert_eq!(shared(&first, &second), [true, false, true]);
+        assert_eq!(second.pages_apart(&first), 1);
+
+        // changed back, the page matches again but is a copy of its own
+        field.set(Point::new(12, 9), '\0').unwrap();
+        let third = Snapshot::take(&field, 2, Some(&second));
+        assert_eq!(shared(&second, &third), [true, false, true]);
+        assert_eq!(third.get(Point::new(12, 9)), first.get(Point::new(12, 9)));
+        assert!(Snapshot::take(&field, 3, Some(&third)).pages_apart(&third) == 0);
+    }
+
+    #[test]
+    fn other_sizes_share_nothing() {
+        let first = Snapshot::take(&field(), 0, None);
+        let bigger = Snapshot::take(&Field::new(32, 25), 1, Some(&first));
+        assert_eq!(bigger.pages.len(), 4);
+        assert_eq!(bigger.pages_apart(&first), 4);
+        assert_eq!(bigger.changes(&field()).count(), 0);
+    }
+
+    #[test]
+    fn stepping_back_restores_each_edit() {
+        let mut history = History::new(100);
+        let mut field = field();
+        let mut before = Vec::new();
+        history.record(&field, 0);
+        for (i, glyph) in "EWNS*ab12".chars().enumerate() {
+            before.push(field.clone());
+            let pt = Point::new((i * 37 % 32) as i32, (i * 7 % 24) as i32);
+            field.set(pt, glyph).unwrap();
+            field.lock(pt);
+            history.record(&field, i as u32 + 1);
+        }
+        assert_eq!(history.len(), 10);
+
+        let mut restored = Field::new(1, 1);
+        while let Some(expected) = before.pop() {
+            let snap = history.step_back().unwrap();
+            assert_eq!(snap.frame as usize, before.len());
+            snap.restore(&mut restored);
+            assert!(restored == expected, "after undoing {} edits", 9 - before.len());
+            assert_eq!(snap.changes(&expected).count(), 0);
+        }
+        assert!(history.step_back().is_none());
+        assert_eq!(history.len(), 1);
+        assert!((0..32 * 24).all(|i| !restored.is_locked(Point::new(i % 32, i / 32))));
+    }
+
+    #[test]
+    fn unchanged_fields_arent_recorded() {
+        let mut history = History::new(3);
+        let mut field = field();
+        history.record(&field, 0);
+        history.record(&field, 1);
+        assert_eq!(history.latest().map(|snap| snap.frame), Some(0));
+        for frame in 1..5 {
+            field.set(Point::new(0, 0), if frame % 2 == 0 { 'E' } else { 'W' }).unwrap();
+            history.record(&field, frame);
+        }
+        // only the latest three are kept
+        assert_eq!(history.len(), 3);
+        let frames: Vec<u32> = history.snapshots.iter().map(|snap| snap.frame).collect();
+        assert_eq!(frames, [2, 3, 4]);
+    }
+
+    #[test]
+    fn changes_are_the_cells_edited_since() {
+        let mut field = field();
+        let snap = Snapshot::take(&field, 0, None);
+        field.set(Point::new(31, 23), 'E').unwrap();
+        field.set(Point::new(0, 1), '5').unwrap();
+        let changes: Vec<Point> = snap.changes(&field).map(|(pt, _)| pt).collect();
+        assert_eq!(changes, [Point::new(0, 1), Point::new(31, 23)]);
+        assert_eq!(snap.get(Point::new(32, 0)), None);
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use crate::control::{Command, SharedStatus};
+
+// a deliberately tiny http/1.1 server, one request per connection:
+//
+//     GET  /field          the grid as plain text
+//     GET  /state          frame, bpm, transport and size as json
+//     POST /write  "x y g" write glyph g at x,y (`.` clears)
+//     POST /bpm    "140"   set the tempo
+//     POST /pause
+//     POST /play
+
+// bodies past this are refused rather than read into memory
+const MAX_BODY: usize = 1 << 20;
+
+// the request line and headers together
+const MAX_HEAD: u64 = 16 << 10;
+
+// a client that goes quiet this long is dropped, as requests are handled
+// one at a time and it would hold up everyone else
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn new(status: &'static str, body: String) -> Self {
+        Self { status, content_type: "text/plain", body }
+    }
+
+    fn json(body: String) -> Self {
+        Self { status: "200 OK", content_type: "application/json", body }
+    }
+}
+
+pub fn spawn(addr: &str, status: SharedStatus, tx: Sender<Command>) -> Result<(), String> {
+    let listener = TcpListener::bind(addr).map_err(|e| format!("http {}: {}", addr, e))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+                continue;
+            }
+            let response = match read_request(&mut stream) {
+                Ok(req) => route(&req, &status, &tx),
+                Err(res) => res,
+            };
+            let _ = write_response(&mut stream, &response);
+        }
+    });
+
+    Ok(())
+}
+
+fn read_request(stream: impl Read) -> Result<Request, Response> {
+    let bad = |e: String| Response::new("400 Bad Request", format!("{}\n", e));
+    let mut reader = BufReader::new(stream);
+    let mut head = Read::take(&mut reader, MAX_HEAD);
+    let mut line = String::new();
+    head.read_line(&mut line).map_err(|e| bad(e.to_string()))?;
+
+    let mut words = line.split_whitespace();
+    let (method, path) = match (words.next(), words.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(bad("malformed request line".to_string())),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if head.read_line(&mut header).map_err(|e| bad(e.to_string()))? == 0 {
+            return Err(bad("headers too long or cut off".to_string()));
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| bad("bad content-length".to_string()))?;
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(Response::new("413 Payload Too Large",
+                                 format!("bodies are limited to {} bytes\n", MAX_BODY)));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(|e| bad(e.to_string()))?;
+    let body = String::from_utf8(body).map_err(|_| bad("body is not utf-8".to_string()))?;
+
+    Ok(Request { method, path, body })
+}
+
+fn write_response(stream: &mut TcpStream, res: &Response) -> std::io::Result<()> {
+    write!(stream,
+           "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           res.status, res.content_type, res.body.len(), res.body)
+}
+
+fn route(req: &Request, status: &SharedStatus, tx: &Sender<Command>) -> Response {
+    let status = status.lock().unwrap().clone();
+
+    let line = match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "/field") => return Response::new("200 OK", status.grid),
+        ("GET", "/state") => return Response::json(status.json()),
+        ("POST", "/write") => format!("write {}", req.body),
+        ("POST", "/bpm") => format!("bpm {}", req.body),
+        ("POST", "/pause") => "pause".to_string(),
+        ("POST", "/play") => "play".to_string(),
+        (_, "/field") | (_, "/state") | (_, "/write") | (_, "/bpm") | (_, "/pause")
+        | (_, "/play") => {
+            return Response::new("405 Method Not Allowed", "method not allowed\n".to_string());
+        }
+        _ => return Response::new("404 Not Found", "not found\n".to_string()),
+    };
+
+    let cmd: Command = match line.parse() {
+        Ok(cmd) => cmd,
+        Err(e) => return Response::new("400 Bad Request", format!("{}\n", e)),
+    };
+
+    if let Err(e) = status.check(&cmd) {
+        return Response::new("400 Bad Request", format!("{}\n", e));
+    }
+
+    match tx.send(cmd) {
+        Ok(()) => Response::new("202 Accepted", "ok\n".to_string()),
+        Err(_) => Response::new("503 Service Unavailable", "engine stopped\n".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(raw: &[u8]) -> Result<Request, Response> {
+        read_request(raw)
+    }
+
+    fn refused(raw: &[u8]) -> (&'static str, String) {
+        match read(raw) {
+            Ok(req) => panic!("read {} {}", req.method, req.path),
+            Err(res) => (res.status, res.body),
+        }
+    }
+
+    #[test]
+    fn requests_are_read() {
+        let req = read(b"POST /write HTTP/1.1\r\nHost: x\r\ncontent-LENGTH: 5\r\n\r\n1 2 Ztrailing").ok().unwrap();
+        assert_eq!((req.method.as_str(), req.path.as_str(), req.body.as_str()), ("POST", "/write", "1 2 Z"));
+        let req = read(b"GET /field HTTP/1.1\r\n\r\n").ok().unwrap();
+        assert_eq!((req.method.as_str(), req.path.as_str(), req.body.as_str()), ("GET", "/field", ""));
+    }
+
+    #[test]
+    fn malformed_request_lines_are_refused() {
+        for raw in [&b""[..], b"\r\n\r\n", b"GET\r\n\r\n"] {
+            assert_eq!(refused(raw), ("400 Bad Request", "malformed request line\n".to_string()));
+        }
+    }
+
+    #[test]
+    fn bad_content_lengths_are_refused() {
+        for length in ["-1", "ten", "", "1 0"] {
+            let raw = format!("POST /bpm HTTP/1.1\r\nContent-Length: {}\r\n\r\n140", length);
+            assert_eq!(refused(raw.as_bytes()), ("400 Bad Request", "bad content-length\n".to_string()));
+        }
+        // promising more than is sent
+        let (status, _) = refused(b"POST /bpm HTTP/1.1\r\nContent-Length: 10\r\n\r\n140");
+        assert_eq!(status, "400 Bad Request");
+        let (status, body) = refused(b"POST /bpm HTTP/1.1\r\nContent-Length: 2\r\n\r\n\xff\xfe");
+        assert_eq!((status, body.as_str()), ("400 Bad Request", "body is not utf-8\n"));
+    }
+
+    #[test]
+    fn heads_are_limited() {
+        let mut raw = b"GET /field HTTP/1.1\r\n".to_vec();
+        while raw.len() as u64 <= MAX_HEAD {
+            raw.extend_from_slice(b"X-Padding: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n");
+        }
+        raw.extend_from_slice(b"\r\n");
+        assert_eq!(refused(&raw), ("400 Bad Request", "headers too long or cut off\n".to_string()));
+        // and headers that never end
+        assert_eq!(refused(b"GET /field HTTP/1.1\r\nHost: x\r\n").1, "headers too long or cut off\n");
+    }
+
+    #[test]
+    fn bodies_are_limited() {
+        let raw = format!("POST /write HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert_eq!(refused(raw.as_bytes()).0, "413 Payload Too Large");
+        let mut raw = format!("POST /write HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY).into_bytes();
+        raw.resize(raw.len() + MAX_BODY, b'.');
+        assert_eq!(read(&raw).ok().unwrap().body.len(), MAX_BODY);
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use lyza::{xorshift, Event};
+
+// bounded randomness on the notes going out, so repeated hits don't all
+// land at the same strength on the same instant. velocities move up or
+// down by up to `velocity`, never as far as 0, which would be a note off,
+// and notes go out up to `timing` percent of a 16th late, their note offs
+// as late as they were. the randomness comes from the context's seeded
+// generator, so a replay plays the same. the `:` operator has its own
+// amount on top of this, for velocities only.
+
+// later than this and a note could go out after the next 16th's notes
+pub const MAX_TIMING: u32 = 50;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Humanize {
+    pub velocity: u8,
+    pub timing: u32,
+}
+
+// a note by port, channel and number
+type Key = (u8, u8, u8);
+
+// how late the notes playing went out, so their note offs can follow
+#[derive(Debug, Default)]
+pub struct Playing {
+    // with whether the note off has gone out this frame
+    late: HashMap<Key, (Duration, bool)>,
+}
+
+impl Playing {
+    // forgets the notes stopped last frame
+    pub fn next_frame(&mut self) {
+        self.late.retain(|_, &mut (_, stopped)| !stopped);
+    }
+}
+
+impl Humanize {
+    pub fn is_off(&self) -> bool {
+        self.velocity == 0 && self.timing == 0
+    }
+
+    // varies a note on, drawing from `rng` for every one, and returns how
+    // much later it goes out. a note off goes out as late as its note on.
+    pub fn apply(&self, rng: &mut u64, playing: &mut Playing, event: &mut Event,
+                 frame_duration: Duration) -> Duration {
+        if self.is_off() {
+            return Duration::ZERO;
+        }
+        match event {
+            Event::NoteOn { port, channel, note, velocity } if *velocity > 0 => {
+                if self.velocity > 0 {
+                    let spread = self.velocity as i64;
+                    let by = (xorshift(rng) % (2 * spread as u64 + 1)) as i64 - spread;
+                    *velocity = (*velocity as i64 + by).clamp(1, 127) as u8;
+                }
+                let percent = xorshift(rng) % (self.timing as u64 + 1);
+                let mut late = frame_duration * percent as u32 / 100;
+                // played again in the frame it was stopped, it mustn't go
+                // out before that note off
+                let key = (*port, *channel, *note);
+                if let Some(&(off, true)) = playing.late.get(&key) {
+                    late = late.max(off);
+                }
+                playing.late.insert(key, (late, false));
+                late
+            }
+            Event::NoteOff { port, channel, note } | Event::NoteOn { port, channel, note, .. } => {
+                match playing.late.get_mut(&(*port, *channel, *note)) {
+                    Some((late, stopped)) => {
+                        *stopped = true;
+                        *late
+                    }
+                    None => Duration::ZERO,
+                }
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    fn on(note: u8) -> Event {
+        Event::NoteOn { port: 0, channel: 0, note, velocity: 100 }
+    }
+
+    fn off(note: u8) -> Event {
+        Event::NoteOff { port: 0, channel: 0, note }
+    }
+
+    #[test]
+    fn note_offs_follow_their_note_ons() {
+        let humanize = Humanize { velocity: 0, timing: MAX_TIMING };
+        for seed in 1..200 {
+            let (mut rng, mut playing) = (seed, Playing::default());
+            let first = humanize.apply(&mut rng, &mut playing, &mut on(60), FRAME);
+            let second = humanize.apply(&mut rng, &mut playing, &mut on(62), FRAME);
+            assert!(first <= FRAME / 2 && second <= FRAME / 2);
+            playing.next_frame();
+            assert_eq!(humanize.apply(&mut rng, &mut playing, &mut off(62), FRAME), second);
+            playing.next_frame();
+            assert_eq!(humanize.apply(&mut rng, &mut playing, &mut off(60), FRAME), first);
+            playing.next_frame();
+            assert!(playing.late.is_empty());
+        }
+    }
+
+    #[test]
+    fn notes_played_again_wait_for_their_note_off() {
+        let humanize = Humanize { velocity: 0, timing: MAX_TIMING };
+        for seed in 1..200 {
+            let (mut rng, mut playing) = (seed, Playing::default());
+            humanize.apply(&mut rng, &mut playing, &mut on(60), FRAME);
+            playing.next_frame();
+            let stopped = humanize.apply(&mut rng, &mut playing, &mut off(60), FRAME);
+            let started = humanize.apply(&mut rng, &mut playing, &mut on(60), FRAME);
+            assert!(started >= stopped, "seed {}: {:?} before {:?}", seed, started, stopped);
+            playing.next_frame();
+            assert_eq!(humanize.apply(&mut rng, &mut playing, &mut off(60), FRAME), started);
+        }
+    }
+
+    #[test]
+    fn velocities_stay_in_range() {
+        let humanize = Humanize { velocity: 20, timing: 0 };
+        let (mut rng, mut playing) = (1, Playing::default());
+        for velocity in [1, 10, 100, 127] {
+            for _ in 0..200 {
+                let mut event = Event::NoteOn { port: 0, channel: 0, note: 60, velocity };
+                assert_eq!(humanize.apply(&mut rng, &mut playing, &mut event, FRAME), Duration::ZERO);
+                match event {
+                    Event::NoteOn { velocity: v, .. } => {
+                        assert!((1..=127).contains(&v) && (v as i32 - velocity as i32).abs() <= 20)
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_changes_when_off() {
+        let (mut rng, mut playing) = (7, Playing::default());
+        let mut event = on(60);
+        assert_eq!(Humanize::default().apply(&mut rng, &mut playing, &mut event, FRAME), Duration::ZERO);
+        assert_eq!((event, rng), (on(60), 7));
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::editor::Mode;
+use crate::term::Key;
+
+// what keys do in the editor. every action is looked up through a table, so
+// a whole layout can be picked with `keymap = "..."` in the config file and
+// single actions rebound from a [keybindings] table:
+//
+//     keymap = "dvorak"
+//
+//     [keybindings]
+//     save = "ctrl-s, ctrl-x ctrl-s"
+//     play_pause = "space p"
+//
+// alternatives are separated by commas, keys within a chord by spaces.
+// several actions can share a key as long as they apply in different modes,
+// e.g. enter is replace in normal mode and commits a pending paste.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    MoveLeft,
+    MoveDown,
+    MoveUp,
+    MoveRight,
+    Replace,
+    InsertRow,
+    InsertColumn,
+    Delete,
+    Select,
+    Paste,
+    Copy,
+    Cut,
+    Erase,
+    Rotate,
+    FlipHorizontal,
+    FlipVertical,
+    Commit,
+    Cancel,
+    Minimap,
+    Help,
+    Search,
+    SearchNext,
+    PlayPause,
+    Quit,
+    ForceQuit,
+    Save,
+    Zoom,
+    AddCursor,
+    ClearCursors,
+    Record,
+    PlayMacro,
+    Ports,
+    Grid,
+    Follow,
+    Diff,
+    Command,
+    Undo,
+    Quantize,
+}
+
+static ACTIONS: &[(Action, &str)] = &[
+    (Action::MoveLeft, "move_left"),
+    (Action::MoveDown, "move_down"),
+    (Action::MoveUp, "move_up"),
+    (Action::MoveRight, "move_right"),
+    (Action::Replace, "replace"),
+    (Action::InsertRow, "insert_row"),
+    (Action::InsertColumn, "insert_column"),
+    (Action::Delete, "delete"),
+    (Action::Select, "select"),
+    (Action::Paste, "paste"),
+    (Action::Copy, "copy"),
+    (Action::Cut, "cut"),
+    (Action::Erase, "erase"),
+    (Action::Rotate, "rotate"),
+    (Action::FlipHorizontal, "flip_horizontal"),
+    (Action::FlipVertical, "flip_vertical"),
+    (Action::Commit, "commit"),
+    (Action::Cancel, "cancel"),
+    (Action::Minimap, "minimap"),
+    (Action::Help, "help"),
+    (Action::Search, "search"),
+    (Action::SearchNext, "search_next"),
+    (Action::PlayPause, "play_pause"),
+    (Action::Quit, "quit"),
+    (Action::ForceQuit, "force_quit"),
+    (Action::Save, "save"),
+    (Action::Zoom, "zoom"),
+    (Action::AddCursor, "add_cursor"),
+    (Action::ClearCursors, "clear_cursors"),
+    (Action::Record, "record"),
+    (Action::PlayMacro, "play_macro"),
+    (Action::Ports, "ports"),
+    (Action::Grid, "grid"),
+    (Action::Follow, "follow"),
+    (Action::Diff, "diff"),
+    (Action::Command, "command"),
+    (Action::Undo, "undo"),
+    (Action::Quantize, "quantize"),
+];
+
+static DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("move_left", "h, left"),
+    ("move_down", "j, down"),
+    ("move_up", "k, up"),
+    ("move_right", "l, right"),
+    ("replace", "R, enter"),
+    ("insert_row", "i"),
+    ("insert_column", "I"),
+    ("delete", "x, delete"),
+    ("select", "v"),
+    ("paste", "p"),
+    ("copy", "y"),
+    ("cut", "d, x"),
+    ("erase", "X"),
+    ("rotate", "r"),
+    ("flip_horizontal", "|"),
+    ("flip_vertical", "_"),
+    ("commit", "p, enter"),
+    ("cancel", "esc"),
+    ("minimap", "m"),
+    ("help", "?"),
+    ("search", "/"),
+    ("search_next", "n"),
+    ("play_pause", "space"),
+    ("quit", "q"),
+    ("force_quit", "ctrl-c"),
+    ("save", "ctrl-s"),
+    ("zoom", "ctrl-z"),
+    ("add_cursor", "c"),
+    ("clear_cursors", "C"),
+    ("record", "Q"),
+    ("play_macro", "@"),
+    ("ports", "P"),
+    ("grid", "g"),
+    ("follow", "f"),
+    ("diff", "D"),
+    ("command", ":"),
+    ("undo", "u"),
+    ("quantize", "b"),
+];
+
+// movement on the dvorak home row, with search_next off `n`
+static DVORAK_BINDINGS: &[(&str, &str)] = &[
+    ("move_left", "h, left"),
+    ("move_down", "t, down"),
+    ("move_up", "n, up"),
+    ("move_right", "s, right"),
+    ("search_next", "l"),
+];
+
+static LAYOUTS: &[&str] = &["default", "dvorak"];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTIONS.iter().find(|(_, n)| *n == name).map(|&(action, _)| action)
+    }
+
+    pub fn name(&self) -> &'static str {
+        ACTIONS.iter().find(|(a, _)| a == self).map(|&(_, n)| n).unwrap()
+    }
+
+    // whether the action means anything in a mode. in the typing modes
+    // plain characters are typed, so only non-character keys reach here.
+    pub fn applies_in(&self, mode: Mode) -> bool {
+        use Action::*;
+        match self {
+            MoveLeft | MoveDown | MoveUp | MoveRight | ForceQuit | Save | Zoom => true,
+            Cancel => mode != Mode::Normal,
+            Delete => !matches!(mode, Mode::Visual | Mode::Paste),
+            Copy | Cut | Erase | Rotate | FlipHorizontal | FlipVertical => mode == Mode::Visual,
+            Commit => mode == Mode::Paste,
+            _ => mode == Mode::Normal,
+        }
+    }
+}
+
+pub enum Lookup {
+    // everything bound to the keys; the caller picks by mode
+    Actions(Vec<Action>),
+    // the keys so far start a longer chord
+    Prefix,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Vec<Action>>,
+}
+
+impl Keymap {
+    pub fn named(layout: &str) -> Result<Self, String> {
+        let mut ret = Keymap { bindings: HashMap::new() };
+        for (action, keys) in DEFAULT_BINDINGS {
+            ret.bind(action, keys)?;
+        }
+        match layout {
+            "default" => {}
+            "dvorak" => {
+                for (action, keys) in DVORAK_BINDINGS {
+                    ret.bind(action, keys)?;
+                }
+            }
+            _ => return Err(format!("unknown keymap `{}`, expected one of {}",
+                                    layout, LAYOUTS.join(", "))),
+        }
+        Ok(ret)
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let mut ret = Self::named(&config.keymap)?;
+        for (action, keys) in config.keybindings.iter() {
+            ret.bind(action, keys).map_err(|e| format!("keybindings.{}: {}", action, e))?;
+        }
+        Ok(ret)
+    }
+
+    // replaces whatever the action was bound to before
+    pub fn bind(&mut self, action: &str, keys: &str) -> Result<(), String> {
+        let action = Action::from_name(action)
+                         .ok_or_else(|| format!("unknown action `{}`", action))?;
+        let chords = keys.split(',')
+                         .map(parse_chord)
+                         .collect::<Result<Vec<_>, _>>()?;
+
+        for actions in self.bindings.values_mut() {
+            actions.retain(|&a| a != action);
+        }
+        self.bindings.retain(|_, actions| !actions.is_empty());
+        for chord in chords {
+            self.bindings.entry(chord).or_default().push(action);
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(actions) = self.bindings.get(keys) {
+            return Lookup::Actions(actions.clone());
+        }
+        if self.bindings.keys().any(|chord| chord.len() > keys.len() && chord.starts_with(keys)) {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    pub fn is_bound(&self, key: Key, action: Action) -> bool {
+        matches!(self.lookup(&[key]), Lookup::Actions(actions) if actions.contains(&action))
+    }
+}
+
+fn parse_chord(src: &str) -> Result<Vec<Key>, String> {
+    let keys = src.split_whitespace()
+                  .map(parse_key)
+                  .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key binding".to_string());
+    }
+    Ok(keys)
+}
+
+fn parse_key(name: &str) -> Result<Key, String> {
+    let key = match name {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "enter" => Key::Enter,
+        "tab" => Key::Tab,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "esc" => Key::Esc,
+        "space" => Key::Char(' '),
+        _ => {
+            let (ctrl, rest) = match name.strip_prefix("ctrl-") {
+                Some(rest) => (true, rest),
+                None => (false, name),
+            };
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) if ctrl && ch.is_ascii_lowercase() => Key::Ctrl(ch),
+                (Some(ch), None) if !ctrl => Key::Char(ch),
+                _ => return Err(format!("unknown key `{}`", name)),
+            }
+        }
+    };
+    Ok(key)
+}
@@ -0,0 +1,2698 @@
+//! the lyza engine: a grid of glyphs where every operator runs once a
+//! frame, moving itself about or reading the cells around it and emitting
+//! [`Event`]s for a frontend to deliver to its [`Sink`]s.
+//!
+//! ```
+//! use lyza::{Context, Field, OpdefTable, Point};
+//!
+//! let mut ctx = Context::new(OpdefTable::default(), Field::new(8, 8));
+//! ctx.field.set(Point::new(0, 0), 'E').unwrap();
+//! let frame = ctx.tick();
+//! assert_eq!(ctx.field.get(Point::new(1, 0)), Some('E'));
+//! assert_eq!(frame.changed, [Point::new(0, 0), Point::new(1, 0)]);
+//! ```
+//!
+//! the `lyza` binary is one frontend over this; embedders bring their own.
+//!
+//! the engine builds without the standard library, with `alloc` only, for
+//! running on microcontrollers. turn off the default `std` feature for that;
+//! all it adds is the conversion from `std::io` errors.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// for `define_op!`, which expands in crates that may not have a prelude
+#[doc(hidden)]
+pub extern crate alloc as __alloc;
+
+use core::fmt;
+use core::default;
+use core::hash::{Hash, Hasher};
+use core::ops;
+use core::str::FromStr;
+use core::error;
+use core::iter;
+use core::time::Duration;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+
+pub mod codec;
+pub mod history;
+pub mod testing;
+
+pub use codec::{decode_base64, encode_base64};
+
+/// everything the engine can report going wrong. none of it is fatal: a
+/// frontend shows the message and carries on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LyzaError {
+    /// a cell holds a glyph no operator is defined for
+    UnknownOperator { glyph: char, at: Point },
+    OutOfBounds(Point),
+    /// an operator at `at` wrote somewhere it shouldn't have: outside the
+    /// field, or over a cell already locked this frame. only reported by
+    /// a [`Strictness`] context.
+    StrayWrite { glyph: char, at: Point, to: Point, locked: bool },
+    /// the operator at `at` returned an error. the frame carries on
+    /// without whatever it was doing.
+    OperatorFailed { glyph: char, at: Point, error: Box<LyzaError> },
+    /// the engine's own bookkeeping went wrong, found by a context with
+    /// `check_invariants` set
+    Invariant(String),
+    /// what the `?` operator reports when the cell above it doesn't hold
+    /// what's east of it. None is an empty cell.
+    Assertion { expected: Option<char>, actual: Option<char> },
+    /// text that didn't make sense, with a message saying why
+    Parse(String),
+    Io(String),
+    /// for frontends driving midi devices
+    Midi(String),
+    /// a value or definition the engine won't take, e.g. a glyph that's
+    /// already taken
+    Invalid(String),
+}
+
+impl fmt::Display for LyzaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LyzaError::UnknownOperator { glyph, at } => write!(f, "unknown operator `{}` at {}", glyph, at),
+            LyzaError::OutOfBounds(pt) => write!(f, "{} is outside the field", pt),
+            LyzaError::StrayWrite { glyph, at, to, locked: false } => {
+                write!(f, "`{}` at {} wrote outside the field, to {}", glyph, at, to)
+            }
+            LyzaError::StrayWrite { glyph, at, to, locked: true } => {
+                write!(f, "`{}` at {} wrote over {}, already locked this frame", glyph, at, to)
+            }
+            LyzaError::OperatorFailed { glyph, at, error } => write!(f, "`{}` at {}: {}", glyph, at, error),
+            LyzaError::Invariant(msg) => write!(f, "invariant broken: {}", msg),
+            LyzaError::Assertion { expected, actual } => {
+                write!(f, "expected `{}`, found `{}`", expected.unwrap_or('.'), actual.unwrap_or('.'))
+            }
+            LyzaError::Parse(msg) | LyzaError::Io(msg) | LyzaError::Invalid(msg) => write!(f, "{}", msg),
+            LyzaError::Midi(msg) => write!(f, "midi: {}", msg),
+        }
+    }
+}
+
+impl error::Error for LyzaError {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for LyzaError {
+    fn from(e: io::Error) -> Self {
+        LyzaError::Io(e.to_string())
+    }
+}
+
+/// for callers that deal in plain messages
+impl From<LyzaError> for String {
+    fn from(e: LyzaError) -> Self {
+        e.to_string()
+    }
+}
+
+//
+
+//
+
+/// a cell position, or an offset between cells. y grows downwards.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn zero() -> Self {
+        Self { x: 0, y: 0 }
+    }
+
+    pub fn translate(&self, x: i32, y: i32) -> Self {
+        Self {
+            x: self.x + x,
+            y: self.y + y,
+        }
+    }
+
+    /// a quarter turn clockwise, as seen on screen: east becomes south
+    pub fn rotate_cw(&self) -> Self {
+        Self { x: -self.y, y: self.x }
+    }
+
+    pub fn rotate_ccw(&self) -> Self {
+        Self { x: self.y, y: -self.x }
+    }
+
+    /// steps between two points moving only along rows and columns
+    pub fn manhattan(&self, other: Point) -> i32 {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    /// steps between two points moving diagonals too
+    pub fn chebyshev(&self, other: Point) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+
+    /// the points north, south, east and west, in that order
+    pub fn neighbors4(&self) -> [Point; 4] {
+        Direction::ALL.map(|dir| *self + dir)
+    }
+
+    /// the eight surrounding points, row by row
+    pub fn neighbors8(&self) -> [Point; 8] {
+        [self.translate(-1, -1), self.translate(0, -1), self.translate(1, -1),
+         self.translate(-1, 0),                         self.translate(1, 0),
+         self.translate(-1, 1),  self.translate(0, 1),  self.translate(1, 1)]
+    }
+}
+
+/// `x,y`, as used on command lines and over the network
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+impl FromStr for Point {
+    type Err = LyzaError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let invalid = || LyzaError::Parse(format!("invalid point `{}`, expected x,y", src));
+        let (x, y) = src.split_once(',').ok_or_else(invalid)?;
+        let x = x.trim().parse().map_err(|_| invalid())?;
+        let y = y.trim().parse().map_err(|_| invalid())?;
+        Ok(Point { x, y })
+    }
+}
+
+impl ops::Add for Point {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            x: self.x + other.x,
+            y: self.y + other.y,
+        }
+    }
+}
+
+impl ops::Sub for Point {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            x: self.x - other.x,
+            y: self.y - other.y,
+        }
+    }
+}
+
+impl ops::AddAssign for Point {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl ops::SubAssign for Point {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl ops::Neg for Point {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self { x: -self.x, y: -self.y }
+    }
+}
+
+/// scales an offset, so `pt * 3` is three times as far
+impl ops::Mul<i32> for Point {
+    type Output = Self;
+
+    fn mul(self, n: i32) -> Self {
+        Self { x: self.x * n, y: self.y * n }
+    }
+}
+
+/// one of the four ways along the grid
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    N,
+    S,
+    E,
+    W,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::N, Direction::S, Direction::E, Direction::W];
+
+    /// one cell that way
+    pub fn to_point(self) -> Point {
+        match self {
+            Direction::N => Point { x:  0, y: -1 },
+            Direction::S => Point { x:  0, y:  1 },
+            Direction::E => Point { x:  1, y:  0 },
+            Direction::W => Point { x: -1, y:  0 },
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::N => Direction::S,
+            Direction::S => Direction::N,
+            Direction::E => Direction::W,
+            Direction::W => Direction::E,
+        }
+    }
+
+    /// a quarter turn clockwise
+    pub fn rotate(self) -> Self {
+        match self {
+            Direction::N => Direction::E,
+            Direction::E => Direction::S,
+            Direction::S => Direction::W,
+            Direction::W => Direction::N,
+        }
+    }
+
+    /// the heading of a movement operator, which is named after it
+    pub fn from_glyph(ch: char) -> Option<Self> {
+        match ch {
+            'N' => Some(Direction::N),
+            'S' => Some(Direction::S),
+            'E' => Some(Direction::E),
+            'W' => Some(Direction::W),
+            _ => None,
+        }
+    }
+}
+
+impl From<Direction> for Point {
+    fn from(dir: Direction) -> Point {
+        dir.to_point()
+    }
+}
+
+impl ops::Add<Direction> for Point {
+    type Output = Self;
+
+    fn add(self, dir: Direction) -> Self {
+        self + dir.to_point()
+    }
+}
+
+impl ops::AddAssign<Direction> for Point {
+    fn add_assign(&mut self, dir: Direction) {
+        *self = *self + dir;
+    }
+}
+
+/// `Direction::E * 3` is three cells east
+impl ops::Mul<i32> for Direction {
+    type Output = Point;
+
+    fn mul(self, n: i32) -> Point {
+        self.to_point() * n
+    }
+}
+
+//
+
+/// a fixed size grid of values, stored row by row in one vec
+#[derive(Clone)]
+pub struct Matrix<T> {
+    pub width: usize,
+    pub height: usize,
+    data: Vec<T>
+}
+
+impl<T: default::Default + Clone> Matrix<T> {
+    /// a grid filled with default values
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![Default::default(); width * height],
+        }
+    }
+
+    /// a grid with a value for every point, built row by row
+    pub fn from_fn(width: usize, height: usize, mut f: impl FnMut(Point) -> T) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(f(Point::new(x as i32, y as i32)));
+            }
+        }
+        Self { width, height, data }
+    }
+
+    /// a grid the same size with `f` applied to every value
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Matrix<U> {
+        Matrix {
+            width: self.width,
+            height: self.height,
+            data: self.data.iter().map(f).collect(),
+        }
+    }
+
+    pub fn fill(&mut self, value: T) {
+        self.data.fill(value);
+    }
+
+    /// the value at a point, or None off the grid
+    pub fn get(&self, pt: Point) -> Option<&T> {
+        if self.in_bounds(pt) {
+            Some(&self.data[self.index(pt)])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, pt: Point) -> Option<&mut T> {
+        if self.in_bounds(pt) {
+            let idx = self.index(pt);
+            Some(&mut self.data[idx])
+        } else {
+            None
+        }
+    }
+
+    /// like `get`, with an error to pass on off the grid
+    pub fn ref_idx(&self, pt: Point) -> Result<&T, LyzaError> {
+        self.get(pt).ok_or(LyzaError::OutOfBounds(pt))
+    }
+
+    pub fn mut_idx(&mut self, pt: Point) -> Result<&mut T, LyzaError> {
+        self.get_mut(pt).ok_or(LyzaError::OutOfBounds(pt))
+    }
+
+    // callers check bounds first
+    fn index(&self, pt: Point) -> usize {
+        pt.y as usize * self.width + pt.x as usize
+    }
+
+    /// every cell, row by row
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    /// each row, top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.data.chunks(self.width.max(1))
+    }
+
+    /// each column, left to right
+    pub fn cols(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.width).map(move |x| self.col(x))
+    }
+
+    /// one row, empty off the grid
+    pub fn row(&self, y: usize) -> &[T] {
+        if y < self.height {
+            &self.data[y * self.width..(y + 1) * self.width]
+        } else {
+            &[]
+        }
+    }
+
+    /// one column top to bottom, empty off the grid
+    pub fn col(&self, x: usize) -> impl Iterator<Item = &T> {
+        let len = if x < self.width { self.height } else { 0 };
+        self.data.iter().skip(x).step_by(self.width.max(1)).take(len)
+    }
+
+    pub fn in_bounds(&self, pt: Point) -> bool {
+        pt.x >= 0 && pt.y >= 0
+            && pt.x < self.width as i32
+            && pt.y < self.height as i32
+    }
+
+    /// a window onto `width`×`height` cells from `origin`. it may hang off
+    /// the edges; cells there just aren't part of it.
+    pub fn view(&self, origin: Point, (width, height): (usize, usize)) -> View<'_, T> {
+        View { matr: self, origin, width, height }
+    }
+
+    /// every cell with its position, row by row
+    pub fn indexed_iter(&self) -> MatrixIterator<'_, T> {
+        MatrixIterator {
+            matr: self,
+            idx: 0,
+        }
+    }
+
+    /// every cell with its position, row by row, for changing in place
+    pub fn indexed_iter_mut(&mut self) -> impl Iterator<Item = (Point, &mut T)> {
+        let width = self.width.max(1);
+        self.data.iter_mut().enumerate().map(move |(i, item)| {
+            (Point::new((i % width) as i32, (i / width) as i32), item)
+        })
+    }
+}
+
+/// `matrix[pt]` is for points known to be on the grid, and panics on any
+/// other. use `get` where a point might fall off the edge.
+impl<T: default::Default + Clone> ops::Index<Point> for Matrix<T> {
+    type Output = T;
+
+    fn index(&self, pt: Point) -> &T {
+        match self.get(pt) {
+            Some(item) => item,
+            None => panic!("{:?} out of bounds of a {}x{} matrix", pt, self.width, self.height),
+        }
+    }
+}
+
+impl<T: default::Default + Clone> ops::IndexMut<Point> for Matrix<T> {
+    fn index_mut(&mut self, pt: Point) -> &mut T {
+        let (width, height) = (self.width, self.height);
+        match self.get_mut(pt) {
+            Some(item) => item,
+            None => panic!("{:?} out of bounds of a {}x{} matrix", pt, width, height),
+        }
+    }
+}
+
+/// a rectangle of a matrix, addressed from its own top left corner
+pub struct View<'a, T> {
+    matr: &'a Matrix<T>,
+    pub origin: Point,
+    pub width: usize,
+    pub height: usize,
+}
+
+// not derived, which would want T: Copy
+impl<'a, T> Clone for View<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for View<'a, T> {}
+
+impl<'a, T: default::Default + Clone> View<'a, T> {
+    pub fn in_bounds(&self, pt: Point) -> bool {
+        pt.x >= 0 && pt.y >= 0
+            && pt.x < self.width as i32
+            && pt.y < self.height as i32
+    }
+
+    /// the value at a point relative to the view, or None outside it or off
+    /// the matrix
+    pub fn get(&self, pt: Point) -> Option<&'a T> {
+        if self.in_bounds(pt) {
+            self.matr.get(self.origin + pt)
+        } else {
+            None
+        }
+    }
+
+    /// the cells inside the matrix with their positions in the view, row by row
+    pub fn indexed_iter(&self) -> impl Iterator<Item = (Point, &'a T)> {
+        let view = *self;
+        (0..view.height as i32)
+            .flat_map(move |y| (0..view.width as i32).map(move |x| Point::new(x, y)))
+            .filter_map(move |pt| view.get(pt).map(|item| (pt, item)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> {
+        self.indexed_iter().map(|(_, item)| item)
+    }
+}
+
+pub struct MatrixIterator<'a, T> {
+    matr: &'a Matrix<T>,
+    idx: usize,
+}
+
+impl<'a, T: default::Default + Clone> Iterator for MatrixIterator<'a, T> {
+    type Item = (Point, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.matr.data.get(self.idx)?;
+        let pt = Point::new((self.idx % self.matr.width) as i32,
+                            (self.idx / self.matr.width) as i32);
+        self.idx += 1;
+        Some((pt, item))
+    }
+}
+
+//
+
+/// what a cell holds. the kind follows from the glyph alone: digits and
+/// lowercase letters are values, `*` is a bang and anything else runs as an
+/// operator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Content {
+    #[default]
+    Empty,
+    /// a value, 0-35
+    Data(u8),
+    /// `state` belongs to the operator: it's kept from frame to frame and
+    /// carried along when the operator moves
+    Op { glyph: char, state: u32 },
+    Bang,
+}
+
+impl Content {
+    /// '\0' and '.' are empty
+    pub fn from_glyph(ch: char) -> Self {
+        match ch {
+            '\0' | '.' => Content::Empty,
+            '*' => Content::Bang,
+            '0'..='9' | 'a'..='z' => Content::Data(codec::decode(ch).unwrap()),
+            _ => Content::Op { glyph: ch, state: 0 },
+        }
+    }
+
+    /// '\0' when empty
+    pub fn glyph(&self) -> char {
+        match *self {
+            Content::Empty => '\0',
+            // values past 35 have no glyph, they wrap around
+            Content::Data(value) => codec::encode(codec::wrap(value as i64)).unwrap(),
+            Content::Op { glyph, .. } => glyph,
+            Content::Bang => '*',
+        }
+    }
+}
+
+/// one cell of a field
+#[derive(Clone)]
+pub struct Slot {
+    pub content: Content,
+}
+
+/// what the engine needs from a cell. fields hold [`Slot`]s unless told
+/// otherwise; embedders with more to keep per cell, a color or a sample id
+/// say, put a `Slot` in a type of their own and pass these through to it.
+///
+/// the engine only ever moves contents from cell to cell. anything else a
+/// cell carries stays where it is when an operator moves off it.
+pub trait CellLike: default::Default + Clone {
+    fn content(&self) -> Content;
+
+    fn set_content(&mut self, content: Content);
+
+    /// the glyph in the cell, '\0' when empty
+    fn glyph(&self) -> char {
+        self.content().glyph()
+    }
+
+    /// replaces whatever was in the cell, operator state included
+    fn set_glyph(&mut self, ch: char) {
+        self.set_content(Content::from_glyph(ch));
+    }
+
+    /// the operator's state, None if the cell isn't an operator
+    fn state(&self) -> Option<u32> {
+        match self.content() {
+            Content::Op { state, .. } => Some(state),
+            _ => None,
+        }
+    }
+
+    /// does nothing if the cell isn't an operator
+    fn set_state(&mut self, state: u32) {
+        if let Content::Op { glyph, .. } = self.content() {
+            self.set_content(Content::Op { glyph, state });
+        }
+    }
+
+    fn is_clear(&self) -> bool {
+        self.content() == Content::Empty
+    }
+
+    /// replaces the cell with a bang
+    fn explode(&mut self) {
+        self.set_content(Content::Bang);
+    }
+
+    fn clear(&mut self) {
+        self.set_content(Content::Empty);
+    }
+}
+
+impl CellLike for Slot {
+    fn content(&self) -> Content {
+        self.content
+    }
+
+    fn set_content(&mut self, content: Content) {
+        self.content = content;
+    }
+}
+
+impl default::Default for Slot {
+    fn default() -> Self {
+        Self {
+            content: Content::Empty,
+        }
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = self.glyph();
+        write!(f, " {} ", if op == '\0' { '.' } else { op })
+    }
+}
+
+//
+
+// a bit for each cell of a field, sized along with it so that setting and
+// clearing bits as frames go by never allocates
+#[derive(Clone, Default)]
+struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    fn new(cells: usize) -> Self {
+        Self { words: vec![0; cells.div_ceil(64)], len: 0 }
+    }
+
+    fn insert(&mut self, i: usize) {
+        let (word, bit) = (&mut self.words[i / 64], 1 << (i % 64));
+        if *word & bit == 0 {
+            *word |= bit;
+            self.len += 1;
+        }
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn remove(&mut self, i: usize) {
+        let (word, bit) = (&mut self.words[i / 64], 1 << (i % 64));
+        if *word & bit != 0 {
+            *word &= !bit;
+            self.len -= 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.words.fill(0);
+        self.len = 0;
+    }
+
+    // how many bits are set
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    // the count kept in `len`, the slow way
+    fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    // the first set bit at or after `i`, skipping clear ones a word at a time
+    fn next_from(&self, i: usize) -> Option<usize> {
+        let mut w = i / 64;
+        let mut word = self.words.get(w)? & (!0 << (i % 64));
+        while word == 0 {
+            w += 1;
+            word = *self.words.get(w)?;
+        }
+        Some(w * 64 + word.trailing_zeros() as usize)
+    }
+}
+
+/// what `Field::stamp` does where the pattern lands on an occupied cell
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Collision {
+    Overwrite,
+    /// keeps what's on the field
+    Keep,
+    /// refuses to stamp anything
+    Refuse,
+}
+
+/// how a pattern goes down onto a field
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stamp {
+    /// empty pattern cells leave the field as it is
+    pub transparent: bool,
+    pub collision: Collision,
+}
+
+impl default::Default for Stamp {
+    /// every cell of the pattern, replacing the field
+    fn default() -> Self {
+        Self { transparent: false, collision: Collision::Overwrite }
+    }
+}
+
+/// which way `Field::flip_region` mirrors
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Axis {
+    /// left to right
+    Horizontal,
+    /// top to bottom
+    Vertical,
+}
+
+/// the grid operators live on
+///
+/// cells are [`Slot`]s unless the field is made with `Field::empty` for
+/// some other [`CellLike`] type.
+///
+/// the field keeps track of which cells have something in them, so frames
+/// on a sparse field needn't look at every cell. filling cells through the
+/// field's own methods keeps that up to date. after filling them straight
+/// through `slots` or their slots, call `reindex`, or the engine may not
+/// notice them.
+///
+/// it also keeps which cells are locked: ones that shouldn't run this
+/// frame, because they were just moved into or read as values by a
+/// neighbour. locks are all cleared at the start of every frame.
+#[derive(Clone)]
+pub struct Field<C: CellLike = Slot> {
+    pub slots: Matrix<C>,
+    // indices into `slots` of cells that may hold something. cells found
+    // empty are dropped again as frames go by.
+    occupied: BitSet,
+    locks: BitSet,
+}
+
+impl Field {
+    /// an empty field
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::empty(width, height)
+    }
+}
+
+impl<C: CellLike> Field<C> {
+    /// an empty field of any cell type, e.g. `Field::<MyCell>::empty(8, 8)`
+    pub fn empty(width: usize, height: usize) -> Self {
+        Self {
+            slots: Matrix::new(width, height),
+            occupied: BitSet::new(width * height),
+            locks: BitSet::new(width * height),
+        }
+    }
+
+    pub fn unlock_all(&mut self) {
+        self.locks.clear();
+    }
+
+    /// whether the cell at a point is locked, false off the field
+    pub fn is_locked(&self, pt: Point) -> bool {
+        self.point_in_bounds(pt) && self.locks.contains(self.index_of(pt))
+    }
+
+    /// locks the cell at a point for the rest of the frame. points off the
+    /// field are ignored.
+    pub fn lock(&mut self, pt: Point) {
+        if self.point_in_bounds(pt) {
+            let i = self.index_of(pt);
+            self.locks.insert(i);
+        }
+    }
+
+    /// the slot at a point, or None off the field
+    pub fn slot(&self, pt: Point) -> Option<&C> {
+        self.slots.get(pt)
+    }
+
+    /// the slot at a point to change in place. the field doesn't see what's
+    /// done to it, so after filling an empty cell this way call `reindex`,
+    /// or use `put`.
+    pub fn slot_mut(&mut self, pt: Point) -> Option<&mut C> {
+        self.slots.get_mut(pt)
+    }
+
+    /// the glyph at a point, '\0' when empty, None off the field
+    pub fn get(&self, pt: Point) -> Option<char> {
+        self.slot(pt).map(C::glyph)
+    }
+
+    /// writes a glyph, replacing whatever was there. '.' and '\0' clear the
+    /// cell. points off the field and control characters are refused.
+    pub fn set(&mut self, pt: Point, glyph: char) -> Result<(), LyzaError> {
+        if glyph.is_control() && glyph != '\0' {
+            return Err(LyzaError::Invalid(format!("{:?} can't be written to the field", glyph)));
+        }
+        self.put(pt, Content::from_glyph(glyph))
+    }
+
+    /// like `set`, with operator state and all
+    pub fn put(&mut self, pt: Point, content: Content) -> Result<(), LyzaError> {
+        self.slots.mut_idx(pt)?.set_content(content);
+        if content != Content::Empty {
+            self.occupy(pt);
+        }
+        Ok(())
+    }
+
+    fn occupy(&mut self, pt: Point) {
+        if self.point_in_bounds(pt) {
+            let i = self.index_of(pt);
+            self.occupied.insert(i);
+        }
+    }
+
+    fn index_of(&self, pt: Point) -> usize {
+        pt.y as usize * self.slots.width + pt.x as usize
+    }
+
+    // what `Context::check_invariants` checks of the field itself: the
+    // bitsets are sized for it and count right, and every cell with
+    // something in it is indexed. stale index entries are allowed.
+    fn check_invariants(&self, broken: &mut Vec<LyzaError>) {
+        let cells = self.slots.width * self.slots.height;
+        for (name, bits) in [("lock", &self.locks), ("occupied", &self.occupied)] {
+            if bits.words.len() != cells.div_ceil(64) {
+                broken.push(LyzaError::Invariant(format!("{} bits sized for {} cells, not {}",
+                                                         name, bits.words.len() * 64, cells)));
+            } else if bits.count() != bits.len() {
+                broken.push(LyzaError::Invariant(format!("{} bits count {} set, not {}",
+                                                         name, bits.len(), bits.count())));
+            }
+        }
+        let unindexed = self.slots.as_slice().iter()
+                            .enumerate()
+                            .find(|&(i, slot)| !slot.is_clear() && !self.occupied.contains(i));
+        if let Some((i, _)) = unindexed {
+            let pt = Point::new((i % self.slots.width) as i32, (i / self.slots.width) as i32);
+            broken.push(LyzaError::Invariant(format!("{} holds something but isn't indexed", pt)));
+        }
+    }
+
+    /// works out which cells have something in them afresh
+    pub fn reindex(&mut self) {
+        // a word of the index at a time, its 64 cells folded into it without
+        // branching, which the compiler can vectorise on any target
+        let mut len = 0;
+        for (word, cells) in self.occupied.words.iter_mut().zip(self.slots.as_slice().chunks(64)) {
+            *word = cells.iter()
+                         .enumerate()
+                         .fold(0, |word, (bit, slot)| word | (!slot.is_clear() as u64) << bit);
+            len += word.count_ones() as usize;
+        }
+        self.occupied.len = len;
+    }
+
+    /// empties `width`×`height` cells from `origin`, leaving locked ones be
+    /// if `keep_locked`. whatever hangs off the field is ignored.
+    pub fn clear_region(&mut self, origin: Point, (width, height): (usize, usize), keep_locked: bool) {
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let pt = origin.translate(x, y);
+                if keep_locked && self.is_locked(pt) {
+                    continue;
+                }
+                if let Some(slot) = self.slots.get_mut(pt) {
+                    slot.clear();
+                }
+            }
+        }
+    }
+
+    /// copies a grid of glyphs onto the field with its top left at `at`.
+    /// whatever hangs off the field is dropped.
+    pub fn stamp(&mut self, pattern: &Matrix<char>, at: Point, how: Stamp) -> Result<(), LyzaError> {
+        let cells: Vec<(Point, char)> = pattern.indexed_iter()
+                                               .filter(|&(_, &ch)| !(how.transparent && (ch == '\0' || ch == '.')))
+                                               .map(|(pt, &ch)| (at + pt, ch))
+                                               .filter(|&(pt, _)| self.point_in_bounds(pt))
+                                               .collect();
+        if how.collision == Collision::Refuse {
+            if let Some((pt, _)) = cells.iter().find(|&&(pt, _)| !self[pt].is_clear()) {
+                return Err(LyzaError::Invalid(format!("{} is already taken", pt)));
+            }
+        }
+        for (pt, ch) in cells {
+            if how.collision == Collision::Keep && !self[pt].is_clear() {
+                continue;
+            }
+            self.put(pt, Content::from_glyph(ch))?;
+        }
+        Ok(())
+    }
+
+    /// turns `width`×`height` cells from `origin` a quarter clockwise, leaving
+    /// a `height`×`width` region at the same origin. cells turned off the
+    /// field are lost.
+    pub fn rotate_region(&mut self, origin: Point, (width, height): (usize, usize)) {
+        self.rearrange(origin, (width, height), |pt| Point::new(height as i32 - 1 - pt.y, pt.x));
+    }
+
+    pub fn flip_region(&mut self, origin: Point, (width, height): (usize, usize), axis: Axis) {
+        self.rearrange(origin, (width, height), |pt| match axis {
+            Axis::Horizontal => Point::new(width as i32 - 1 - pt.x, pt.y),
+            Axis::Vertical => Point::new(pt.x, height as i32 - 1 - pt.y),
+        });
+    }
+
+    // moves every cell of a region to `to(pt)`, both relative to `origin`.
+    // operators keep their state, anything else in a cell stays put.
+    fn rearrange(&mut self, origin: Point, size: (usize, usize), to: impl Fn(Point) -> Point) {
+        let before = Matrix::from_fn(size.0, size.1, |pt| {
+            self.slot(origin + pt).map_or(Content::Empty, C::content)
+        });
+        self.clear_region(origin, size, false);
+        for (pt, &content) in before.indexed_iter() {
+            // whatever lands off the field is lost
+            let _ = self.put(origin + to(pt), content);
+        }
+    }
+
+    /// every cell whose glyph differs in `other`, with the glyph here and
+    /// there. cells beyond the edge of one field count as empty in it.
+    pub fn diff(&self, other: &Field<C>) -> Vec<(Point, char, char)> {
+        let width = self.slots.width.max(other.slots.width);
+        let height = self.slots.height.max(other.slots.height);
+        let mut ret = Vec::new();
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let pt = Point::new(x, y);
+                let old = self.get(pt).unwrap_or('\0');
+                let new = other.get(pt).unwrap_or('\0');
+                if old != new {
+                    ret.push((pt, old, new));
+                }
+            }
+        }
+        ret
+    }
+
+    pub fn clear_all(&mut self) {
+        for slot in self.slots.as_mut_slice() {
+            slot.clear();
+        }
+        self.occupied.clear();
+    }
+
+    /// the same as `field[pt]`
+    pub fn ref_slot(&self, pt: Point) -> Result<&C, LyzaError> {
+        self.slots.ref_idx(pt)
+    }
+
+    pub fn point_in_bounds(&self, pt: Point) -> bool {
+        self.slots.in_bounds(pt)
+    }
+
+    /// a copy at a new size, anchored top left. whatever doesn't fit is lost.
+    pub fn resized(&self, width: usize, height: usize) -> Field<C> {
+        let mut ret = Field {
+            slots: Matrix::from_fn(width, height, |pt| self.slot(pt).cloned().unwrap_or_default()),
+            occupied: BitSet::new(width * height),
+            locks: BitSet::new(width * height),
+        };
+        ret.reindex();
+        ret
+    }
+}
+
+/// like the matrix underneath, `field[pt]` panics off the field where
+/// `slot` returns None
+impl<C: CellLike> ops::Index<Point> for Field<C> {
+    type Output = C;
+
+    fn index(&self, pt: Point) -> &C {
+        &self.slots[pt]
+    }
+}
+
+impl<C: CellLike> ops::IndexMut<Point> for Field<C> {
+    fn index_mut(&mut self, pt: Point) -> &mut C {
+        &mut self.slots[pt]
+    }
+}
+
+/// fields are equal when they're the same size and every cell holds the
+/// same thing, operator state included. locks only matter mid-frame, so
+/// they're left out, here and in the hash, as is anything else a cell
+/// carries beyond its content.
+impl<C: CellLike> PartialEq for Field<C> {
+    fn eq(&self, other: &Field<C>) -> bool {
+        self.slots.width == other.slots.width
+            && self.slots.height == other.slots.height
+            && self.slots.as_slice().iter()
+                   .zip(other.slots.as_slice())
+                   .all(|(a, b)| a.content() == b.content())
+    }
+}
+
+impl<C: CellLike> Eq for Field<C> {}
+
+impl<C: CellLike> Hash for Field<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.slots.width.hash(state);
+        self.slots.height.hash(state);
+        for slot in self.slots.as_slice() {
+            slot.content().hash(state);
+        }
+    }
+}
+
+/// three characters a cell, `[x]` where it's locked
+impl<C: CellLike> fmt::Display for Field<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (pt, slot) in self.slots.indexed_iter() {
+            let op = slot.glyph();
+            let ch = if op == '\0' { '.' } else { op };
+            if self.is_locked(pt) {
+                write!(f, "[{}]", ch)?;
+            } else {
+                write!(f, " {} ", ch)?;
+            }
+            if pt.x as usize + 1 == self.slots.width {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//
+
+/// what an operator is for, as shown in the editor
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Category {
+    Movement,
+    Math,
+    Io,
+    Util,
+    Bang,
+    /// anything added from outside the crate that fits none of the others
+    Custom,
+}
+
+impl Category {
+    pub const ALL: [Category; 6] = [Category::Movement, Category::Math, Category::Io,
+                                    Category::Util, Category::Bang, Category::Custom];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Movement => "movement",
+            Category::Math => "math",
+            Category::Io => "io",
+            Category::Util => "util",
+            Category::Bang => "bang",
+            Category::Custom => "custom",
+        }
+    }
+}
+
+/// a cell an operator reads or writes, relative to the operator
+#[derive(Clone, Debug, PartialEq)]
+pub struct Port {
+    pub offset: Point,
+    pub name: String,
+    /// what the operator goes by when the cell is empty, if anything
+    pub default: Option<char>,
+    /// written by the operator rather than read
+    pub output: bool,
+}
+
+impl Port {
+    pub fn input(offset: impl Into<Point>, name: &str) -> Port {
+        Port { offset: offset.into(), name: name.to_string(), default: None, output: false }
+    }
+
+    pub fn output(offset: impl Into<Point>, name: &str) -> Port {
+        Port { output: true, ..Port::input(offset, name) }
+    }
+
+    pub fn or(self, default: char) -> Port {
+        Port { default: Some(default), ..self }
+    }
+}
+
+/// an operator: its glyph and what it does when it runs
+#[derive(Clone)]
+pub struct Opdef<C: CellLike = Slot> {
+    pub long_name: String,
+    pub operator: char,
+    pub category: Category,
+    /// the cells the operator reads and writes, in the order it reads them
+    pub ports: Vec<Port>,
+    /// one line on what the operator does, for help listings
+    pub doc: String,
+    /// free-form labels for finding and grouping operators, e.g. "network"
+    pub tags: Vec<String>,
+    /// other glyphs that run this operator, e.g. a unicode arrow for a mover.
+    /// lowercase letters and digits are data and can't be aliases.
+    pub aliases: Vec<char>,
+    /// runs the operator at `ctx.at`
+    pub callback: Callback<C>,
+}
+
+/// how an operator runs
+pub enum Callback<C: CellLike = Slot> {
+    /// the same function for every cell, keeping nothing between frames
+    Fn(fn(&mut OpCtx<C>) -> Result<(), LyzaError>),
+    /// makes an instance for each cell running the operator
+    Stateful(fn() -> Box<dyn Operator<C>>),
+}
+
+// derived, these would want the cell type to be Copy too
+impl<C: CellLike> Clone for Callback<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C: CellLike> Copy for Callback<C> {}
+
+/// an operator that remembers things between frames. every cell running it
+/// gets an instance of its own, made fresh when the operator turns up there
+/// and dropped once the cell holds something else, so a moving operator
+/// starts over wherever it lands.
+///
+/// ```
+/// use lyza::{define_op, encode_base64, Context, Direction, Field, LyzaError, OpCtx,
+///            Operator, OpdefTable, Point};
+///
+/// #[derive(Default)]
+/// struct Count(u8);
+///
+/// impl Operator for Count {
+///     fn tick(&mut self, ctx: &mut OpCtx) -> Result<(), LyzaError> {
+///         self.0 = (self.0 + 1) % 36;
+///         ctx.write(Direction::S, encode_base64(self.0)?);
+///         Ok(())
+///     }
+/// }
+///
+/// let mut table = OpdefTable::new();
+/// table.add(define_op!('C', count, Custom, "counts up every frame", state: Count)).unwrap();
+/// let mut ctx = Context::new(table, Field::new(1, 2));
+/// ctx.field.set(Point::new(0, 0), 'C').unwrap();
+/// ctx.tick();
+/// ctx.tick();
+/// assert_eq!(ctx.field.get(Point::new(0, 1)), Some('2'));
+/// ```
+///
+/// an error stops the operator for the frame, not the frame: it comes back
+/// as a warning naming the cell.
+pub trait Operator<C: CellLike = Slot> {
+    fn tick(&mut self, ctx: &mut OpCtx<C>) -> Result<(), LyzaError>;
+}
+
+/// what `define_op!` callbacks can end in: nothing, for operators that
+/// can't fail, or a result
+#[doc(hidden)]
+pub trait OpResult {
+    fn into_result(self) -> Result<(), LyzaError>;
+}
+
+impl OpResult for () {
+    fn into_result(self) -> Result<(), LyzaError> {
+        Ok(())
+    }
+}
+
+impl OpResult for Result<(), LyzaError> {
+    fn into_result(self) -> Result<(), LyzaError> {
+        self
+    }
+}
+
+impl<C: CellLike> Opdef<C> {
+    /// the port names separated by spaces, e.g. "topic value"
+    pub fn port_names(&self) -> String {
+        self.ports.iter().map(|port| port.name.as_str()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// the operators a context knows, by glyph. `default()` has the built in
+/// set; embedders can add their own or start from `new()`.
+///
+/// `add` defines a glyph outright. `replace` shadows whatever the glyph
+/// means for now and `remove` takes the newest definition away again,
+/// uncovering the one it shadowed, so overrides from plugins can come and go
+/// without losing the built in operators underneath.
+///
+/// a definition's aliases follow it: they resolve to its glyph for as long
+/// as it's the one in effect. everything that takes a glyph, `remove` and
+/// `restore` included, accepts an alias in place of the canonical glyph.
+///
+/// glyphs that read as data (`0-9a-z`), empty cells and control characters
+/// can't be operators, and `add` won't take a glyph or alias that's already
+/// in use. `replace` is how to knowingly take one over.
+pub struct OpdefTable<C: CellLike = Slot> {
+    defs: BTreeMap<char, Vec<Opdef<C>>>,
+    // alias -> canonical glyph
+    aliases: BTreeMap<char, char>,
+}
+
+impl OpdefTable {
+    pub fn new() -> OpdefTable {
+        OpdefTable::empty()
+    }
+}
+
+impl<C: CellLike> OpdefTable<C> {
+    /// a table with nothing in it for any cell type
+    pub fn empty() -> Self {
+        OpdefTable { defs: BTreeMap::new(), aliases: BTreeMap::new() }
+    }
+
+    /// defines a new operator, refusing glyphs that are taken
+    pub fn add(&mut self, opd: Opdef<C>) -> Result<(), LyzaError> {
+        self.check(&opd, false)?;
+        let ch = opd.operator;
+        self.defs.insert(ch, vec![opd]);
+        self.relink(ch);
+        Ok(())
+    }
+
+    /// overrides the glyph until the override is removed. its aliases still
+    /// can't belong to another operator.
+    pub fn replace(&mut self, opd: Opdef<C>) -> Result<(), LyzaError> {
+        self.check(&opd, true)?;
+        let ch = opd.operator;
+        self.defs.entry(ch).or_default().push(opd);
+        self.relink(ch);
+        Ok(())
+    }
+
+    // `shadowing` lets the definition's own glyph, and aliases already
+    // pointing at it, be taken over
+    fn check(&self, opd: &Opdef<C>, shadowing: bool) -> Result<(), LyzaError> {
+        for ch in iter::once(opd.operator).chain(opd.aliases.iter().copied()) {
+            let invalid = match Content::from_glyph(ch) {
+                Content::Empty => Some(format!("{:?} is an empty cell, not an operator", ch)),
+                Content::Data(_) => Some(format!("`{}` reads as data and can't be an operator", ch)),
+                _ if ch.is_control() => Some(format!("{:?} can't be an operator", ch)),
+                _ => None,
+            };
+            if let Some(msg) = invalid {
+                return Err(LyzaError::Invalid(msg));
+            }
+            let taken = match self.canonical(ch) {
+                Some(owner) => !(shadowing && owner == opd.operator),
+                None => false,
+            };
+            if taken {
+                let owner = self.find(ch).unwrap();
+                return Err(LyzaError::Invalid(format!("`{}` is already taken by {} (`{}`), can't define {}",
+                                                      ch, owner.long_name, owner.operator, opd.long_name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// takes away the newest definition of a glyph, returning it. the one it
+    /// shadowed, if any, is back in effect.
+    pub fn remove(&mut self, ch: char) -> Option<Opdef<C>> {
+        let ch = self.canonical(ch)?;
+        let defs = self.defs.get_mut(&ch)?;
+        let ret = defs.pop();
+        if defs.is_empty() {
+            self.defs.remove(&ch);
+        }
+        self.relink(ch);
+        ret
+    }
+
+    /// drops every override of a glyph, back to its first definition.
+    /// false if the glyph wasn't defined.
+    pub fn restore(&mut self, ch: char) -> bool {
+        let ch = match self.canonical(ch) {
+            Some(ch) => ch,
+            None => return false,
+        };
+        if let Some(defs) = self.defs.get_mut(&ch) {
+            defs.truncate(1);
+        }
+        self.relink(ch);
+        true
+    }
+
+    /// the glyph a definition is registered under, following aliases
+    pub fn canonical(&self, ch: char) -> Option<char> {
+        if self.defs.contains_key(&ch) {
+            Some(ch)
+        } else {
+            self.aliases.get(&ch).copied()
+        }
+    }
+
+    pub fn find(&self, ch: char) -> Option<&Opdef<C>> {
+        self.canonical(ch)
+            .and_then(|ch| self.defs.get(&ch))
+            .and_then(|defs| defs.last())
+    }
+
+    /// the definitions in effect, in glyph order
+    pub fn iter(&self) -> impl Iterator<Item = &Opdef<C>> {
+        self.defs.values().filter_map(|defs| defs.last())
+    }
+
+    /// how many glyphs are defined, not counting aliases
+    pub fn len(&self) -> usize {
+        self.defs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defs.is_empty()
+    }
+
+    /// every canonical glyph, sorted
+    pub fn glyphs(&self) -> Vec<char> {
+        self.defs.keys().copied().collect()
+    }
+
+    // points the aliases at whatever definition of `ch` is now in effect.
+    // a glyph defined in its own right always wins over an alias for it.
+    fn relink(&mut self, ch: char) {
+        self.aliases.retain(|_, canonical| *canonical != ch);
+        let aliases = match self.defs.get(&ch).and_then(|defs| defs.last()) {
+            Some(opd) => opd.aliases.clone(),
+            None => return,
+        };
+        for alias in aliases {
+            if alias != ch {
+                self.aliases.insert(alias, ch);
+            }
+        }
+    }
+}
+
+/// declares an [`Opdef`] in one expression: glyph, name, category and doc
+/// line, then any ports, tags and aliases, and last the callback, or
+/// `state: T` for an [`Operator`] made with `T::default()`. the callback's
+/// body can end in `()` or a `Result<(), LyzaError>`, and use `?`.
+///
+/// ```
+/// use lyza::{define_op, Direction, OpdefTable, Port};
+///
+/// let mut table = OpdefTable::new();
+/// table.add(define_op!('D', dup, Custom, "copies the cell west of it east",
+///     ports: [Port::input(Direction::W, "in"), Port::output(Direction::E, "out")],
+///     tags: ["copy"],
+///     |ctx| {
+///         if let Some(ch) = ctx.read_port(Direction::W) {
+///             ctx.write(Direction::E, ch);
+///         }
+///     })).unwrap();
+/// assert_eq!(table.find('D').unwrap().long_name, "dup");
+/// ```
+#[macro_export]
+macro_rules! define_op {
+    (@opdef $glyph:literal, $name:ident, $category:ident, $doc:literal,
+     [$($port:expr),*], [$($tag:literal),*], [$($alias:literal),*], $callback:expr) => {
+        $crate::Opdef {
+            long_name: $crate::__alloc::string::ToString::to_string(stringify!($name)),
+            operator: $glyph,
+            category: $crate::Category::$category,
+            ports: $crate::__alloc::vec![$($port),*],
+            doc: $crate::__alloc::string::ToString::to_string($doc),
+            tags: $crate::__alloc::vec![$($crate::__alloc::string::ToString::to_string($tag)),*],
+            aliases: $crate::__alloc::vec![$($alias),*],
+            callback: $callback,
+        }
+    };
+    ($glyph:literal, $name:ident, $category:ident, $doc:literal
+     $(, ports: [$($port:expr),* $(,)?])?
+     $(, tags: [$($tag:literal),* $(,)?])?
+     $(, aliases: [$($alias:literal),* $(,)?])?
+     , |$ctx:ident| $body:block $(,)?) => {
+        $crate::define_op!(@opdef $glyph, $name, $category, $doc,
+                           [$($($port),*)?], [$($($tag),*)?], [$($($alias),*)?],
+                           $crate::Callback::Fn(|$ctx: &mut $crate::OpCtx<_>| {
+                               $crate::OpResult::into_result($body)
+                           }))
+    };
+    ($glyph:literal, $name:ident, $category:ident, $doc:literal
+     $(, ports: [$($port:expr),* $(,)?])?
+     $(, tags: [$($tag:literal),* $(,)?])?
+     $(, aliases: [$($alias:literal),* $(,)?])?
+     , state: $op:ty $(,)?) => {
+        $crate::define_op!(@opdef $glyph, $name, $category, $doc,
+                           [$($($port),*)?], [$($($tag),*)?], [$($($alias),*)?],
+                           $crate::Callback::Stateful(|| $crate::__alloc::boxed::Box::new(<$op>::default())))
+    };
+}
+
+/// the built in operators, for fields of any cell type
+impl<C: CellLike> default::Default for OpdefTable<C> {
+    fn default() -> Self {
+        let builtins: [Opdef<C>; 12] = [
+            define_op!('*', bang, Bang, "bangs its neighbours for one frame", |ctx| {
+                ctx.field[ctx.at].clear();
+                ctx.field.lock(ctx.at);
+                ctx.emit_event(Event::Bang(ctx.at));
+            }),
+            define_op!('E', east, Movement, "moves east a cell every frame",
+                       tags: ["mover"], aliases: ['→'], |ctx| {
+                ctx.move_toward(Direction::E);
+            }),
+            define_op!('W', west, Movement, "moves west a cell every frame",
+                       tags: ["mover"], aliases: ['←'], |ctx| {
+                ctx.move_toward(Direction::W);
+            }),
+            define_op!('N', north, Movement, "moves north a cell every frame",
+                       tags: ["mover"], aliases: ['↑'], |ctx| {
+                ctx.move_toward(Direction::N);
+            }),
+            define_op!('S', south, Movement, "moves south a cell every frame",
+                       tags: ["mover"], aliases: ['↓'], |ctx| {
+                ctx.move_toward(Direction::S);
+            }),
+            define_op!('H', halt, Util, "stops the cell below it from running",
+                       ports: [Port::output(Direction::S, "halted")], |ctx| {
+                ctx.field.lock(ctx.at + Direction::S);
+            }),
+            define_op!('?', assert, Util, "fails unless the cell above it holds what's east of it",
+                       ports: [Port::input(Direction::N, "actual"), Port::input(Direction::E, "expected")],
+                       tags: ["test"], |ctx| {
+                let actual = ctx.read_port(Direction::N);
+                let expected = ctx.read_port(Direction::E);
+                if actual != expected {
+                    return Err(LyzaError::Assertion { expected, actual });
+                }
+                Ok(())
+            }),
+            define_op!('$', command, Util, "runs its text as a lyza command",
+                       ports: [Port::input(Direction::E, "text")],
+                       tags: ["command"], |ctx| {
+                // the text runs east up to the first empty cell
+                let mut text = String::new();
+                let mut offset = Direction::E.to_point();
+                while let Some(ch) = ctx.read_port(offset) {
+                    text.push(if ch == ':' { ' ' } else { ch });
+                    offset += Direction::E;
+                }
+                if ctx.is_banged() && !text.is_empty() {
+                    ctx.emit_event(Event::Command(text));
+                }
+            }),
+            define_op!('&', mqtt, Io, "publishes value to topic",
+                       ports: [Port::input(Direction::E, "topic").or('0'),
+                               Port::input(Direction::E * 2, "value")],
+                       tags: ["network"], |ctx| {
+                let topic = ctx.read_value(Direction::E).unwrap_or(0);
+                let value = ctx.read_value(Direction::E * 2);
+                if ctx.is_banged() {
+                    let payload = match value {
+                        Some(value) => value.to_string(),
+                        None => "bang".to_string(),
+                    };
+                    ctx.emit_event(Event::Mqtt { topic, payload });
+                }
+            }),
+            define_op!('^', dmx, Io, "sets dmx channel page*36+channel+1",
+                       ports: [Port::input(Direction::E, "universe").or('0'),
+                               Port::input(Direction::E * 2, "page").or('0'),
+                               Port::input(Direction::E * 3, "channel").or('0'),
+                               Port::input(Direction::E * 4, "value").or('0')],
+                       tags: ["network", "lighting"], |ctx| {
+                let universe = ctx.read_value(Direction::E).unwrap_or(0);
+                // a glyph only counts to 35, so the channel takes two to
+                // reach all 512
+                let page = ctx.read_value(Direction::E * 2).unwrap_or(0);
+                let channel = ctx.read_value(Direction::E * 3).unwrap_or(0);
+                let value = ctx.read_value(Direction::E * 4).unwrap_or(0);
+                if !ctx.is_banged() {
+                    return Ok(());
+                }
+                let channel = page as u16 * 36 + channel as u16 + 1;
+                if channel > 512 {
+                    return Err(LyzaError::Invalid(format!("dmx channel {} past 512", channel)));
+                }
+                // 0-z spans the full dmx range
+                let value = (value as u32 * 255 / 35) as u8;
+                ctx.emit_event(Event::Dmx { universe: universe as u16, channel, value });
+                Ok(())
+            }),
+            define_op!(':', midi, Io, "plays a midi note for length frames",
+                       ports: [Port::input(Direction::E, "channel").or('0'),
+                               Port::input(Direction::E * 2, "octave"),
+                               Port::input(Direction::E * 3, "note"),
+                               Port::input(Direction::E * 4, "velocity").or('z'),
+                               Port::input(Direction::E * 5, "length").or('1'),
+                               Port::input(Direction::E * 6, "device").or('0'),
+                               Port::input(Direction::E * 7, "humanize").or('0')],
+                       tags: ["midi"], |ctx| {
+                let channel = ctx.read_value(Direction::E).unwrap_or(0);
+                let octave = ctx.read_value(Direction::E * 2);
+                let note = ctx.read_port(Direction::E * 3);
+                let velocity = ctx.read_value(Direction::E * 4).unwrap_or(35);
+                let length = ctx.read_value(Direction::E * 5).unwrap_or(1);
+                let device = ctx.read_value(Direction::E * 6).unwrap_or(0);
+                let humanize = ctx.read_value(Direction::E * 7).unwrap_or(0);
+                let (octave, note) = match (octave, note) {
+                    (Some(octave), Some(note)) if ctx.is_banged() => (octave, note),
+                    _ => return Ok(()),
+                };
+                if channel > 15 {
+                    return Err(LyzaError::Invalid(format!("midi channel {} past 15", channel)));
+                }
+                // uppercase letters are naturals and lowercase ones sharps,
+                // with e and b being f and the next octave's c
+                let semitone = match note {
+                    'C' => 0, 'c' => 1, 'D' => 2, 'd' => 3, 'E' => 4, 'e' | 'F' => 5, 'f' => 6,
+                    'G' => 7, 'g' => 8, 'A' => 9, 'a' => 10, 'B' => 11, 'b' => 12,
+                    other => return Err(LyzaError::Invalid(format!("`{}` isn't a note", other))),
+                };
+                // octave 4 holds middle c
+                let note = 12 * (octave as u32 + 1) + semitone;
+                if note > 127 {
+                    return Err(LyzaError::Invalid(format!("midi note {} past 127", note)));
+                }
+                // 0 is silent
+                if velocity == 0 {
+                    return Ok(());
+                }
+                // humanize moves the velocity up or down by as many steps
+                // at most, keeping it above 0. nothing's drawn without it.
+                let mut velocity = velocity as i64;
+                if humanize > 0 {
+                    let spread = humanize as i64;
+                    velocity = (velocity + (ctx.rng() % (2 * spread as u64 + 1)) as i64 - spread).max(1);
+                }
+                // 0-z spans the velocities
+                let velocity = (velocity.min(35) * 127 / 35) as u8;
+                ctx.play_note(device, channel, note as u8, velocity, length as u32);
+                Ok(())
+            }),
+            define_op!('~', scsynth, Io, "starts or sets a supercollider synth",
+                       ports: [Port::input(Direction::E, "s|n"),
+                               Port::input(Direction::E * 2, "def|param").or('0'),
+                               Port::input(Direction::E * 3, "node").or('0'),
+                               Port::input(Direction::E * 4, "value")],
+                       tags: ["network", "audio"], |ctx| {
+                // `s` starts a synth from the def at idx, `n` sets the param at idx
+                let cmd = ctx.read_port(Direction::E);
+                let idx = ctx.read_value(Direction::E * 2).unwrap_or(0);
+                let node = ctx.read_value(Direction::E * 3).unwrap_or(0);
+                let value = ctx.read_value(Direction::E * 4);
+                if !ctx.is_banged() {
+                    return Ok(());
+                }
+                match cmd {
+                    Some('s') => ctx.emit_event(Event::ScNew { synthdef: idx, node, value }),
+                    Some('n') => ctx.emit_event(Event::ScSet {
+                        param: idx,
+                        node,
+                        value: value.unwrap_or(0),
+                    }),
+                    Some(other) => return Err(LyzaError::Invalid(format!("`{}` isn't s or n", other))),
+                    None => {}
+                }
+                Ok(())
+            }),
+        ];
+
+        let mut ret = OpdefTable::empty();
+        for opd in builtins {
+            ret.add(opd).expect("built in operators overlap");
+        }
+        ret
+    }
+}
+
+//
+
+/// things operators want to say to the outside world. callbacks emit these
+/// during a frame, they come back in its [`FrameResult`] and the frontend
+/// hands them to an [`EventBus`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// channels count from 0. `port` picks which of the frontend's midi
+    /// devices it goes to, 0 for the first or when there's just the one.
+    NoteOn { port: u8, channel: u8, note: u8, velocity: u8 },
+    NoteOff { port: u8, channel: u8, note: u8 },
+    Cc { port: u8, channel: u8, control: u8, value: u8 },
+    Osc { address: String, values: Vec<u8> },
+    Udp(String),
+    /// something for whoever is watching, e.g. in a status line
+    Log(String),
+    /// a bang went off at a cell
+    Bang(Point),
+    Mqtt { topic: u8, payload: String },
+    Dmx { universe: u16, channel: u16, value: u8 },
+    ScNew { synthdef: u8, node: u8, value: Option<u8> },
+    ScSet { param: u8, node: u8, value: u8 },
+    /// a command line from the `$` operator, for the frontend to run
+    Command(String),
+}
+
+/// somewhere events end up: a midi port, an osc socket, a recording. each
+/// sink picks out the events it deals with and ignores the rest.
+pub trait Sink {
+    fn send(&mut self, event: &Event) -> Result<(), LyzaError>;
+
+    /// called once everything from a frame has been sent, for sinks that
+    /// batch
+    fn flush(&mut self) -> Result<(), LyzaError> {
+        Ok(())
+    }
+}
+
+/// every sink a frontend has attached, fed a frame's events at a time
+#[derive(Default)]
+pub struct EventBus {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn attach(&mut self, sink: Box<dyn Sink>) {
+        self.sinks.push(sink);
+    }
+
+    /// sends every event to every sink, returning whatever failed. one
+    /// sink failing doesn't keep the event from the others.
+    pub fn deliver<'a>(&mut self, events: impl IntoIterator<Item = &'a Event>) -> Vec<LyzaError> {
+        let mut errors = Vec::new();
+        for event in events {
+            for sink in self.sinks.iter_mut() {
+                if let Err(e) = sink.send(event) {
+                    errors.push(e);
+                }
+            }
+        }
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.flush() {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+}
+
+//
+
+/// the language a field is written in: which operators there are, the
+/// order cells run in and what counts as a bang reaching an operator. the
+/// engine and the editor work the same whatever the rules, so variants,
+/// stricter or more experimental, only need a rule set of their own.
+pub trait RuleSet<C: CellLike = Slot> {
+    /// short and without spaces, for project files
+    fn name(&self) -> &str;
+
+    fn opdefs(&self) -> &OpdefTable<C>;
+
+    fn opdefs_mut(&mut self) -> &mut OpdefTable<C>;
+
+    /// the order cells run in within a frame
+    fn scan(&self) -> Scan {
+        Scan::Rows
+    }
+
+    /// whether the operator at `at` counts as banged. by default that's a
+    /// bang in one of the four cells next to it.
+    fn is_banged(&self, field: &Field<C>, at: Point) -> bool {
+        at.neighbors4().iter().any(|&pt| {
+            field.slot(pt).is_some_and(|slot| slot.content() == Content::Bang)
+        })
+    }
+}
+
+/// the order cells run in. an operator writing ahead of the scan can be
+/// read, or run, later in the same frame, so this matters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Scan {
+    /// left to right along each row, from the top
+    Rows,
+    /// top to bottom down each column, from the left
+    Columns,
+}
+
+impl Scan {
+    /// every point of a `width`×`height` field, in order
+    pub fn points(self, width: usize, height: usize) -> impl Iterator<Item = Point> {
+        (0..width * height).map(move |i| match self {
+            Scan::Rows => Point::new((i % width) as i32, (i / width) as i32),
+            Scan::Columns => Point::new((i / height) as i32, (i % height) as i32),
+        })
+    }
+}
+
+/// which operator writes a context reports as warnings. writes go the
+/// same way whatever it is: outside the field they're dropped, over a
+/// locked cell they land, so a patch runs the same while it's checked.
+///
+/// ```
+/// use lyza::{define_op, Context, Direction, Field, LyzaError, OpdefTable, Point, Strictness};
+///
+/// let mut table = OpdefTable::new();
+/// table.add(define_op!('D', dup, Custom, "copies the cell west of it east", |ctx| {
+///     if let Some(ch) = ctx.read_port(Direction::W) {
+///         ctx.write(Direction::E, ch);
+///     }
+/// })).unwrap();
+/// let mut ctx = Context::new(table, Field::new(2, 1));
+/// ctx.field.set(Point::new(0, 0), '5').unwrap();
+/// ctx.field.set(Point::new(1, 0), 'D').unwrap();
+/// ctx.strict = Strictness::Bounds;
+/// assert_eq!(ctx.step().warnings, [LyzaError::StrayWrite {
+///     glyph: 'D', at: Point::new(1, 0), to: Point::new(2, 0), locked: false,
+/// }]);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Strictness {
+    /// none of them
+    #[default]
+    Lenient,
+    /// writes outside the field
+    Bounds,
+    /// those and writes over cells already locked this frame, which
+    /// usually means two operators fighting over a cell
+    Locks,
+}
+
+/// lyza's own rules: the operators it's given, rows from the top and bangs
+/// reaching the four cells around them
+pub struct Native<C: CellLike = Slot> {
+    pub opdefs: OpdefTable<C>,
+}
+
+impl<C: CellLike> Native<C> {
+    pub const NAME: &'static str = "lyza";
+}
+
+/// with the built in operators
+impl<C: CellLike> default::Default for Native<C> {
+    fn default() -> Self {
+        Native { opdefs: OpdefTable::default() }
+    }
+}
+
+impl<C: CellLike> RuleSet<C> for Native<C> {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn opdefs(&self) -> &OpdefTable<C> {
+        &self.opdefs
+    }
+
+    fn opdefs_mut(&mut self) -> &mut OpdefTable<C> {
+        &mut self.opdefs
+    }
+}
+
+/// rules for patches written for orca: lyza's operators, plus `#`
+/// comments, which keep the rest of their row up to the next `#` from
+/// running. lyza's own operators already behave as orca's do; the
+/// conformance corpus shipped with the command line tool keeps track of
+/// what else differs.
+pub struct Orca<C: CellLike = Slot> {
+    pub opdefs: OpdefTable<C>,
+}
+
+impl<C: CellLike> Orca<C> {
+    pub const NAME: &'static str = "orca";
+}
+
+/// with the built in operators and `#`
+impl<C: CellLike> default::Default for Orca<C> {
+    fn default() -> Self {
+        let mut opdefs = OpdefTable::default();
+        let comment = define_op!('#', comment, Util, "keeps the row up to the next `#` from running", |ctx| {
+            let mut pt = ctx.at + Direction::E;
+            while let Some(glyph) = ctx.field.get(pt) {
+                ctx.field.lock(pt);
+                if glyph == '#' {
+                    break;
+                }
+                pt += Direction::E;
+            }
+        });
+        opdefs.add(comment).expect("`#` is a built in operator");
+        Orca { opdefs }
+    }
+}
+
+impl<C: CellLike> RuleSet<C> for Orca<C> {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn opdefs(&self) -> &OpdefTable<C> {
+        &self.opdefs
+    }
+
+    fn opdefs_mut(&mut self) -> &mut OpdefTable<C> {
+        &mut self.opdefs
+    }
+}
+
+/// everything one frame did, from [`Context::tick`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameResult {
+    /// which frame it was, counting from 0
+    pub frame: u32,
+    /// emitted by operators, in the order they ran
+    pub events: Vec<Event>,
+    /// cells whose contents differ from before the frame, row by row
+    pub changed: Vec<Point>,
+    /// what went wrong without stopping the frame, e.g. a cell holding a
+    /// glyph no operator is defined for
+    pub warnings: Vec<LyzaError>,
+    /// every operator run, in order, from a context with `trace` set
+    pub trace: Vec<OpTrace>,
+}
+
+/// one operator's run within a frame. only what went through its
+/// [`OpCtx`] is seen; a callback changing the field directly isn't.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpTrace {
+    pub glyph: char,
+    pub at: Point,
+    /// ports read, in order
+    pub read: Vec<Point>,
+    /// cells written, moved into or out of, in order
+    pub wrote: Vec<Point>,
+}
+
+/// what frames have cost so far, for finding out why a patch stutters.
+/// frame times are only measured with the `std` feature; how late frames
+/// started is up to the frontend, which knows when they were due.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub frames: u64,
+    /// operators run, over every frame
+    pub ops: u64,
+    /// the same by glyph, to find the ones a patch spends its time in
+    pub by_op: BTreeMap<char, u64>,
+    /// events emitted, over every frame
+    pub events: u64,
+    /// time spent processing frames, over every frame
+    pub busy: Duration,
+    /// the longest a single frame took
+    pub max_busy: Duration,
+    /// how late frames started, over every frame reported with `late`
+    pub jitter: Duration,
+    pub max_jitter: Duration,
+    late_frames: u64,
+}
+
+impl Stats {
+    /// records that a frame started `by` after it was due
+    pub fn late(&mut self, by: Duration) {
+        self.jitter += by;
+        self.max_jitter = self.max_jitter.max(by);
+        self.late_frames += 1;
+    }
+
+    pub fn mean_busy(&self) -> Duration {
+        mean(self.busy, self.frames)
+    }
+
+    pub fn mean_jitter(&self) -> Duration {
+        mean(self.jitter, self.late_frames)
+    }
+
+    /// starts counting afresh
+    pub fn reset(&mut self) {
+        *self = Stats::default();
+    }
+}
+
+fn mean(total: Duration, n: u64) -> Duration {
+    if n == 0 {
+        Duration::ZERO
+    } else {
+        Duration::from_secs_f64(total.as_secs_f64() / n as f64)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_frame = |n: u64| if self.frames == 0 { 0.0 } else { n as f64 / self.frames as f64 };
+        write!(f, "{} frames, {:.1} ops and {:.1} events a frame, {:?} a frame (max {:?}), \
+                   jitter {:?} (max {:?})",
+               self.frames, per_frame(self.ops), per_frame(self.events),
+               self.mean_busy(), self.max_busy, self.mean_jitter(), self.max_jitter)
+    }
+}
+
+/// a running field: the grid, its operators and how far it's got
+pub struct Context<C: CellLike = Slot> {
+    pub rules: Box<dyn RuleSet<C>>,
+    pub field: Field<C>,
+    /// frames processed so far
+    pub frame_ct: u32,
+    pub stats: Stats,
+    /// which stray writes come back as warnings
+    pub strict: Strictness,
+    /// checks the engine's own bookkeeping after every frame, reporting
+    /// anything off as [`LyzaError::Invariant`] warnings. slow, and meant
+    /// for working on the engine and its operators rather than patches.
+    pub check_invariants: bool,
+    /// records what every operator read and wrote in
+    /// [`FrameResult::trace`], for working out what a patch is doing
+    pub trace: bool,
+    rng: u64,
+    // stateful operators by cell, with the glyph they were made for
+    instances: BTreeMap<Point, (char, Box<dyn Operator<C>>)>,
+    // the frame's scratch space, emptied at the start of every frame but
+    // never freed, so once it's grown to fit a patch frames stop allocating
+    arena: FrameResult,
+    before: Vec<Content>,
+    // notes from `:` still playing
+    held: Vec<HeldNote>,
+}
+
+// a note that's playing, stopped once `frames` more frames have begun
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct HeldNote {
+    port: u8,
+    channel: u8,
+    note: u8,
+    frames: u32,
+}
+
+impl<C: CellLike> Context<C> {
+    /// lyza's own rules with the given operators
+    pub fn new(opdefs: OpdefTable<C>, field: Field<C>) -> Self
+        where C: 'static
+    {
+        Self::with_rules(Box::new(Native { opdefs }), field)
+    }
+
+    pub fn with_rules(rules: Box<dyn RuleSet<C>>, field: Field<C>) -> Self {
+        Context {
+            rules,
+            field,
+            frame_ct: 0,
+            stats: Stats::default(),
+            strict: Strictness::default(),
+            check_invariants: false,
+            trace: false,
+            rng: 0x2545_f491_4f6c_dd1d,
+            instances: BTreeMap::new(),
+            arena: FrameResult::default(),
+            before: Vec::new(),
+            held: Vec::new(),
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        // xorshift never leaves zero
+        self.rng = seed.max(1);
+    }
+
+    pub fn random(&mut self) -> u64 {
+        xorshift(&mut self.rng)
+    }
+
+    /// runs every unlocked operator once, in the order the rules scan in.
+    /// cells holding a glyph no operator is defined for are left alone and
+    /// come back as warnings.
+    ///
+    /// a sparse field scanned by rows only has its occupied cells visited,
+    /// cells filled ahead of the scan during the frame included.
+    pub fn tick(&mut self) -> FrameResult {
+        self.step().clone()
+    }
+
+    /// what the latest `step` or `tick` did, empty before the first
+    pub fn last_frame(&self) -> &FrameResult {
+        &self.arena
+    }
+
+    /// like `tick`, but the result stays with the context and its space is
+    /// reused by the next frame, so a patch that has settled runs without
+    /// allocating. what's needed past the next frame has to be copied out.
+    pub fn step(&mut self) -> &FrameResult {
+        #[cfg(feature = "std")]
+        let started = std::time::Instant::now();
+        self.field.unlock_all();
+        self.before.clear();
+        self.before.extend(self.field.slots.as_slice().iter().map(C::content));
+        let ret = &mut self.arena;
+        ret.frame = self.frame_ct;
+        ret.events.clear();
+        ret.changed.clear();
+        ret.warnings.clear();
+        ret.trace.clear();
+        // notes that have played their length stop before anything runs,
+        // so one played again straight away sounds afresh
+        for held in &mut self.held {
+            held.frames -= 1;
+            if held.frames == 0 {
+                ret.events.push(Event::NoteOff { port: held.port, channel: held.channel, note: held.note });
+            }
+        }
+        self.held.retain(|held| held.frames > 0);
+        let rules = &*self.rules;
+        let field = &mut self.field;
+        let (width, height) = (field.slots.width, field.slots.height);
+
+        let sparse = rules.scan() == Scan::Rows && field.occupied.len() * SPARSE_RATIO < width * height;
+        let mut next = 0;
+        let mut every = rules.scan().points(width, height);
+        let mut seen = 0;
+
+        loop {
+            let pt = if sparse {
+                match field.occupied.next_from(next) {
+                    Some(i) => {
+                        next = i + 1;
+                        Point::new((i % width) as i32, (i / width) as i32)
+                    }
+                    None => break,
+                }
+            } else {
+                match every.next() {
+                    Some(pt) => pt,
+                    None => break,
+                }
+            };
+            let slot = &field[pt];
+            if slot.is_clear() {
+                if sparse {
+                    field.occupied.remove(next - 1);
+                }
+                continue;
+            }
+            seen += 1;
+            if field.is_locked(pt) {
+                continue;
+            }
+            let op = match slot.content() {
+                Content::Empty | Content::Data(_) => continue,
+                Content::Op { glyph, .. } => glyph,
+                Content::Bang => '*',
+            };
+            let opd = match rules.opdefs().find(op) {
+                Some(opd) => opd,
+                None => {
+                    ret.warnings.push(LyzaError::UnknownOperator { glyph: op, at: pt });
+                    continue;
+                }
+            };
+            let trace = match self.trace {
+                true => {
+                    ret.trace.push(OpTrace { glyph: op, at: pt, read: Vec::new(), wrote: Vec::new() });
+                    ret.trace.last_mut()
+                }
+                false => None,
+            };
+            let mut op_ctx = OpCtx {
+                at: pt,
+                field: &mut *field,
+                frame: self.frame_ct,
+                events: &mut ret.events,
+                warnings: &mut ret.warnings,
+                strict: self.strict,
+                glyph: op,
+                trace,
+                rng: &mut self.rng,
+                held: &mut self.held,
+                rules,
+            };
+            self.stats.ops += 1;
+            *self.stats.by_op.entry(op).or_insert(0) += 1;
+            let result = match opd.callback {
+                Callback::Fn(callback) => callback(&mut op_ctx),
+                Callback::Stateful(spawn) => {
+                    let instance = self.instances.entry(pt).or_insert_with(|| (op, spawn()));
+                    if instance.0 != op {
+                        *instance = (op, spawn());
+                    }
+                    instance.1.tick(&mut op_ctx)
+                }
+            };
+            if let Err(e) = result {
+                ret.warnings.push(LyzaError::OperatorFailed { glyph: op, at: pt, error: Box::new(e) });
+            }
+        }
+
+        // cells emptied straight through their slots never leave the index
+        // on a full scan, so it's rebuilt once it's mostly stale
+        if !sparse && field.occupied.len() > seen * 2 {
+            field.reindex();
+        }
+
+        // whatever was overwritten or moved away starts over next time
+        let field = &self.field;
+        self.instances.retain(|&pt, (op, _)| field.get(pt) == Some(*op));
+
+        ret.changed.extend(field.slots.as_slice().iter()
+                                .zip(&self.before)
+                                .enumerate()
+                                .filter(|(_, (slot, content))| slot.content() != **content)
+                                .map(|(i, _)| Point::new((i % width) as i32, (i / width) as i32)));
+
+        if self.check_invariants {
+            field.check_invariants(&mut ret.warnings);
+            // a bang clears itself when it runs, so one still in place
+            // afterwards must have been locked, i.e. written again
+            let stale = field.slots.as_slice().iter()
+                             .zip(&self.before)
+                             .enumerate()
+                             .find(|&(i, (slot, &then))| {
+                                 then == Content::Bang && slot.content() == Content::Bang && !field.locks.contains(i)
+                             });
+            if let Some((i, _)) = stale {
+                let pt = Point::new((i % width) as i32, (i / width) as i32);
+                ret.warnings.push(LyzaError::Invariant(format!("the bang at {} outlived its frame", pt)));
+            }
+        }
+
+        self.frame_ct += 1;
+        self.stats.frames += 1;
+        self.stats.events += ret.events.len() as u64;
+        #[cfg(feature = "std")]
+        {
+            let took = started.elapsed();
+            self.stats.busy += took;
+            self.stats.max_busy = self.stats.max_busy.max(took);
+        }
+        ret
+    }
+}
+
+// below one occupied cell in this many, frames only visit the occupied ones
+// rather than scanning the whole field
+const SPARSE_RATIO: usize = 4;
+
+/// xorshift64*, plenty for picking values on a grid. the state must start
+/// out nonzero, and never becomes zero from then on.
+pub fn xorshift(state: &mut u64) -> u64 {
+    let mut x = *state;
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    *state = x;
+    x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+/// what an operator's callback works with while it runs: the field around
+/// it, the context's random numbers and somewhere to send its events.
+/// offsets are relative to the operator.
+pub struct OpCtx<'a, C: CellLike = Slot> {
+    /// the cell being run
+    pub at: Point,
+    pub field: &'a mut Field<C>,
+    /// the frame being processed, counting from 0
+    pub frame: u32,
+    events: &'a mut Vec<Event>,
+    warnings: &'a mut Vec<LyzaError>,
+    strict: Strictness,
+    glyph: char,
+    trace: Option<&'a mut OpTrace>,
+    rng: &'a mut u64,
+    held: &'a mut Vec<HeldNote>,
+    rules: &'a dyn RuleSet<C>,
+}
+
+impl<C: CellLike> OpCtx<'_, C> {
+    /// the glyph at an offset, locking the cell so it's treated as a value
+    /// rather than run as an operator this frame
+    pub fn read_port(&mut self, offset: impl Into<Point>) -> Option<char> {
+        let pt = self.at + offset.into();
+        let glyph = self.field.get(pt)?;
+        self.field.lock(pt);
+        if let Some(trace) = &mut self.trace {
+            trace.read.push(pt);
+        }
+        if glyph == '\0' { None } else { Some(glyph) }
+    }
+
+    /// `read_port` as a value, 0-35 with letters in either case. None for an
+    /// empty cell or a glyph that isn't a digit or a letter.
+    pub fn read_value(&mut self, offset: impl Into<Point>) -> Option<u8> {
+        self.read_port(offset).and_then(codec::decode)
+    }
+
+    /// puts a glyph at an offset, locked so it doesn't run until the next
+    /// frame. false if that's outside the field.
+    pub fn write(&mut self, offset: impl Into<Point>, glyph: char) -> bool {
+        let pt = self.at + offset.into();
+        let inside = self.field.point_in_bounds(pt);
+        let stray = match inside {
+            false => self.strict >= Strictness::Bounds,
+            true => self.strict >= Strictness::Locks && self.field.is_locked(pt),
+        };
+        if stray {
+            self.warnings.push(LyzaError::StrayWrite { glyph: self.glyph, at: self.at, to: pt, locked: inside });
+        }
+        if self.field.set(pt, glyph).is_err() {
+            return false;
+        }
+        self.field.lock(pt);
+        if let Some(trace) = &mut self.trace {
+            trace.wrote.push(pt);
+        }
+        true
+    }
+
+    /// puts a bang at an offset for the next frame
+    pub fn bang(&mut self, offset: impl Into<Point>) -> bool {
+        self.write(offset, '*')
+    }
+
+    /// whether the rules say the operator is banged, usually by a bang
+    /// next to it
+    pub fn is_banged(&self) -> bool {
+        self.rules.is_banged(self.field, self.at)
+    }
+
+    /// moves the operator a cell, or turns it into a bang if the way is
+    /// blocked
+    pub fn move_toward(&mut self, heading: Direction) {
+        let next = self.at + heading;
+
+        if self.field.slot(next).is_some_and(C::is_clear) {
+            let content = self.field[self.at].content();
+            let _ = self.field.put(next, content);
+            self.field.lock(next);
+            self.field[self.at].clear();
+            if let Some(trace) = &mut self.trace {
+                trace.wrote.push(next);
+            }
+        } else {
+            self.field[self.at].explode();
+        }
+        self.field.lock(self.at);
+        if let Some(trace) = &mut self.trace {
+            trace.wrote.push(self.at);
+        }
+    }
+
+    /// queues an event for the frontend to deliver once the frame is done
+    pub fn emit_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// sends a note on now and its note off once `frames` more frames have
+    /// begun, at least one. the same note already playing is stopped
+    /// first, so it sounds again rather than running on.
+    pub fn play_note(&mut self, port: u8, channel: u8, note: u8, velocity: u8, frames: u32) {
+        if let Some(i) = self.held.iter().position(|h| (h.port, h.channel, h.note) == (port, channel, note)) {
+            self.held.swap_remove(i);
+            self.events.push(Event::NoteOff { port, channel, note });
+        }
+        self.events.push(Event::NoteOn { port, channel, note, velocity });
+        self.held.push(HeldNote { port, channel, note, frames: frames.max(1) });
+    }
+
+    /// the context's next random number
+    pub fn rng(&mut self) -> u64 {
+        xorshift(self.rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reindex_finds_every_occupied_cell() {
+        // 3 rows of 43, so words straddle rows and the last is partial
+        let mut field = Field::new(43, 3);
+        let filled = [0, 1, 63, 64, 65, 127, 128];
+        for &i in &filled {
+            field.slots.as_mut_slice()[i].set_glyph('E');
+        }
+        field.reindex();
+        assert_eq!(field.occupied.len(), filled.len());
+        assert_eq!(field.occupied.count(), filled.len());
+        for i in 0..43 * 3 {
+            assert_eq!(field.occupied.contains(i), filled.contains(&i), "cell {}", i);
+        }
+
+        field.slots.as_mut_slice()[64].clear();
+        field.reindex();
+        assert!(!field.occupied.contains(64));
+        assert_eq!(field.occupied.len(), filled.len() - 1);
+    }
+
+    fn pattern(src: &str) -> Matrix<char> {
+        testing::parse(src).unwrap().slots.map(CellLike::glyph)
+    }
+
+    fn stamped(field: &str, pattern_src: &str, at: Point, how: Stamp) -> Result<String, LyzaError> {
+        let mut field = testing::parse(field).unwrap();
+        field.stamp(&pattern(pattern_src), at, how)?;
+        Ok(testing::text(&field))
+    }
+
+    const FULL: &str = "
+        xxx
+        xxx
+        xxx
+    ";
+
+    #[test]
+    fn stamps_replace_by_default() {
+        assert_eq!(stamped(FULL, "A.\n.B", Point::new(1, 1), Stamp::default()).unwrap(), "xxx\nxA.\nx.B\n");
+    }
+
+    #[test]
+    fn transparent_stamps_skip_empty_cells() {
+        let how = Stamp { transparent: true, ..Stamp::default() };
+        assert_eq!(stamped(FULL, "A.\n.B", Point::new(1, 1), how).unwrap(), "xxx\nxAx\nxxB\n");
+    }
+
+    #[test]
+    fn kept_cells_stay_put() {
+        let how = Stamp { transparent: false, collision: Collision::Keep };
+        assert_eq!(stamped("x..\n...", "AB\nCD", Point::new(0, 0), how).unwrap(), "xB.\nCD.\n");
+    }
+
+    #[test]
+    fn refusing_stamps_change_nothing() {
+        let how = Stamp { transparent: false, collision: Collision::Refuse };
+        let mut field = testing::parse("..x\n...").unwrap();
+        let err = field.stamp(&pattern("AB\nCD"), Point::new(1, 0), how).unwrap_err();
+        assert_eq!(err, LyzaError::Invalid("2,0 is already taken".to_string()));
+        assert_eq!(testing::text(&field), "..x\n...\n");
+
+        // only cells that would land count, so empty ones and ones off the
+        // field don't collide
+        let how = Stamp { transparent: true, collision: Collision::Refuse };
+        assert_eq!(stamped("..x\n...", "A.\nCD", Point::new(1, 0), how).unwrap(), ".Ax\n.CD\n");
+        assert_eq!(stamped("..x\n...", "AB", Point::new(2, 1), how).unwrap(), "..x\n..A\n");
+    }
+
+    #[test]
+    fn stamps_off_the_field_are_clipped() {
+        let how = Stamp::default();
+        assert_eq!(stamped(FULL, "AB\nCD", Point::new(2, 2), how).unwrap(), "xxx\nxxx\nxxA\n");
+        assert_eq!(stamped(FULL, "AB\nCD", Point::new(-1, -1), how).unwrap(), "Dxx\nxxx\nxxx\n");
+        assert_eq!(stamped(FULL, "AB", Point::new(5, 5), how).unwrap(), "xxx\nxxx\nxxx\n");
+    }
+
+    #[test]
+    fn rotating_four_times_changes_nothing() {
+        let mut field = testing::parse("
+            AB..
+            CD..
+            EF..
+        ").unwrap();
+        field.slots[Point::new(1, 1)].set_state(7);
+        let before = field.clone();
+
+        // a quarter turn clockwise, leaving 3 wide by 2 high
+        field.rotate_region(Point::new(0, 0), (2, 3));
+        assert_eq!(testing::text(&field), "ECA.\nFDB.\n....\n");
+        assert_eq!(field[Point::new(1, 1)].state(), Some(7));
+        field.rotate_region(Point::new(0, 0), (3, 2));
+        field.rotate_region(Point::new(0, 0), (2, 3));
+        field.rotate_region(Point::new(0, 0), (3, 2));
+        assert!(field == before);
+    }
+
+    #[test]
+    fn regions_turned_off_the_field_are_clipped() {
+        let mut field = testing::parse("
+            ....
+            .ABC
+        ").unwrap();
+        field.rotate_region(Point::new(1, 1), (3, 1));
+        assert_eq!(testing::text(&field), "....\n.A..\n");
+
+        // a region reaching past the edge reads empty there
+        let mut field = testing::parse("
+            ..AB
+            ..CD
+        ").unwrap();
+        field.rotate_region(Point::new(2, 0), (3, 2));
+        assert_eq!(testing::text(&field), "..CA\n..DB\n");
+    }
+
+    #[test]
+    fn flipping_twice_changes_nothing() {
+        let mut field = testing::parse("
+            AB.
+            C..
+        ").unwrap();
+        let before = field.clone();
+        field.flip_region(Point::new(0, 0), (3, 2), Axis::Horizontal);
+        assert_eq!(testing::text(&field), ".BA\n..C\n");
+        field.flip_region(Point::new(0, 0), (3, 2), Axis::Horizontal);
+        assert!(field == before);
+        field.flip_region(Point::new(0, 0), (2, 2), Axis::Vertical);
+        assert_eq!(testing::text(&field), "C..\nAB.\n");
+        field.flip_region(Point::new(0, 0), (2, 2), Axis::Vertical);
+        assert!(field == before);
+    }
+
+    #[test]
+    fn diffs_list_changed_glyphs_in_row_order() {
+        let old = testing::parse("A.B\n.C.").unwrap();
+        let new = testing::parse("A.D\nE..").unwrap();
+        assert_eq!(old.diff(&old), []);
+        assert_eq!(old.diff(&new), [(Point::new(2, 0), 'B', 'D'), (Point::new(0, 1), '\0', 'E'),
+                                    (Point::new(1, 1), 'C', '\0')]);
+    }
+
+    #[test]
+    fn diffs_see_past_the_smaller_field_as_empty() {
+        let small = testing::parse("AB").unwrap();
+        let big = testing::parse("A.\n.C").unwrap();
+        assert_eq!(small.diff(&big), [(Point::new(1, 0), 'B', '\0'), (Point::new(1, 1), '\0', 'C')]);
+        assert_eq!(big.diff(&small), [(Point::new(1, 0), '\0', 'B'), (Point::new(1, 1), 'C', '\0')]);
+    }
+
+    #[test]
+    fn diffs_only_see_glyphs() {
+        let old = testing::parse("AB").unwrap();
+        let mut new = old.clone();
+        new.slots[Point::new(0, 0)].set_state(3);
+        new.lock(Point::new(1, 0));
+        assert_eq!(old.diff(&new), []);
+    }
+
+    #[cfg(feature = "std")]
+    fn hash_of(field: &Field) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        field.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn locks_leave_equality_and_hashes_alone() {
+        let field = testing::parse("AB\n.1").unwrap();
+        let mut locked = field.clone();
+        locked.lock(Point::new(0, 0));
+        locked.lock(Point::new(1, 1));
+        assert!(locked == field);
+        assert_eq!(hash_of(&locked), hash_of(&field));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn operator_state_counts_for_equality_and_hashes() {
+        let field = testing::parse("AB").unwrap();
+        let mut changed = field.clone();
+        changed.slots[Point::new(0, 0)].set_state(1);
+        assert!(changed != field);
+        assert_ne!(hash_of(&changed), hash_of(&field));
+        changed.slots[Point::new(0, 0)].set_state(0);
+        assert!(changed == field);
+        assert_eq!(hash_of(&changed), hash_of(&field));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn sizes_count_for_equality_and_hashes() {
+        // the same cells, laid out in different rows
+        let wide = testing::parse("AB").unwrap();
+        let tall = testing::parse("A\nB").unwrap();
+        assert!(wide != tall);
+        assert_ne!(hash_of(&wide), hash_of(&tall));
+        assert!(Field::new(2, 1) != Field::new(3, 1));
+    }
+
+    fn op(glyph: char, name: &str) -> Opdef {
+        let mut opd = define_op!('X', x, Custom, "does nothing", |_ctx| {});
+        opd.operator = glyph;
+        opd.long_name = name.to_string();
+        opd
+    }
+
+    fn name_of(table: &OpdefTable, ch: char) -> Option<&str> {
+        table.find(ch).map(|opd| opd.long_name.as_str())
+    }
+
+    #[test]
+    fn replacements_stack_and_come_off_in_turn() {
+        let mut table = OpdefTable::new();
+        table.add(op('X', "first")).unwrap();
+        table.replace(op('X', "second")).unwrap();
+        table.replace(op('X', "third")).unwrap();
+        assert_eq!(name_of(&table, 'X'), Some("third"));
+        assert_eq!((table.len(), table.iter().count()), (1, 1));
+
+        assert_eq!(table.remove('X').unwrap().long_name, "third");
+        assert_eq!(name_of(&table, 'X'), Some("second"));
+        assert_eq!(table.remove('X').unwrap().long_name, "second");
+        assert_eq!(name_of(&table, 'X'), Some("first"));
+        assert_eq!(table.remove('X').unwrap().long_name, "first");
+        assert_eq!(name_of(&table, 'X'), None);
+        assert!(table.is_empty());
+        assert!(table.remove('X').is_none());
+    }
+
+    #[test]
+    fn restoring_drops_every_replacement() {
+        let mut table = OpdefTable::default();
+        table.replace(op('E', "sideways")).unwrap();
+        table.replace(op('E', "backwards")).unwrap();
+        assert!(table.restore('E'));
+        assert_eq!(name_of(&table, 'E'), Some("east"));
+        assert!(!table.restore('X'));
+    }
+
+    #[test]
+    fn replacing_a_missing_glyph_defines_it() {
+        let mut table = OpdefTable::new();
+        table.replace(op('X', "new")).unwrap();
+        assert_eq!(name_of(&table, 'X'), Some("new"));
+        assert!(table.add(op('X', "again")).is_err());
+    }
+
+    fn aliased(glyph: char, name: &str, aliases: &[char]) -> Opdef {
+        let mut opd = op(glyph, name);
+        opd.aliases = aliases.to_vec();
+        opd
+    }
+
+    #[test]
+    fn aliases_find_their_operator() {
+        let mut table = OpdefTable::new();
+        table.add(aliased('X', "ex", &['×', '✕'])).unwrap();
+        assert_eq!(table.canonical('×'), Some('X'));
+        assert_eq!(name_of(&table, '✕'), Some("ex"));
+        // only the canonical glyph is listed
+        assert_eq!(table.glyphs(), ['X']);
+        assert_eq!((table.len(), table.iter().count()), (1, 1));
+        assert_eq!(table.canonical('Y'), None);
+    }
+
+    #[test]
+    fn aliases_follow_replacements() {
+        let mut table = OpdefTable::new();
+        table.add(aliased('X', "ex", &['×'])).unwrap();
+        table.replace(aliased('X', "cross", &['✕'])).unwrap();
+        assert_eq!(name_of(&table, '✕'), Some("cross"));
+        assert_eq!(name_of(&table, '×'), None);
+
+        assert_eq!(table.remove('✕').unwrap().long_name, "cross");
+        assert_eq!(name_of(&table, '×'), Some("ex"));
+        assert_eq!(name_of(&table, '✕'), None);
+    }
+
+    #[test]
+    fn aliases_run_on_the_field() {
+        testing::assert_frames("→..\n↓..\n...", &["
+            .→.
+            ...
+            ↓..
+        "]);
+    }
+
+    #[test]
+    fn data_and_empty_glyphs_are_refused() {
+        let mut table = OpdefTable::new();
+        for ch in ['0', '9', 'a', 'z', '.', '\0', '\n'] {
+            assert!(matches!(table.add(op(ch, "bad")), Err(LyzaError::Invalid(_))), "{:?}", ch);
+            assert!(table.replace(op(ch, "bad")).is_err(), "{:?}", ch);
+            assert!(table.add(aliased('X', "ok", &[ch])).is_err(), "{:?}", ch);
+        }
+        assert!(table.is_empty());
+        assert_eq!(table.add(op('a', "bad")).unwrap_err(),
+                   LyzaError::Invalid("`a` reads as data and can't be an operator".to_string()));
+    }
+
+    #[test]
+    fn taken_glyphs_are_refused() {
+        let mut table = OpdefTable::default();
+        assert_eq!(table.add(op('E', "other")).unwrap_err(),
+                   LyzaError::Invalid("`E` is already taken by east (`E`), can't define other".to_string()));
+        // aliases on either side
+        assert!(table.add(op('→', "other")).is_err());
+        assert!(table.add(aliased('X', "other", &['W'])).is_err());
+        assert!(table.add(aliased('X', "other", &['←'])).is_err());
+        assert!(table.replace(aliased('E', "other", &['←'])).is_err());
+        assert_eq!(name_of(&table, 'E'), Some("east"));
+        assert_eq!(table.find('X').map(|opd| opd.operator), None);
+    }
+
+    fn midi(frame: &FrameResult) -> Vec<Event> {
+        frame.events.iter().filter(|e| matches!(e, Event::NoteOn { .. } | Event::NoteOff { .. })).cloned().collect()
+    }
+
+    #[test]
+    fn notes_stop_after_their_length() {
+        // the mover is blocked by the `:` and bangs it as a `*`
+        let mut ctx = Context::new(OpdefTable::default(), testing::parse("E:14Cz31").unwrap());
+        let on = Event::NoteOn { port: 1, channel: 1, note: 60, velocity: 127 };
+        let off = Event::NoteOff { port: 1, channel: 1, note: 60 };
+        assert_eq!(midi(ctx.step()), [on]);
+        assert_eq!(midi(ctx.step()), []);
+        assert_eq!(midi(ctx.step()), []);
+        assert_eq!(midi(ctx.step()), [off]);
+    }
+
+    #[test]
+    fn notes_played_again_restart() {
+        let mut ctx = Context::new(OpdefTable::default(), testing::parse("E:04ag9").unwrap());
+        let on = Event::NoteOn { port: 0, channel: 0, note: 70, velocity: 58 };
+        let off = Event::NoteOff { port: 0, channel: 0, note: 70 };
+        assert_eq!(midi(ctx.step()), vec![on.clone()]);
+        ctx.field.set(Point::new(0, 0), 'E').unwrap();
+        assert_eq!(midi(ctx.step()), [off, on]);
+    }
+
+    #[test]
+    fn notes_can_be_humanized() {
+        // velocity i is 18, so 15 to 21 with 3 either way, 54 to 76 sent
+        let mut seen = alloc::collections::BTreeSet::new();
+        for seed in 1..50 {
+            let mut ctx = Context::new(OpdefTable::default(), testing::parse("E:04Ci1.3").unwrap());
+            ctx.reseed(seed);
+            match midi(ctx.step())[..] {
+                [Event::NoteOn { velocity, .. }] if (54..=76).contains(&velocity) => seen.insert(velocity),
+                ref other => panic!("{:?}", other),
+            };
+        }
+        assert!(seen.len() > 1);
+    }
+
+    #[test]
+    fn io_values_read_the_same_in_either_case() {
+        let events = |src| Context::new(OpdefTable::default(), testing::parse(src).unwrap()).step().events.clone();
+        for (lower, upper) in [("E:a4Ci", "E:A4CI"), ("E^01a5", "E^01A5"), ("E&bk", "E&BK")] {
+            assert_eq!(events(lower), events(upper), "{}", upper);
+        }
+        assert!(events("E:a4Ci").contains(&Event::NoteOn { port: 0, channel: 10, note: 60, velocity: 65 }));
+        assert!(events("E^01a5").contains(&Event::Dmx { universe: 0, channel: 47, value: 36 }));
+        assert!(events("E&bk").contains(&Event::Mqtt { topic: 11, payload: "20".to_string() }));
+    }
+
+    #[test]
+    fn notes_stop_without_their_operator() {
+        let mut ctx = Context::new(OpdefTable::default(), testing::parse("E:04C.2").unwrap());
+        assert_eq!(midi(ctx.step()).len(), 1);
+        ctx.field.set(Point::new(1, 0), '\0').unwrap();
+        assert_eq!(midi(ctx.step()), []);
+        assert_eq!(midi(ctx.step()), [Event::NoteOff { port: 0, channel: 0, note: 60 }]);
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt::Write;
+
+use crate::viewport::Viewport;
+use lyza::{CellLike, Field, Point};
+
+// a scaled down picture of the whole field: every minimap cell counts the
+// occupied field cells in its block. counts are kept up to date by comparing
+// against last frame's occupancy, so a frame only touches cells that changed.
+
+const MAX_COLS: usize = 24;
+const MAX_ROWS: usize = 8;
+
+pub struct Minimap {
+    pub cols: usize,
+    pub rows: usize,
+    block_w: usize,
+    block_h: usize,
+    counts: Vec<u32>,
+    occupied: Vec<bool>,
+    field_w: usize,
+}
+
+impl Minimap {
+    pub fn new(field: &Field) -> Self {
+        let (w, h) = (field.slots.width, field.slots.height);
+        let block_w = w.div_ceil(MAX_COLS).max(1);
+        let block_h = h.div_ceil(MAX_ROWS).max(1);
+        let cols = w.div_ceil(block_w);
+        let rows = h.div_ceil(block_h);
+
+        let mut ret = Self {
+            cols,
+            rows,
+            block_w,
+            block_h,
+            counts: vec![0; cols * rows],
+            occupied: vec![false; w * h],
+            field_w: w,
+        };
+        ret.update(field);
+        ret
+    }
+
+    pub fn update(&mut self, field: &Field) {
+        for (pt, slot) in field.slots.indexed_iter() {
+            let i = pt.y as usize * self.field_w + pt.x as usize;
+            let now = !slot.is_clear();
+            if now == self.occupied[i] {
+                continue;
+            }
+            self.occupied[i] = now;
+            let block = self.block_of(pt);
+            if now {
+                self.counts[block] += 1;
+            } else {
+                self.counts[block] -= 1;
+            }
+        }
+    }
+
+    // false once the field has been resized out from under the map
+    pub fn covers(&self, field: &Field) -> bool {
+        self.field_w == field.slots.width && self.occupied.len() == field.slots.width * field.slots.height
+    }
+
+    fn block_of(&self, pt: Point) -> usize {
+        (pt.y as usize / self.block_h) * self.cols + pt.x as usize / self.block_w
+    }
+
+    // draws the map with its top left corner at a 1-based screen position,
+    // the blocks under the viewport highlighted
+    pub fn render(&self, buf: &mut String, row: usize, col: usize, view: &Viewport) {
+        let (span_w, span_h) = view.span();
+        let per_block = (self.block_w * self.block_h) as u32;
+
+        for y in 0..self.rows {
+            let _ = write!(buf, "\x1b[{};{}H", row + y, col);
+            for x in 0..self.cols {
+                let count = self.counts[y * self.cols + x];
+                let ch = match count {
+                    0 => ' ',
+                    _ if count * 4 < per_block => '.',
+                    _ if count * 2 < per_block => ':',
+                    _ => '#',
+                };
+                let top_left = Point::new((x * self.block_w) as i32, (y * self.block_h) as i32);
+                let seen = top_left.x + self.block_w as i32 > view.origin.x
+                    && top_left.y + self.block_h as i32 > view.origin.y
+                    && top_left.x < view.origin.x + span_w
+                    && top_left.y < view.origin.y + span_h;
+                if seen {
+                    let _ = write!(buf, "\x1b[0;30;47m{}", ch);
+                } else {
+                    let _ = write!(buf, "\x1b[0;37;40m{}", ch);
+                }
+            }
+            buf.push_str("\x1b[0m");
+        }
+    }
+}
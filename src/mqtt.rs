@@ -0,0 +1,89 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+// just enough mqtt 3.1.1 to publish at qos 0: CONNECT, CONNACK and PUBLISH.
+// keepalive is disabled so we never owe the broker a PINGREQ.
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+
+pub struct MqttClient {
+    addr: String,
+    client_id: String,
+    stream: TcpStream,
+}
+
+impl MqttClient {
+    pub fn connect(addr: &str, client_id: &str) -> io::Result<Self> {
+        let stream = handshake(addr, client_id)?;
+        Ok(Self {
+            addr: addr.to_string(),
+            client_id: client_id.to_string(),
+            stream,
+        })
+    }
+
+    // a dropped connection gets one reconnect before giving up on the message
+    pub fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+        let packet = publish_packet(topic, payload);
+        if self.stream.write_all(&packet).is_ok() {
+            return Ok(());
+        }
+        self.stream = handshake(&self.addr, &self.client_id)?;
+        self.stream.write_all(&packet)
+    }
+}
+
+fn handshake(addr: &str, client_id: &str) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_nodelay(true)?;
+
+    let mut body = Vec::new();
+    push_str(&mut body, "MQTT");
+    body.push(4);          // protocol level 3.1.1
+    body.push(0x02);       // clean session
+    body.extend_from_slice(&[0, 0]);
+    push_str(&mut body, client_id);
+    stream.write_all(&packet(CONNECT, &body))?;
+
+    let mut ack = [0; 4];
+    stream.read_exact(&mut ack)?;
+    if ack[0] != CONNACK || ack[3] != 0 {
+        return Err(io::Error::new(io::ErrorKind::ConnectionRefused,
+                                  format!("broker refused connection (code {})", ack[3])));
+    }
+    Ok(stream)
+}
+
+fn publish_packet(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    push_str(&mut body, topic);
+    body.extend_from_slice(payload);
+    packet(PUBLISH, &body)
+}
+
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut ret = vec![kind];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        ret.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    ret.extend_from_slice(body);
+    ret
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
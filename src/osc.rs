@@ -0,0 +1,93 @@
+use std::convert::TryInto;
+use std::io;
+use std::net::UdpSocket;
+
+// osc 1.0 message encoding, int32/float32/string arguments only
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    Str(String),
+}
+
+pub fn encode(address: &str, args: &[OscArg]) -> Vec<u8> {
+    let mut ret = Vec::new();
+    push_padded(&mut ret, address.as_bytes());
+
+    let mut tags = String::from(",");
+    for arg in args {
+        tags.push(match arg {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::Str(_) => 's',
+        });
+    }
+    push_padded(&mut ret, tags.as_bytes());
+
+    for arg in args {
+        match arg {
+            OscArg::Int(i) => ret.extend_from_slice(&i.to_be_bytes()),
+            OscArg::Float(f) => ret.extend_from_slice(&f.to_be_bytes()),
+            OscArg::Str(s) => push_padded(&mut ret, s.as_bytes()),
+        }
+    }
+    ret
+}
+
+// strings are nul terminated and padded out to a multiple of four bytes
+fn push_padded(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(bytes);
+    let pad = 4 - bytes.len() % 4;
+    buf.extend(std::iter::repeat_n(0, pad));
+}
+
+// the inverse of encode. unknown type tags make the whole message invalid.
+pub fn decode(bytes: &[u8]) -> Option<(String, Vec<OscArg>)> {
+    let mut at = 0;
+    let address = read_padded(bytes, &mut at)?;
+    let tags = read_padded(bytes, &mut at)?;
+    let tags = tags.strip_prefix(',')?;
+
+    let mut args = Vec::new();
+    for tag in tags.chars() {
+        let arg = match tag {
+            'i' => OscArg::Int(i32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?)),
+            'f' => OscArg::Float(f32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?)),
+            's' => {
+                args.push(OscArg::Str(read_padded(bytes, &mut at)?));
+                continue;
+            }
+            _ => return None,
+        };
+        at += 4;
+        args.push(arg);
+    }
+    Some((address, args))
+}
+
+fn read_padded(bytes: &[u8], at: &mut usize) -> Option<String> {
+    let rest = bytes.get(*at..)?;
+    let len = rest.iter().position(|&b| b == 0)?;
+    let s = std::str::from_utf8(&rest[..len]).ok()?.to_string();
+    *at += len + 4 - len % 4;
+    Some(s)
+}
+
+pub struct OscSender {
+    socket: UdpSocket,
+    dest: String,
+}
+
+impl OscSender {
+    pub fn open(dest: &str) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind("0.0.0.0:0")?,
+            dest: dest.to_string(),
+        })
+    }
+
+    pub fn send(&self, address: &str, args: &[OscArg]) -> io::Result<()> {
+        self.socket.send_to(&encode(address, args), &self.dest).map(|_| ())
+    }
+}
@@ -0,0 +1,501 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::UdpSocket;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::artnet::ArtNet;
+use crate::config::Config;
+use crate::mqtt::MqttClient;
+use crate::osc::{OscArg, OscSender};
+use crate::tuning::Tuning;
+use lyza::{Event, EventBus, LyzaError, Sink};
+
+// supercollider nodes created from the grid are numbered from here up,
+// clear of the ids sclang hands out
+const SC_NODE_BASE: i32 = 1000;
+
+// further behind than this and the output thread says so
+const LAG_WARNING: Duration = Duration::from_millis(50);
+
+// a frame's events on their way to the output thread, stamped with when the
+// frame ran. each comes with how much later it's to go out if it's midi,
+// for a groove or humanizing.
+struct Batch {
+    at: Instant,
+    events: Vec<(Duration, Event)>,
+}
+
+// the sinks, run on a thread of their own so a slow socket or device never
+// holds up a frame. batches go over in frame order, and their emptied vecs
+// come back to be filled again, along with any delivery errors.
+pub struct Output {
+    batches: Option<Sender<Batch>>,
+    recycled: Receiver<Vec<(Duration, Event)>>,
+    errors: Receiver<String>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Output {
+    // connects to everything the config names on the new thread, and only
+    // returns once that's done, with the first connection that failed
+    pub fn start(config: &Config) -> Result<Self, String> {
+        let (batch_tx, batch_rx) = mpsc::channel::<Batch>();
+        let (recycle_tx, recycled) = mpsc::channel();
+        let (error_tx, errors) = mpsc::channel();
+        let (ready_tx, ready) = mpsc::channel();
+        let config = config.clone();
+
+        let worker = thread::spawn(move || {
+            let opened = match config.dry_run {
+                true => Ok(dry_run(error_tx.clone())),
+                false => open(&config),
+            };
+            let mut bus = match opened {
+                Ok(bus) => bus,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = ready_tx.send(Ok(()));
+            let mut lagging = false;
+            // midi held back, soonest first, and what's due
+            let mut held: VecDeque<(Instant, Event)> = VecDeque::new();
+            let mut due = Vec::new();
+            loop {
+                let next = match held.front() {
+                    Some(&(at, _)) => batch_rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+                    None => batch_rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                let now = Instant::now();
+                while held.front().is_some_and(|&(at, _)| at <= now) {
+                    due.extend(held.pop_front().map(|(_, event)| event));
+                }
+                let mut batch = match next {
+                    Ok(batch) => batch,
+                    Err(RecvTimeoutError::Timeout) => {
+                        for e in bus.deliver(&due) {
+                            let _ = error_tx.send(e.to_string());
+                        }
+                        due.clear();
+                        continue;
+                    }
+                    // what's held still goes out, if early
+                    Err(RecvTimeoutError::Disconnected) => {
+                        due.extend(held.drain(..).map(|(_, event)| event));
+                        for e in bus.deliver(&due) {
+                            let _ = error_tx.send(e.to_string());
+                        }
+                        break;
+                    }
+                };
+
+                let behind = batch.at.elapsed();
+                if behind > LAG_WARNING && !lagging {
+                    let _ = error_tx.send(format!("output is running {}ms behind", behind.as_millis()));
+                }
+                lagging = behind > LAG_WARNING;
+                for (delay, event) in batch.events.drain(..) {
+                    if is_midi(&event) && !delay.is_zero() {
+                        let at = batch.at + delay;
+                        let i = held.partition_point(|&(then, _)| then <= at);
+                        held.insert(i, (at, event));
+                    } else {
+                        due.push(event);
+                    }
+                }
+                for e in bus.deliver(&due) {
+                    let _ = error_tx.send(e.to_string());
+                }
+                due.clear();
+                let _ = recycle_tx.send(batch.events);
+            }
+        });
+
+        match ready.recv() {
+            Ok(Ok(())) => Ok(Self {
+                batches: Some(batch_tx),
+                recycled,
+                errors,
+                worker: Some(worker),
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("output thread stopped".to_string()),
+        }
+    }
+
+    // hands a frame's events over, in a vec the thread gave back earlier
+    // when there is one. nothing is sent for a silent frame. midi goes out
+    // as much later than the rest as it says.
+    pub fn send(&self, events: impl IntoIterator<Item = (Duration, Event)>) {
+        let mut events = events.into_iter().peekable();
+        if events.peek().is_none() {
+            return;
+        }
+        let mut batch = self.recycled.try_recv().unwrap_or_default();
+        batch.extend(events);
+        if let Some(batches) = &self.batches {
+            let _ = batches.send(Batch { at: Instant::now(), events: batch });
+        }
+    }
+
+    // what went wrong delivering since last asked
+    pub fn errors(&self) -> impl Iterator<Item = String> + '_ {
+        self.errors.try_iter()
+    }
+}
+
+// whatever is still queued goes out before the program moves on
+impl Drop for Output {
+    fn drop(&mut self) {
+        self.batches = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn is_midi(event: &Event) -> bool {
+    matches!(event, Event::NoteOn { .. } | Event::NoteOff { .. } | Event::Cc { .. })
+}
+
+// a bus with a sink for every destination the config names
+pub fn open(config: &Config) -> Result<EventBus, String> {
+    let mut bus = EventBus::new();
+
+    if let Some(addr) = &config.mqtt_broker {
+        let client = MqttClient::connect(addr, &config.mqtt_client_id)
+                         .map_err(|e| format!("mqtt {}: {}", addr, e))?;
+        bus.attach(Box::new(MqttSink { client, topics: config.mqtt_topics.clone() }));
+    }
+    if let Some(dest) = &config.artnet_dest {
+        let artnet = ArtNet::open(dest).map_err(|e| format!("art-net {}: {}", dest, e))?;
+        bus.attach(Box::new(ArtNetSink(artnet)));
+    }
+    if let Some(dest) = &config.scsynth_dest {
+        let sc = OscSender::open(dest).map_err(|e| format!("scsynth {}: {}", dest, e))?;
+        bus.attach(Box::new(ScsynthSink {
+            sc,
+            synthdefs: config.scsynth_synthdefs.clone(),
+            params: config.scsynth_params.clone(),
+        }));
+    }
+    for dest in &config.osc_destinations {
+        let osc = OscSender::open(dest).map_err(|e| format!("osc {}: {}", dest, e))?;
+        bus.attach(Box::new(OscSink(osc)));
+    }
+    if let Some(dest) = &config.udp_dest {
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("udp {}: {}", dest, e))?;
+        bus.attach(Box::new(UdpSink { socket, dest: dest.clone() }));
+    }
+    let tuning = config.midi_tuning.as_deref().map(Tuning::load).transpose()?;
+    let devices: Vec<(&str, &str)> = config.midi_devices.iter().map(|d| device_name(d)).collect();
+    if let Some(route) = config.midi_routes.iter().find(|r| !devices.iter().any(|(name, _)| name == r)) {
+        return Err(format!("midi route to `{}`, which no --midi-device is named", route));
+    }
+    for &(name, path) in &devices {
+        let mut sink = MidiSink::open(path)?;
+        if !config.midi_routes.is_empty() {
+            let ports = config.midi_routes.iter().enumerate().filter(|(_, r)| *r == name);
+            sink.ports = Some(ports.map(|(port, _)| port as u8).collect());
+        }
+        if let Some(tuning) = &tuning {
+            sink.start_mpe(tuning.clone()).map_err(|e| e.to_string())?;
+        }
+        bus.attach(Box::new(sink));
+    }
+
+    Ok(bus)
+}
+
+// a bus that connects to nothing and reports what it would have sent,
+// alongside delivery errors
+pub fn dry_run(log: Sender<String>) -> EventBus {
+    let mut bus = EventBus::new();
+    bus.attach(Box::new(DryRunSink(log)));
+    bus
+}
+
+struct DryRunSink(Sender<String>);
+
+impl Sink for DryRunSink {
+    fn send(&mut self, event: &Event) -> Result<(), LyzaError> {
+        // bangs are for whoever's watching the field, not sent anywhere
+        if !matches!(event, Event::Bang(_)) {
+            let _ = self.0.send(format!("dry run: {:?}", event));
+        }
+        Ok(())
+    }
+}
+
+struct MqttSink {
+    client: MqttClient,
+    topics: Vec<String>,
+}
+
+impl Sink for MqttSink {
+    fn send(&mut self, event: &Event) -> Result<(), LyzaError> {
+        if let Event::Mqtt { topic, payload } = event {
+            let topic = self.topics
+                            .get(*topic as usize)
+                            .cloned()
+                            .unwrap_or_else(|| format!("lyza/{}", topic));
+            self.client.publish(&topic, payload.as_bytes())
+                       .map_err(|e| LyzaError::Io(format!("mqtt publish to {}: {}", topic, e)))?;
+        }
+        Ok(())
+    }
+}
+
+struct ArtNetSink(ArtNet);
+
+impl Sink for ArtNetSink {
+    fn send(&mut self, event: &Event) -> Result<(), LyzaError> {
+        if let Event::Dmx { universe, channel, value } = *event {
+            self.0.set(universe, channel, value);
+        }
+        Ok(())
+    }
+
+    // changed universes go out once a frame
+    fn flush(&mut self) -> Result<(), LyzaError> {
+        self.0.flush().map_err(|e| LyzaError::Io(format!("art-net: {}", e)))
+    }
+}
+
+struct ScsynthSink {
+    sc: OscSender,
+    synthdefs: Vec<String>,
+    params: Vec<String>,
+}
+
+impl Sink for ScsynthSink {
+    fn send(&mut self, event: &Event) -> Result<(), LyzaError> {
+        let sent = match *event {
+            Event::ScNew { synthdef, node, value } => {
+                let name = lookup(&self.synthdefs, synthdef, "synthdef")?;
+                // add to the head of the default group
+                let mut args = vec![OscArg::Str(name.to_string()),
+                                    OscArg::Int(SC_NODE_BASE + node as i32),
+                                    OscArg::Int(0),
+                                    OscArg::Int(1)];
+                if let (Some(value), Some(param)) = (value, self.params.first()) {
+                    args.push(OscArg::Str(param.clone()));
+                    args.push(OscArg::Float(value as f32));
+                }
+                self.sc.send("/s_new", &args)
+            }
+            Event::ScSet { param, node, value } => {
+                let name = lookup(&self.params, param, "param")?;
+                self.sc.send("/n_set", &[OscArg::Int(SC_NODE_BASE + node as i32),
+                                         OscArg::Str(name.to_string()),
+                                         OscArg::Float(value as f32)])
+            }
+            _ => return Ok(()),
+        };
+        sent.map_err(|e| LyzaError::Io(format!("scsynth: {}", e)))
+    }
+}
+
+fn lookup<'a>(names: &'a [String], idx: u8, what: &str) -> Result<&'a str, LyzaError> {
+    names.get(idx as usize)
+         .map(String::as_str)
+         .ok_or_else(|| LyzaError::Invalid(format!("scsynth: no {} configured at index {}", what, idx)))
+}
+
+struct OscSink(OscSender);
+
+impl Sink for OscSink {
+    fn send(&mut self, event: &Event) -> Result<(), LyzaError> {
+        if let Event::Osc { address, values } = event {
+            let args: Vec<OscArg> = values.iter().map(|&v| OscArg::Int(v as i32)).collect();
+            self.0.send(address, &args).map_err(|e| LyzaError::Io(format!("osc: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+struct UdpSink {
+    socket: UdpSocket,
+    dest: String,
+}
+
+impl Sink for UdpSink {
+    fn send(&mut self, event: &Event) -> Result<(), LyzaError> {
+        if let Event::Udp(payload) = event {
+            self.socket.send_to(payload.as_bytes(), &self.dest)
+                       .map_err(|e| LyzaError::Io(format!("udp {}: {}", self.dest, e)))?;
+        }
+        Ok(())
+    }
+}
+
+// raw midi bytes written straight to a device node, e.g. /dev/snd/midiC1D0
+// or /dev/midi1. no running status, every message goes out whole.
+// `<name>=<path>`, or a bare path that's its own name
+fn device_name(device: &str) -> (&str, &str) {
+    match device.split_once('=') {
+        Some((name, path)) if !name.contains('/') => (name, path),
+        _ => (device, device),
+    }
+}
+
+struct MidiSink {
+    name: String,
+    device: File,
+    // the note ports routed here, None for all of them. ports without a
+    // route go nowhere.
+    ports: Option<Vec<u8>>,
+    mpe: Option<Mpe>,
+}
+
+// with a tuning, notes go out mpe style: each on a channel of its own,
+// bent to its pitch, with channel 1 left for everything the notes share.
+// the channel an event names is ignored then.
+struct Mpe {
+    tuning: Tuning,
+    // what's playing on channels 2 to 16, as the note asked for with its
+    // channel and the 12-tet note sent
+    playing: [Option<(u8, u8, u8)>; 15],
+    // where to look for a free channel first, so a released note's tail
+    // isn't bent by the next one straight away
+    next: usize,
+}
+
+impl MidiSink {
+    fn open(name: &str) -> Result<Self, String> {
+        let device = OpenOptions::new().write(true)
+                                       .open(name)
+                                       .map_err(|e| LyzaError::Midi(format!("{}: {}", name, e)))?;
+        Ok(Self { name: name.to_string(), device, ports: None, mpe: None })
+    }
+
+    // tells the synth about the zone, channel 1 with 15 channels for notes
+    fn start_mpe(&mut self, tuning: Tuning) -> Result<(), LyzaError> {
+        self.write(&[0xb0, 101, 0, 0xb0, 100, 6, 0xb0, 6, 15])?;
+        self.mpe = Some(Mpe { tuning, playing: [None; 15], next: 0 });
+        Ok(())
+    }
+
+    fn write(&mut self, msg: &[u8]) -> Result<(), LyzaError> {
+        self.device.write_all(msg).map_err(|e| LyzaError::Midi(format!("{}: {}", self.name, e)))
+    }
+}
+
+impl Mpe {
+    // a free channel, or the longest playing one when all are taken
+    fn note_on(&mut self, channel: u8, note: u8, velocity: u8) -> Vec<u8> {
+        let slot = (0..15).map(|i| (self.next + i) % 15)
+                          .find(|&i| self.playing[i].is_none())
+                          .unwrap_or(self.next);
+        self.next = (slot + 1) % 15;
+        let member = slot as u8 + 1;
+        let mut ret = Vec::with_capacity(9);
+        if let Some((_, _, sent)) = self.playing[slot] {
+            ret.extend_from_slice(&[0x80 | member, sent, 0]);
+        }
+        let (sent, bend) = self.tuning.pitch(note & 0x7f);
+        self.playing[slot] = Some((channel, note, sent));
+        ret.extend_from_slice(&[0xe0 | member, (bend & 0x7f) as u8, (bend >> 7) as u8,
+                                0x90 | member, sent, velocity & 0x7f]);
+        ret
+    }
+
+    fn note_off(&mut self, channel: u8, note: u8) -> Option<[u8; 3]> {
+        let slot = self.playing.iter().position(|p| matches!(*p, Some((c, n, _)) if (c, n) == (channel, note)))?;
+        let (_, _, sent) = self.playing[slot].take()?;
+        Some([0x80 | (slot as u8 + 1), sent, 0])
+    }
+}
+
+impl Sink for MidiSink {
+    fn send(&mut self, event: &Event) -> Result<(), LyzaError> {
+        let port = match *event {
+            Event::NoteOn { port, .. } | Event::NoteOff { port, .. } | Event::Cc { port, .. } => port,
+            _ => return Ok(()),
+        };
+        if self.ports.as_ref().is_some_and(|ports| !ports.contains(&port)) {
+            return Ok(());
+        }
+        if let Some(mpe) = &mut self.mpe {
+            let msg = match *event {
+                Event::NoteOn { channel, note, velocity, .. } => mpe.note_on(channel, note, velocity),
+                Event::NoteOff { channel, note, .. } => match mpe.note_off(channel, note) {
+                    Some(msg) => msg.to_vec(),
+                    None => return Ok(()),
+                },
+                Event::Cc { control, value, .. } => vec![0xb0, control & 0x7f, value & 0x7f],
+                _ => return Ok(()),
+            };
+            return self.write(&msg);
+        }
+        let msg = match *event {
+            Event::NoteOn { channel, note, velocity, .. } => [0x90 | channel & 0xf, note & 0x7f, velocity & 0x7f],
+            Event::NoteOff { channel, note, .. } => [0x80 | channel & 0xf, note & 0x7f, 0],
+            Event::Cc { channel, control, value, .. } => [0xb0 | channel & 0xf, control & 0x7f, value & 0x7f],
+            _ => return Ok(()),
+        };
+        self.write(&msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // an empty file standing in for a midi device
+    fn device(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lyza-{}-{}", std::process::id(), name));
+        File::create(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn devices_can_be_named() {
+        assert_eq!(device_name("drums=/dev/snd/midiC1D0"), ("drums", "/dev/snd/midiC1D0"));
+        assert_eq!(device_name("/dev/snd/midiC1D0"), ("/dev/snd/midiC1D0", "/dev/snd/midiC1D0"));
+        assert_eq!(device_name("/tmp/a=b"), ("/tmp/a=b", "/tmp/a=b"));
+    }
+
+    #[test]
+    fn sinks_only_take_their_ports() {
+        let path = device("routes");
+        let mut sink = MidiSink::open(path.to_str().unwrap()).unwrap();
+        sink.ports = Some(vec![1, 2]);
+        sink.send(&Event::NoteOn { port: 0, channel: 0, note: 60, velocity: 100 }).unwrap();
+        sink.send(&Event::NoteOn { port: 2, channel: 3, note: 62, velocity: 100 }).unwrap();
+        sink.send(&Event::Cc { port: 3, channel: 0, control: 1, value: 2 }).unwrap();
+        sink.send(&Event::NoteOff { port: 1, channel: 9, note: 36 }).unwrap();
+        drop(sink);
+        assert_eq!(fs::read(&path).unwrap(), [0x93, 62, 100, 0x89, 36, 0]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn mpe_notes_get_channels_of_their_own() {
+        let mut mpe = Mpe { tuning: Tuning::edo(24), playing: [None; 15], next: 0 };
+        // bend, 8107 as 7 bit halves, then the note on, both on member channel 2
+        assert_eq!(mpe.note_on(0, 61, 90), [0xe1, 43, 63, 0x91, 61, 90]);
+        assert_eq!(mpe.note_on(0, 60, 90), [0xe2, 0, 64, 0x92, 60, 90]);
+        assert_eq!(mpe.note_off(0, 61), Some([0x81, 61, 0]));
+        assert_eq!(mpe.note_off(0, 61), None);
+        // the freed channel waits until the others have had a turn
+        assert_eq!(mpe.note_on(3, 62, 90)[0], 0xe3);
+    }
+
+    #[test]
+    fn mpe_steals_the_oldest_note_when_full() {
+        let mut mpe = Mpe { tuning: Tuning::edo(12), playing: [None; 15], next: 0 };
+        for note in 0..15 {
+            mpe.note_on(0, note, 90);
+        }
+        assert_eq!(mpe.note_on(0, 100, 90), [0x81, 0, 0, 0xe1, 0, 64, 0x91, 100, 90]);
+        assert_eq!(mpe.note_off(0, 0), None);
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::arrange::Arrangement;
+use crate::groove::Groove;
+use lyza::{CellLike, Field, Native, Point};
+
+// .orca files are the bare grid, one row per line with `.` for empty cells.
+// .lyza files are the same grid behind a small header:
+//
+//     lyza 1
+//     bpm 120
+//     rules lyza
+//
+//     ........
+//     ..E.....
+//
+// `rules` names the rule set the patch is written for, lyza's own when it's
+// left out, or `orca` for orca's comments on top. `groove` names the feel
+// the midi goes out with, see groove.rs. a song arrangement's lines can
+// follow, see arrange.rs.
+
+static LYZA_MAGIC: &str = "lyza 1";
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Format {
+    Orca,
+    Lyza,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("orca") => Ok(Format::Orca),
+            Some("lyza") => Ok(Format::Lyza),
+            _ => Err(format!("{}: unknown file type, expected .orca or .lyza",
+                             path.display())),
+        }
+    }
+}
+
+pub struct Project {
+    pub field: Field,
+    pub header: Header,
+}
+
+// everything in a .lyza file besides the grid
+#[derive(Clone, Default)]
+pub struct Header {
+    pub bpm: Option<u32>,
+    pub rules: Option<String>,
+    pub groove: Option<String>,
+    pub arrangement: Arrangement,
+}
+
+impl Project {
+    pub fn new(field: Field) -> Self {
+        Self { field, header: Header::default() }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let format = Format::from_path(path)?;
+        let src = fs::read_to_string(path)
+                      .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&src, format).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        save(path, &self.field, &self.header)
+    }
+
+    pub fn parse(src: &str, format: Format) -> Result<Self, String> {
+        let mut lines = src.lines();
+        let mut header = Header::default();
+
+        if format == Format::Lyza {
+            if lines.next().map(str::trim) != Some(LYZA_MAGIC) {
+                return Err("missing `lyza 1` header".to_string());
+            }
+            for line in lines.by_ref() {
+                let line = line.trim();
+                if line.is_empty() {
+                    break;
+                }
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next()) {
+                    (Some("bpm"), Some(v)) => {
+                        header.bpm = Some(v.parse().map_err(|_| format!("invalid bpm `{}`", v))?);
+                    }
+                    (Some("rules"), Some(name)) => header.rules = Some(name.to_string()),
+                    (Some("groove"), Some(name)) => {
+                        header.groove = Some(Groove::named(name)?.name.to_string());
+                    }
+                    _ if header.arrangement.parse_header(line)? => {}
+                    _ => return Err(format!("unknown header line `{}`", line)),
+                }
+            }
+        }
+
+        let rows: Vec<Vec<char>> = lines.map(|l| l.trim_end().chars().collect()).collect();
+        let height = rows.len();
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if width == 0 || height == 0 {
+            return Err("empty grid".to_string());
+        }
+
+        let mut field = Field::new(width, height);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &ch) in row.iter().enumerate() {
+                if ch != '.' {
+                    field.set(Point::new(x as i32, y as i32), ch)?;
+                }
+            }
+        }
+
+        Ok(Self { field, header })
+    }
+
+    pub fn serialize(&self, format: Format) -> String {
+        serialize(&self.field, &self.header, format)
+    }
+}
+
+// the editor keeps its field inside a running context, so saving works
+// from borrowed parts as well
+
+pub fn save(path: &Path, field: &Field, header: &Header) -> Result<(), String> {
+    let format = Format::from_path(path)?;
+    fs::write(path, serialize(field, header, format))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// the rules line is left out for lyza's own rules, and the groove line
+// for a straight one
+pub fn serialize(field: &Field, header: &Header, format: Format) -> String {
+    let mut ret = String::new();
+
+    if format == Format::Lyza {
+        ret.push_str(LYZA_MAGIC);
+        ret.push('\n');
+        if let Some(bpm) = header.bpm {
+            ret.push_str(&format!("bpm {}\n", bpm));
+        }
+        if let Some(rules) = header.rules.as_deref().filter(|&r| r != <Native>::NAME) {
+            ret.push_str(&format!("rules {}\n", rules));
+        }
+        if let Some(groove) = header.groove.as_deref().filter(|&g| g != Groove::default().name) {
+            ret.push_str(&format!("groove {}\n", groove));
+        }
+        ret.push_str(&header.arrangement.header());
+        ret.push('\n');
+    }
+
+    ret.push_str(&grid_text(field));
+    ret
+}
+
+// the editor autosaves next to the file so a crash doesn't take the patch
+// with it. always .lyza, whatever the real file is, so the header survives.
+pub fn recovery_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(".{}.recover", name))
+}
+
+pub fn save_recovery(path: &Path, field: &Field, header: &Header) -> Result<(), String> {
+    let recovery = recovery_path(path);
+    fs::write(&recovery, serialize(field, header, Format::Lyza))
+        .map_err(|e| format!("{}: {}", recovery.display(), e))
+}
+
+pub fn load_recovery(path: &Path) -> Result<Project, String> {
+    let recovery = recovery_path(path);
+    let src = fs::read_to_string(&recovery)
+                  .map_err(|e| format!("{}: {}", recovery.display(), e))?;
+    Project::parse(&src, Format::Lyza).map_err(|e| format!("{}: {}", recovery.display(), e))
+}
+
+pub fn remove_recovery(path: &Path) {
+    let _ = fs::remove_file(recovery_path(path));
+}
+
+pub fn grid_text(field: &Field) -> String {
+    let mut ret = String::new();
+    write_grid(&mut ret, field);
+    ret
+}
+
+pub fn write_grid(buf: &mut String, field: &Field) {
+    for row in field.slots.rows() {
+        for slot in row {
+            let op = slot.glyph();
+            buf.push(if op == '\0' { '.' } else { op });
+        }
+        buf.push('\n');
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use crate::theme::Theme;
+use lyza::{CellLike, Field, OpdefTable, Point};
+
+// text rendering of a whole field for the line-based frontends. cells are
+// three characters wide, ` x ` or `[x]` when locked, like Field's Display,
+// with optional color, a box-drawn border and guide lines every few cells.
+
+pub struct Renderer {
+    pub theme: Theme,
+    pub color: bool,
+    pub border: bool,
+    pub guides: Option<usize>,
+    // cells that changed in the last frame, when they're being highlighted
+    pub changes: Option<Changes>,
+    // each row as last drawn, with the cells it was drawn from: glyph,
+    // locked and changed
+    rows: Vec<Row>,
+    buf: String,
+}
+
+#[derive(Default)]
+struct Row {
+    cells: Vec<(char, bool, bool)>,
+    text: String,
+}
+
+// the difference between a field and how it looked at the previous update
+pub struct Changes {
+    previous: Field,
+    changed: HashSet<Point>,
+}
+
+impl Changes {
+    pub fn new(field: &Field) -> Self {
+        Self { previous: field.clone(), changed: HashSet::new() }
+    }
+
+    pub fn update(&mut self, field: &Field) {
+        self.changed = self.previous.diff(field).into_iter().map(|(pt, _, _)| pt).collect();
+        self.previous = field.clone();
+    }
+
+    pub fn is_changed(&self, pt: Point) -> bool {
+        self.changed.contains(&pt)
+    }
+
+    pub fn points(&self) -> impl Iterator<Item = &Point> {
+        self.changed.iter()
+    }
+}
+
+impl Renderer {
+    pub fn new(theme: Theme) -> Self {
+        Self {
+            theme,
+            color: false,
+            border: false,
+            guides: None,
+            changes: None,
+            rows: Vec::new(),
+            buf: String::new(),
+        }
+    }
+
+    // call once per frame when highlighting changes
+    pub fn update(&mut self, field: &Field) {
+        if let Some(changes) = &mut self.changes {
+            changes.update(field);
+        }
+    }
+
+    fn is_changed(&self, pt: Point) -> bool {
+        self.changes.as_ref().is_some_and(|c| c.is_changed(pt))
+    }
+
+    fn is_guide(&self, i: usize) -> bool {
+        matches!(self.guides, Some(n) if n > 0 && i > 0 && i.is_multiple_of(n))
+    }
+
+    fn push_frame(&self, buf: &mut String, s: &str) {
+        if self.color {
+            let _ = write!(buf, "\x1b[2m{}\x1b[0m", s);
+        } else {
+            buf.push_str(s);
+        }
+    }
+
+    // a horizontal rule: corner, the line, where it meets a guide, corner
+    fn push_rule(&self, buf: &mut String, width: usize, left: char, fill: char, cross: char,
+                 right: char) {
+        if self.color {
+            buf.push_str("\x1b[2m");
+        }
+        if self.border {
+            buf.push(left);
+        }
+        for x in 0..width {
+            if self.is_guide(x) {
+                buf.push(cross);
+            }
+            buf.extend(std::iter::repeat_n(fill, 3));
+        }
+        if self.border {
+            buf.push(right);
+        }
+        if self.color {
+            buf.push_str("\x1b[0m");
+        }
+        buf.push('\n');
+    }
+
+    // the field as text, in a buffer kept from frame to frame. rows drawn
+    // from the same cells as last time are copied rather than formatted
+    // again, which on most frames is nearly all of them.
+    pub fn render(&mut self, field: &Field, opdefs: &OpdefTable) -> &str {
+        let (width, height) = (field.slots.width, field.slots.height);
+        let mut buf = std::mem::take(&mut self.buf);
+        let mut rows = std::mem::take(&mut self.rows);
+        buf.clear();
+        rows.resize_with(height, Row::default);
+
+        if self.border {
+            self.push_rule(&mut buf, width, '┌', '─', '┬', '┐');
+        }
+
+        for (y, (cells, row)) in field.slots.rows().zip(&mut rows).enumerate() {
+            if self.is_guide(y) {
+                self.push_rule(&mut buf, width, '├', '┄', '┼', '┤');
+            }
+            let key = cells.iter().enumerate().map(|(x, slot)| {
+                let pt = Point::new(x as i32, y as i32);
+                (slot.glyph(), field.is_locked(pt), self.is_changed(pt))
+            });
+            if !row.cells.iter().copied().eq(key.clone()) {
+                row.cells.clear();
+                row.cells.extend(key);
+                row.text.clear();
+                self.render_row(&mut row.text, &row.cells, opdefs);
+            }
+            buf.push_str(&row.text);
+        }
+
+        if self.border && height > 0 {
+            self.push_rule(&mut buf, width, '└', '─', '┴', '┘');
+        }
+        self.rows = rows;
+        self.buf = buf;
+        &self.buf
+    }
+
+    fn render_row(&self, buf: &mut String, cells: &[(char, bool, bool)], opdefs: &OpdefTable) {
+        let theme = &self.theme;
+        if self.border {
+            self.push_frame(buf, "│");
+        }
+
+        for (x, &(op, lock, changed)) in cells.iter().enumerate() {
+            if self.is_guide(x) {
+                self.push_frame(buf, "┆");
+            }
+
+            let ch = theme.glyph(op);
+            let (open, close) = if lock {
+                (theme.glyphs.lock_open, theme.glyphs.lock_close)
+            } else {
+                (' ', ' ')
+            };
+
+            if self.color {
+                let sgr = theme.sgr(opdefs.find(op).map(|opd| opd.category), op);
+                let lock = &theme.colors.locked;
+                let _ = write!(buf, "\x1b[0;{}m{}\x1b[0;{}m", lock, open, sgr);
+                if changed && !theme.colors.changed.is_empty() {
+                    let _ = write!(buf, "\x1b[{}m", theme.colors.changed);
+                }
+                let _ = write!(buf, "{}\x1b[0;{}m{}\x1b[0m", ch, lock, close);
+            } else if changed {
+                // no color to show it with, so bracket the cell instead
+                buf.push('>');
+                buf.push(ch);
+                buf.push('<');
+            } else {
+                buf.push(open);
+                buf.push(ch);
+                buf.push(close);
+            }
+        }
+
+        if self.border {
+            self.push_frame(buf, "│");
+        }
+        buf.push('\n');
+    }
+}
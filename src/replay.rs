@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::control::Command;
+
+// everything that reached a running field from outside, each with the
+// frame it arrived before, so a session can be run again exactly. with the
+// random seed written at the top, operators pick the same values too.
+//
+//     lyza inputs 1
+//     seed 7081316924873510931
+//     0 bpm 140
+//     12 write 3 4 E
+//     40 pause
+
+const HEADER: &str = "lyza inputs 1";
+
+pub struct Recorder {
+    file: LineWriter<File>,
+    pub seed: u64,
+}
+
+impl Recorder {
+    // the seed is picked from the clock unless given, and is for the
+    // caller to reseed the context with
+    pub fn create(path: &Path, seed: Option<u64>) -> Result<Self, String> {
+        let seed = seed.unwrap_or_else(clock_seed);
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut ret = Self { file: LineWriter::new(file), seed };
+        writeln!(ret.file, "{}\nseed {}", HEADER, seed).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(ret)
+    }
+
+    // written straight through, so a crash keeps everything up to it
+    pub fn log(&mut self, frame: u32, cmd: &Command) -> Result<(), String> {
+        writeln!(self.file, "{} {}", frame, cmd).map_err(|e| format!("recording: {}", e))
+    }
+}
+
+pub fn clock_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64)
+}
+
+pub struct Replay {
+    pub seed: u64,
+    inputs: VecDeque<(u32, Command)>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let src = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&src).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut lines = src.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim()) != Some(HEADER) {
+            return Err(format!("not a recording, expected `{}` on the first line", HEADER));
+        }
+        let seed = match lines.next() {
+            Some((_, line)) => line.trim()
+                                   .strip_prefix("seed ")
+                                   .and_then(|n| n.trim().parse().ok())
+                                   .ok_or_else(|| "line 2: expected `seed <n>`".to_string())?,
+            None => return Err("line 2: expected `seed <n>`".to_string()),
+        };
+
+        let mut inputs = VecDeque::new();
+        for (i, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (frame, cmd) = line.split_once(' ')
+                                   .ok_or_else(|| format!("line {}: expected `<frame> <command>`", i + 1))?;
+            let frame = frame.parse().map_err(|_| format!("line {}: invalid frame `{}`", i + 1, frame))?;
+            let cmd = cmd.parse().map_err(|e| format!("line {}: {}", i + 1, e))?;
+            inputs.push_back((frame, cmd));
+        }
+        Ok(Self { seed, inputs })
+    }
+
+    // the inputs that arrived before `frame`, in the order they did
+    pub fn due(&mut self, frame: u32) -> Vec<Command> {
+        let mut ret = Vec::new();
+        while self.inputs.front().is_some_and(|&(at, _)| at <= frame) {
+            ret.extend(self.inputs.pop_front().map(|(_, cmd)| cmd));
+        }
+        ret
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use crate::arrange::Arrangement;
+use crate::commands::{Commands, Request};
+use crate::config::Config;
+use crate::control::{self, Command, Status, Transport};
+use crate::groove::Groove;
+use crate::humanize::{Humanize, Playing};
+use crate::output::Output;
+use crate::project::{Header, Project};
+use lyza::{Context, Event, LyzaError, Native, Orca, Point, RuleSet};
+
+// everything a frontend needs to drive the engine: the field and operators,
+// the transport and the output connections. frontends own one of these and
+// only decide when to tick it and how to show it.
+
+pub struct Session {
+    pub ctx: Context,
+    pub transport: Transport,
+    // frames' events go to the sinks from a thread of their own
+    pub output: Output,
+    pub commands: Commands,
+    // the song, if the project has one, switching patterns in a region
+    pub arrangement: Arrangement,
+    // how late the midi from each frame goes out
+    pub groove: Groove,
+    pub humanize: Humanize,
+    playing: Playing,
+    // asked for by `$` operators, for the frontend to pick up after a tick
+    pub requests: Vec<Request>,
+    // cells the last tick changed, for frontends that only redraw those.
+    // None when a command ran, which could have changed anything.
+    pub changed: Option<Vec<Point>>,
+    // what the engine warned about last frame, so an unknown operator
+    // sitting in the field is reported once rather than every frame
+    warnings: Vec<LyzaError>,
+}
+
+// the rule sets a project can ask for by name, lyza's own when it doesn't
+pub fn rule_set(name: Option<&str>) -> Result<Box<dyn RuleSet>, String> {
+    match name {
+        None => Ok(Box::new(Native::default())),
+        Some(name) if name == <Native>::NAME => Ok(Box::new(Native::default())),
+        Some(name) if name == <Orca>::NAME => Ok(Box::new(Orca::default())),
+        Some(name) => Err(format!("unknown rules `{}`", name)),
+    }
+}
+
+// four frames to the beat, as in orca
+pub fn frame_duration(bpm: u32) -> Duration {
+    Duration::from_millis(60_000 / (bpm as u64 * 4))
+}
+
+impl Session {
+    // `bpm` is passed separately, as the project's can be overridden
+    pub fn new(config: &Config, project: Project, bpm: u32) -> Result<Self, String> {
+        let groove = project.header.groove.as_deref().map_or_else(|| Ok(Groove::default()), Groove::named)?;
+        Ok(Self {
+            ctx: Context::with_rules(rule_set(project.header.rules.as_deref())?, project.field),
+            transport: Transport::new(bpm),
+            output: Output::start(config)?,
+            commands: Commands::default(),
+            arrangement: project.header.arrangement,
+            groove,
+            humanize: config.humanize,
+            playing: Playing::default(),
+            requests: Vec::new(),
+            changed: Some(Vec::new()),
+            warnings: Vec::new(),
+        })
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        frame_duration(self.transport.bpm)
+    }
+
+    // runs one frame and sends what it emitted, returning delivery errors
+    // and anything operators logged. a paused session doesn't advance.
+    pub fn tick(&mut self) -> Vec<String> {
+        if self.transport.paused {
+            self.changed.get_or_insert_with(Vec::new).clear();
+            return Vec::new();
+        }
+        let switched = self.arrangement.update(&mut self.ctx.field, self.ctx.frame_ct);
+        // humanizing draws once a frame from the seeded generator, and
+        // only when it's on, so patches pick the same values as ever
+        // without it
+        let mut rng = if self.humanize.is_off() { 0 } else { self.ctx.random() };
+        // the frame stays with the context, and what's kept of it is copied
+        // into space kept from frame to frame
+        let frame = self.ctx.step();
+        let changed = self.changed.get_or_insert_with(Vec::new);
+        changed.clear();
+        changed.extend_from_slice(&frame.changed);
+        if switched {
+            self.changed = None;
+        }
+
+        let mut lines = Vec::new();
+        let mut logged = Vec::new();
+        for event in &frame.events {
+            match event {
+                Event::Command(line) => lines.push(line.clone()),
+                Event::Log(msg) => logged.push(msg.clone()),
+                _ => {}
+            }
+        }
+        let step = frame_duration(self.transport.bpm);
+        let groove = self.groove.delay(frame.frame, step);
+        let humanize = self.humanize;
+        let playing = &mut self.playing;
+        playing.next_frame();
+        self.output.send(frame.events.iter()
+                              .filter(|e| !matches!(e, Event::Command(_) | Event::Log(_)))
+                              .map(|e| {
+                                  let mut e = e.clone();
+                                  let late = humanize.apply(&mut rng, playing, &mut e, step);
+                                  (groove + late, e)
+                              }));
+        // delivery errors turn up a frame or so after the events that
+        // caused them
+        let mut errors: Vec<String> = self.output.errors().collect();
+        errors.extend(logged);
+        let seen = &self.warnings;
+        errors.extend(frame.warnings.iter()
+                           .filter(|w| !seen.contains(w))
+                           .map(LyzaError::to_string));
+        self.warnings.clone_from(&frame.warnings);
+        if !lines.is_empty() {
+            self.changed = None;
+        }
+        for line in lines {
+            match self.run_command(&line) {
+                // a patch only gets to save itself, or any patch opened
+                // could write over whatever file it names
+                Ok(Some(Request::Write(Some(_)))) => {
+                    errors.push(format!("${}: the grid can only save to the patch's own file", line));
+                }
+                Ok(Some(req)) => self.requests.push(req),
+                Ok(None) => {}
+                Err(e) => errors.push(format!("${}: {}", line, e)),
+            }
+        }
+        errors
+    }
+
+    pub fn run_command(&mut self, line: &str) -> Result<Option<Request>, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or_else(|| "empty command".to_string())?;
+        let args: Vec<&str> = words.collect();
+        let handler = self.commands.find(name)
+                          .ok_or_else(|| format!("unknown command `{}`", name))?;
+        handler(self, &args)
+    }
+
+    pub fn apply(&mut self, cmd: &Command) -> Result<(), String> {
+        control::apply(cmd, &mut self.ctx, &mut self.transport)
+    }
+
+    // the cells whose operators failed in the last frame
+    pub fn failed(&self) -> impl Iterator<Item = Point> + '_ {
+        self.warnings.iter().filter_map(|w| match w {
+            LyzaError::OperatorFailed { at, .. } => Some(*at),
+            _ => None,
+        })
+    }
+
+    // what's saved along with the field
+    pub fn header(&self) -> Header {
+        Header {
+            bpm: Some(self.transport.bpm),
+            rules: Some(self.ctx.rules.name().to_string()),
+            groove: Some(self.groove.name.to_string()),
+            arrangement: self.arrangement.clone(),
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        Status::capture(&self.ctx, &self.transport)
+    }
+
+    pub fn update_status(&self, status: &mut Status) {
+        status.update(&self.ctx, &self.transport);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::Format;
+
+    // a session running `grid`, sending its output nowhere
+    fn session(grid: &str) -> Session {
+        let config = Config { dry_run: true, ..Config::default() };
+        let project = Project::parse(grid, Format::Orca).unwrap();
+        Session::new(&config, project, 120).unwrap()
+    }
+
+    #[test]
+    fn the_grid_cant_save_elsewhere() {
+        let mut session = session("$w:other\n*.......\n");
+        let errors = session.tick();
+        assert!(errors.contains(&"$w other: the grid can only save to the patch's own file".to_string()),
+                "{:?}", errors);
+        assert!(session.requests.is_empty());
+    }
+
+    #[test]
+    fn the_grid_can_save_itself() {
+        let mut session = session("$w\n*.\n");
+        let errors = session.tick();
+        assert!(errors.iter().all(|e| !e.starts_with('$')), "{:?}", errors);
+        assert_eq!(session.requests, [Request::Write(None)]);
+        assert_eq!(session.changed, None);
+    }
+
+    #[test]
+    fn grid_commands_run_and_report_errors() {
+        let mut session = session("$bpm:140\n*.......\n$nope...\n*.......\n");
+        let errors = session.tick();
+        assert_eq!(session.transport.bpm, 140);
+        assert!(errors.contains(&"$nope: unknown command `nope`".to_string()), "{:?}", errors);
+    }
+}
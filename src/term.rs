@@ -0,0 +1,178 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// raw terminal handling on top of stty and plain ansi escapes
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Esc,
+    Mouse(Mouse),
+}
+
+// positions are zero-based screen cells
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mouse {
+    Press(usize, usize),
+    Drag(usize, usize),
+    Release(usize, usize),
+    ScrollUp { shift: bool },
+    ScrollDown { shift: bool },
+}
+
+pub struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    // puts the terminal in raw mode on the alternate screen until dropped
+    pub fn enter() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        let mut out = io::stdout();
+        // alternate screen, hidden cursor, and sgr mouse reports with drags
+        out.write_all(b"\x1b[?1049h\x1b[?25l\x1b[2J\x1b[?1002h\x1b[?1006h")?;
+        out.flush()?;
+        Ok(Self { saved: saved.trim().to_string() })
+    }
+
+    // (columns, rows)
+    pub fn size() -> (usize, usize) {
+        stty(&["size"]).ok()
+                       .and_then(|s| {
+                           let mut it = s.split_whitespace().map(|n| n.parse().ok());
+                           match (it.next().flatten(), it.next().flatten()) {
+                               (Some(rows), Some(cols)) => Some((cols, rows)),
+                               _ => None,
+                           }
+                       })
+                       .unwrap_or((80, 24))
+    }
+
+    // keys arrive on a channel so the caller can wait with a timeout
+    pub fn keys() -> Receiver<Key> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut buf = [0; 64];
+            loop {
+                let n = match stdin.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => n,
+                };
+                for key in decode_keys(&buf[..n]) {
+                    if tx.send(key).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        rx
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        let _ = out.write_all(b"\x1b[?1006l\x1b[?1002l\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = out.flush();
+        let _ = stty(&[self.saved.as_str()]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let out = Command::new("stty")
+                  .args(args)
+                  .stdin(Stdio::inherit())
+                  .output()?;
+    if !out.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&out.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+// a read normally holds a whole escape sequence, so a lone ESC byte at the
+// end of a chunk is the escape key itself
+fn decode_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut ret = Vec::new();
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        let key = match ch {
+            '\x1b' => {
+                if chars.peek() != Some(&'[') && chars.peek() != Some(&'O') {
+                    Key::Esc
+                } else {
+                    chars.next();
+                    match chars.next() {
+                        Some('<') => match decode_mouse(&mut chars) {
+                            Some(mouse) => Key::Mouse(mouse),
+                            None => continue,
+                        },
+                        Some('A') => Key::Up,
+                        Some('B') => Key::Down,
+                        Some('C') => Key::Right,
+                        Some('D') => Key::Left,
+                        Some('3') => {
+                            chars.next_if_eq(&'~');
+                            Key::Delete
+                        }
+                        _ => continue,
+                    }
+                }
+            }
+            '\r' | '\n' => Key::Enter,
+            '\t' => Key::Tab,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x01'..='\x1a' => Key::Ctrl((ch as u8 - 1 + b'a') as char),
+            _ if ch.is_control() => continue,
+            _ => Key::Char(ch),
+        };
+        ret.push(key);
+    }
+
+    ret
+}
+
+// the rest of an sgr mouse report after `ESC [ <`: `button;x;y` then M for
+// press/drag or m for release
+fn decode_mouse(chars: &mut impl Iterator<Item = char>) -> Option<Mouse> {
+    let mut report = String::new();
+    let mut release = false;
+    for ch in chars.by_ref() {
+        match ch {
+            'M' => break,
+            'm' => {
+                release = true;
+                break;
+            }
+            _ => report.push(ch),
+        }
+    }
+
+    let mut nums = report.split(';').map(|n| n.parse::<usize>().ok());
+    let (button, x, y) = (nums.next()??, nums.next()??, nums.next()??);
+    let (x, y) = (x.saturating_sub(1), y.saturating_sub(1));
+    let shift = button & 4 != 0;
+
+    match button & !(4 | 8 | 16) {
+        64 => Some(Mouse::ScrollUp { shift }),
+        65 => Some(Mouse::ScrollDown { shift }),
+        0 if release => Some(Mouse::Release(x, y)),
+        0 => Some(Mouse::Press(x, y)),
+        32 => Some(Mouse::Drag(x, y)),
+        _ => None,
+    }
+}
@@ -0,0 +1,288 @@
+//! pinning down what operators do. a field is written out as rows of
+//! glyphs, run a frame at a time and compared against how each frame
+//! should look, with the rows that differ marked when they don't match.
+//!
+//! ```
+//! use lyza::testing;
+//!
+//! testing::assert_frames("
+//!     E..
+//!     ...
+//! ", &["
+//!     .E.
+//!     ...
+//! ", "
+//!     ..E
+//!     ...
+//! "]);
+//! ```
+//!
+//! grids are indented along with the code around them; the indentation
+//! and blank lines at either end are ignored. `.` is an empty cell and
+//! short rows are filled out with empty ones.
+//!
+//! [`fuzz_tick`] is an entry point for fuzzers, which hand it raw bytes to
+//! build a field out of.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{CellLike, Context, Field, LyzaError, OpdefTable, Point};
+
+/// a field from rows of glyphs
+pub fn parse(src: &str) -> Result<Field, LyzaError> {
+    let rows = rows(src);
+    let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+    let mut field = Field::new(width, rows.len());
+    for (y, row) in rows.iter().enumerate() {
+        for (x, ch) in row.chars().enumerate() {
+            field.set(Point::new(x as i32, y as i32), ch)?;
+        }
+    }
+    Ok(field)
+}
+
+/// the field as rows of glyphs, the way `parse` reads them
+pub fn text<C: CellLike>(field: &Field<C>) -> String {
+    let mut ret = String::new();
+    for row in field.slots.rows() {
+        for slot in row {
+            let op = slot.glyph();
+            ret.push(if op == '\0' { '.' } else { op });
+        }
+        ret.push('\n');
+    }
+    ret
+}
+
+// the lines of a grid, without blank lines at either end or the
+// indentation they all share
+fn rows(src: &str) -> Vec<&str> {
+    let lines: Vec<&str> = src.lines().collect();
+    let start = lines.iter().position(|l| !l.trim().is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|l| !l.trim().is_empty()).map_or(start, |i| i + 1);
+    let lines = &lines[start..end];
+    let indent = lines.iter()
+                      .filter(|l| !l.trim().is_empty())
+                      .map(|l| l.len() - l.trim_start().len())
+                      .min()
+                      .unwrap_or(0);
+    lines.iter().map(|l| l.get(indent..).unwrap_or("").trim_end()).collect()
+}
+
+/// a frame that didn't come out as expected
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    /// which frame, counting from 0
+    pub frame: u32,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// the two grids side by side, with `<` after each row that differs and
+/// `^` under the cells that do
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let expected = rows(&self.expected);
+        let actual = rows(&self.actual);
+        let width = expected.iter().map(|r| r.chars().count()).max().unwrap_or(0).max("expected".len());
+        writeln!(f, "frame {} differs", self.frame)?;
+        writeln!(f, "  {:<w$}  actual", "expected", w = width)?;
+        for i in 0..expected.len().max(actual.len()) {
+            let want = expected.get(i).copied().unwrap_or("");
+            let got = actual.get(i).copied().unwrap_or("");
+            if want == got {
+                writeln!(f, "  {:<w$}  {}", want, got, w = width)?;
+                continue;
+            }
+            writeln!(f, "  {:<w$}  {}  <", want, got, w = width)?;
+            let len = want.chars().count().max(got.chars().count());
+            let marks: String = (0..len).map(|x| {
+                if want.chars().nth(x) == got.chars().nth(x) { ' ' } else { '^' }
+            }).collect();
+            writeln!(f, "  {:<w$}  {}", "", marks.trim_end(), w = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// runs `src` with the built in operators a frame for each of `frames`,
+/// checking the field after every one. stops at the first that differs.
+///
+/// panics if `src` isn't a grid `parse` can read.
+pub fn check(src: &str, frames: &[&str]) -> Result<(), Mismatch> {
+    let field = match parse(src) {
+        Ok(field) => field,
+        Err(e) => panic!("bad grid: {}", e),
+    };
+    let mut ctx = Context::new(OpdefTable::default(), field);
+    check_context(&mut ctx, frames)
+}
+
+/// like `check`, for a context set up some other way: other operators,
+/// other rules or a seeded random number generator
+pub fn check_context<C: CellLike>(ctx: &mut Context<C>, frames: &[&str]) -> Result<(), Mismatch> {
+    for expected in frames {
+        let frame = ctx.frame_ct;
+        ctx.step();
+        let actual = text(&ctx.field);
+        if rows(expected) != rows(&actual) {
+            return Err(Mismatch { frame, expected: String::from(*expected), actual });
+        }
+    }
+    Ok(())
+}
+
+/// `check`, panicking with the difference when a frame doesn't match
+pub fn assert_frames(src: &str, frames: &[&str]) {
+    if let Err(mismatch) = check(src, frames) {
+        panic!("\n{}", mismatch);
+    }
+}
+
+/// frames `fuzz_tick` runs each field for
+pub const FUZZ_FRAMES: usize = 16;
+
+/// a field built out of raw bytes, so a fuzzer can reach any grid. the
+/// first two pick the size, up to 32 on a side and possibly empty, and the
+/// rest fill it row by row: printable ascii as its glyph, anything else as
+/// an empty cell.
+pub fn field_from_bytes(data: &[u8]) -> Field {
+    let size = |i: usize| data.get(i).map_or(0, |&b| b as usize % 33);
+    let (width, height) = (size(0), size(1));
+    let mut field = Field::new(width, height);
+    let cells = data.iter().skip(2).take(width * height);
+    for (i, &b) in cells.enumerate() {
+        let glyph = if b.is_ascii_graphic() { b as char } else { '\0' };
+        let _ = field.set(Point::new((i % width) as i32, (i / width) as i32), glyph);
+    }
+    field
+}
+
+/// runs a field from `field_from_bytes` for [`FUZZ_FRAMES`] with the built in
+/// operators, seeded from the bytes too. whatever's in the cells, nothing
+/// should panic, the field should keep its size and the context's
+/// invariants should hold.
+pub fn fuzz_tick(data: &[u8]) {
+    let field = field_from_bytes(data);
+    let (width, height) = (field.slots.width, field.slots.height);
+    let mut ctx = Context::new(OpdefTable::default(), field);
+    ctx.reseed(data.iter().fold(1, |seed: u64, &b| seed.rotate_left(8) ^ b as u64));
+    ctx.check_invariants = true;
+    for _ in 0..FUZZ_FRAMES {
+        let frame = ctx.step();
+        assert!(frame.changed.iter().all(|pt| pt.x >= 0 && pt.y >= 0
+                                              && (pt.x as usize) < width && (pt.y as usize) < height),
+                "changes reported off the field");
+        if let Some(broken) = frame.warnings.iter().find(|w| matches!(w, LyzaError::Invariant(_))) {
+            panic!("{}", broken);
+        }
+        assert_eq!((ctx.field.slots.width, ctx.field.slots.height), (width, height), "the field changed size");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+    use crate::Orca;
+
+    fn run(src: &str, frames: &[&str]) {
+        let mut ctx = Context::new(OpdefTable::default(), parse(src).unwrap());
+        if let Err(mismatch) = check_context(&mut ctx, frames) {
+            panic!("\n{}", mismatch);
+        }
+    }
+
+    #[test]
+    fn movers_move_a_cell_a_frame() {
+        run("
+            E...
+            ...N
+            S...
+            ...W
+        ", &["
+            .E.N
+            ....
+            ....
+            S.W.
+        "]);
+    }
+
+    #[test]
+    fn movers_run_once_a_frame() {
+        // each lands ahead of the scan and is locked there, so it doesn't
+        // run again on the way past
+        run("
+            E...
+            S...
+            ....
+            ....
+        ", &["
+            .E..
+            ....
+            S...
+            ....
+        ", "
+            ..E.
+            ....
+            ....
+            S...
+        "]);
+    }
+
+    #[test]
+    fn blocked_movers_turn_to_bangs_that_clear() {
+        run("
+            ..E
+            EW.
+        ", &["
+            ..*
+            **.
+        ", "
+            ...
+            ...
+        "]);
+    }
+
+    #[test]
+    fn halt_stops_the_cell_below() {
+        run("
+            H.
+            E.
+        ", &["
+            H.
+            E.
+        ", "
+            H.
+            E.
+        "]);
+    }
+
+    #[test]
+    fn comments_stop_their_row_running() {
+        let field = parse("
+            #E.#E..
+            .......
+        ").unwrap();
+        let mut ctx = Context::with_rules(Box::new(Orca::default()), field);
+        if let Err(mismatch) = check_context(&mut ctx, &["
+            #E.#.E.
+            .......
+        "]) {
+            panic!("\n{}", mismatch);
+        }
+    }
+
+    #[test]
+    fn locks_last_only_a_frame() {
+        let mut ctx = Context::new(OpdefTable::default(), parse("E..").unwrap());
+        ctx.field.lock(Point::new(0, 0));
+        ctx.check_invariants = true;
+        assert_eq!(check_context(&mut ctx, &[".E."]), Ok(()));
+        assert!(ctx.field.is_locked(Point::new(1, 0)));
+        assert!(ctx.arena.warnings.is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use crate::config::Config;
+use lyza::Category;
+
+// how cells look on screen. glyphs are the characters drawn for empty and
+// locked cells, colors are sgr parameter strings ("1;31", "" for none).
+//
+// themes are picked by name with `theme = "..."` in the config file, and any
+// part of them can be overridden from a [theme] table:
+//
+//     [theme]
+//     empty = "·"
+//     locked = "()"
+//
+//     [theme.colors]
+//     movement = "1;36"
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Glyphs {
+    pub empty: char,
+    pub lock_open: char,
+    pub lock_close: char,
+}
+
+impl Glyphs {
+    pub const DEFAULT: Glyphs = Glyphs {
+        empty: '.',
+        lock_open: '[',
+        lock_close: ']',
+    };
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Colors {
+    pub empty: String,
+    pub data: String,
+    pub locked: String,
+    pub bang: String,
+    pub movement: String,
+    pub math: String,
+    pub io: String,
+    pub util: String,
+    pub custom: String,
+    // backgrounds for beat grid columns, and for them flashing on the beat
+    pub grid: String,
+    pub beat: String,
+    // background for cells that changed in the last frame
+    pub changed: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub glyphs: Glyphs,
+    pub colors: Colors,
+}
+
+static THEME_NAMES: &[&str] = &["default", "mono", "dots"];
+
+impl Theme {
+    pub fn named(name: &str) -> Result<Self, String> {
+        let colors = |c: [&str; 12]| Colors {
+            empty: c[0].to_string(),
+            data: c[1].to_string(),
+            locked: c[2].to_string(),
+            bang: c[3].to_string(),
+            movement: c[4].to_string(),
+            math: c[5].to_string(),
+            io: c[6].to_string(),
+            util: c[7].to_string(),
+            custom: c[8].to_string(),
+            grid: c[9].to_string(),
+            beat: c[10].to_string(),
+            changed: c[11].to_string(),
+        };
+        let standard = colors(["2", "32", "2", "1;31", "36", "33", "35", "34", "1;32",
+                               "48;5;235", "48;5;240", "48;5;58"]);
+
+        match name {
+            "default" => Ok(Self { glyphs: Glyphs::DEFAULT, colors: standard }),
+            "mono" => Ok(Self { glyphs: Glyphs::DEFAULT, colors: colors([""; 12]) }),
+            "dots" => Ok(Self {
+                glyphs: Glyphs { empty: '·', lock_open: '‹', lock_close: '›' },
+                colors: standard,
+            }),
+            _ => Err(format!("unknown theme `{}`, expected one of {}",
+                             name, THEME_NAMES.join(", "))),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        Self::named(&config.theme)?.with_overrides(&config.theme_overrides)
+    }
+
+    // applies "theme.*" keys from the config file
+    pub fn with_overrides(mut self, overrides: &HashMap<String, String>) -> Result<Self, String> {
+        for (key, value) in overrides.iter() {
+            let single = || {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Ok(ch),
+                    _ => Err(format!("theme.{} should be a single character", key)),
+                }
+            };
+            match key.as_str() {
+                "empty" => self.glyphs.empty = single()?,
+                "locked" => {
+                    let chars: Vec<char> = value.chars().collect();
+                    match chars.as_slice() {
+                        [open, close] => {
+                            self.glyphs.lock_open = *open;
+                            self.glyphs.lock_close = *close;
+                        }
+                        _ => return Err("theme.locked should be two characters".to_string()),
+                    }
+                }
+                "colors.empty" => self.colors.empty = value.clone(),
+                "colors.data" => self.colors.data = value.clone(),
+                "colors.locked" => self.colors.locked = value.clone(),
+                "colors.bang" => self.colors.bang = value.clone(),
+                "colors.movement" => self.colors.movement = value.clone(),
+                "colors.math" => self.colors.math = value.clone(),
+                "colors.io" => self.colors.io = value.clone(),
+                "colors.util" => self.colors.util = value.clone(),
+                "colors.custom" => self.colors.custom = value.clone(),
+                "colors.grid" => self.colors.grid = value.clone(),
+                "colors.beat" => self.colors.beat = value.clone(),
+                "colors.changed" => self.colors.changed = value.clone(),
+                _ => return Err(format!("unknown theme setting `theme.{}`", key)),
+            }
+        }
+        Ok(self)
+    }
+
+    // color for a glyph of the given category. glyphs that aren't operators
+    // are data, unless they're empty.
+    pub fn sgr(&self, cat: Option<Category>, op: char) -> &str {
+        match cat {
+            Some(Category::Movement) => &self.colors.movement,
+            Some(Category::Math) => &self.colors.math,
+            Some(Category::Io) => &self.colors.io,
+            Some(Category::Util) => &self.colors.util,
+            Some(Category::Bang) => &self.colors.bang,
+            Some(Category::Custom) => &self.colors.custom,
+            None if op == '\0' => &self.colors.empty,
+            None => &self.colors.data,
+        }
+    }
+
+    pub fn glyph(&self, op: char) -> char {
+        if op == '\0' { self.glyphs.empty } else { op }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::named("default").unwrap()
+    }
+}
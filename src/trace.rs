@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::control::json_string;
+use lyza::{FrameResult, Point};
+
+// what every operator did, frame by frame, as json lines to go through
+// afterwards. a frame's line comes first, then one for each operator that
+// ran in it:
+//
+//     {"frame":12,"ops":2,"events":1,"changed":2}
+//     {"frame":12,"op":"E","at":[3,4],"read":[],"wrote":[[4,4],[3,4]]}
+//     {"frame":12,"op":"^","at":[0,6],"read":[[1,6],[2,6],[3,6]],"wrote":[]}
+
+pub struct Tracer {
+    file: BufWriter<File>,
+}
+
+impl Tracer {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self { file: BufWriter::new(file) })
+    }
+
+    pub fn write(&mut self, frame: &FrameResult) -> Result<(), String> {
+        let mut out = format!("{{\"frame\":{},\"ops\":{},\"events\":{},\"changed\":{}}}\n",
+                              frame.frame, frame.trace.len(), frame.events.len(), frame.changed.len());
+        for op in &frame.trace {
+            out.push_str(&format!("{{\"frame\":{},\"op\":{},\"at\":{},\"read\":{},\"wrote\":{}}}\n",
+                                  frame.frame, json_string(&op.glyph.to_string()), point(op.at),
+                                  points(&op.read), points(&op.wrote)));
+        }
+        self.file.write_all(out.as_bytes()).map_err(|e| format!("tracing: {}", e))
+    }
+}
+
+fn point(pt: Point) -> String {
+    format!("[{},{}]", pt.x, pt.y)
+}
+
+fn points(pts: &[Point]) -> String {
+    format!("[{}]", pts.iter().map(|&pt| point(pt)).collect::<Vec<_>>().join(","))
+}
@@ -0,0 +1,1009 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::arrange::FRAMES_PER_BAR;
+use crate::commands::Request;
+use crate::config::Config;
+use crate::control::Command;
+use crate::editor::{Editor, Mode};
+use crate::keymap::{Action, Keymap, Lookup};
+use crate::minimap::Minimap;
+use crate::project::{self, Project};
+use crate::render::Changes;
+use crate::replay::Recorder;
+use crate::session::Session;
+use crate::term::{Key, Mouse, Terminal};
+use crate::theme::Theme;
+use crate::viewport::Viewport;
+use lyza::history::{History, Snapshot};
+use lyza::{codec, Axis, Category, CellLike, Direction, Point};
+
+const SIZE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+// what decides where things are on screen. while it stays the same between
+// renders only the cells that changed are drawn again.
+#[derive(Clone, PartialEq)]
+struct Layout {
+    origin: Point,
+    zoom: usize,
+    cols: usize,
+    rows: usize,
+    // the grid columns flash on the beat, which only needs them redrawn
+    on_beat: bool,
+    // top, left, width and height of the ports box
+    ports: Option<(usize, usize, usize, usize)>,
+}
+
+struct App {
+    session: Session,
+    theme: Theme,
+    path: PathBuf,
+    editor: Editor,
+    viewport: Viewport,
+    minimap: Minimap,
+    show_minimap: bool,
+    beat_grid: usize,
+    show_grid: bool,
+    show_help: bool,
+    show_ports: bool,
+    // highlighting of cells changed by the last frame, when it's on
+    changes: Option<Changes>,
+    // text being typed after a leader key, e.g. `/` for search
+    prompt: Option<(char, String)>,
+    last_search: Option<String>,
+    keymap: Keymap,
+    // keys typed so far towards a chord
+    chord: Vec<Key>,
+    // `Q` or `@` waiting for the register to record into / play back
+    pending_register: Option<char>,
+    recording: Option<(char, Vec<Key>)>,
+    // a mover the cursor and viewport stay with, where it was last seen and
+    // which way it's heading
+    following: Option<(char, Point, Option<Direction>)>,
+    macros: HashMap<char, Vec<Key>>,
+    replaying: bool,
+    message: String,
+    modified: bool,
+    // the field after every frame and edit, for undo to step back through
+    history: History,
+    // where edits are written down as control commands, to be replayed
+    // with `run --replay`, and the transport as last written down
+    recorder: Option<Recorder>,
+    recorded: (u32, bool),
+    // the field before an undo, which took the snapshot edits are
+    // otherwise compared against off the history
+    undone: Option<Snapshot>,
+    // whether edits made while playing wait for the next bar line, and the
+    // ones waiting, latest last
+    quantize: bool,
+    staged: Vec<(Point, char)>,
+    autosave: Option<Duration>,
+    autosaved: Instant,
+    quit: bool,
+    // the layout last drawn, and whether something other than frames moved
+    // on since, e.g. a key, so the screen has to be drawn whole
+    drawn: Option<Layout>,
+    redraw: bool,
+    // field cells changed by frames since the last render
+    dirty: Vec<Point>,
+    // cells whose operators failed last frame, drawn inverted
+    failed: Vec<Point>,
+}
+
+// `recovered` is a field restored from a recovery file, which starts out
+// unsaved
+pub fn run(config: &Config, path: &Path, project: Project, bpm: u32,
+           recovered: bool, recorder: Option<Recorder>) -> Result<(), String> {
+    let minimap = Minimap::new(&project.field);
+    let mut history = History::new(config.history);
+    history.record(&project.field, 0);
+    let mut app = App {
+        session: Session::new(config, project, bpm)?,
+        theme: Theme::from_config(config)?,
+        path: path.to_owned(),
+        editor: Editor::new(),
+        viewport: Viewport::new(80, 23),
+        minimap,
+        show_minimap: true,
+        beat_grid: config.beat_grid,
+        show_grid: true,
+        show_help: false,
+        show_ports: true,
+        changes: None,
+        prompt: None,
+        last_search: None,
+        keymap: Keymap::from_config(config)?,
+        chord: Vec::new(),
+        pending_register: None,
+        recording: None,
+        following: None,
+        macros: HashMap::new(),
+        replaying: false,
+        message: String::new(),
+        modified: recovered,
+        history,
+        recorded: (bpm, false),
+        undone: None,
+        quantize: config.quantize,
+        staged: Vec::new(),
+        recorder,
+        autosave: Some(Duration::from_secs(config.autosave)).filter(|d| !d.is_zero()),
+        autosaved: Instant::now(),
+        quit: false,
+        drawn: None,
+        redraw: true,
+        dirty: Vec::new(),
+        failed: Vec::new(),
+    };
+
+    if let Some(recorder) = &app.recorder {
+        app.session.ctx.reseed(recorder.seed);
+    }
+
+    let term = Terminal::enter().map_err(|e| format!("terminal: {}", e))?;
+    let keys = Terminal::keys();
+
+    // a panic would otherwise print into the alternate screen and vanish
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| event_loop(&mut app, &keys)));
+    panic::set_hook(default_hook);
+    drop(term);
+
+    match result {
+        Ok(Ok(())) => {
+            project::remove_recovery(&app.path);
+            Ok(())
+        }
+        Ok(Err(e)) => Err(e),
+        Err(payload) => {
+            let msg = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                             .or_else(|| payload.downcast_ref::<String>().cloned())
+                             .unwrap_or_else(|| "unknown error".to_string());
+            let saved = match app.save_recovery() {
+                Ok(()) => format!("the patch was saved to {}",
+                                  project::recovery_path(&app.path).display()),
+                Err(e) => format!("could not save the patch: {}", e),
+            };
+            Err(format!("crashed: {}\n{}", msg, saved))
+        }
+    }
+}
+
+fn event_loop(app: &mut App, keys: &Receiver<Key>) -> Result<(), String> {
+    let mut next = Instant::now() + app.session.frame_duration();
+    let mut size_checked = Instant::now() - SIZE_CHECK_INTERVAL;
+
+    while !app.quit {
+        // stty is a process spawn, so don't ask about the size every frame
+        if size_checked.elapsed() >= SIZE_CHECK_INTERVAL {
+            let (cols, rows) = Terminal::size();
+            app.viewport.resize(cols, rows.saturating_sub(1), &app.session.ctx.field);
+            size_checked = Instant::now();
+        }
+        app.render().map_err(|e| format!("terminal: {}", e))?;
+
+        let now = Instant::now();
+        match keys.recv_timeout(next.saturating_duration_since(now)) {
+            Ok(key) => {
+                app.handle_key(key);
+                app.stage_edits();
+                app.log_edits();
+                app.record();
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                app.tick();
+                app.record();
+                // don't try to catch up on frames missed while stalled
+                next = (next + app.session.frame_duration()).max(now);
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if app.autosave.is_some_and(|every| app.modified && app.autosaved.elapsed() >= every) {
+            if let Err(e) = app.save_recovery() {
+                app.message = e;
+            }
+            app.autosaved = Instant::now();
+        }
+    }
+
+    Ok(())
+}
+
+impl App {
+    fn tick(&mut self) {
+        let ctx = &self.session.ctx;
+        if !self.session.transport.paused && ctx.frame_ct.is_multiple_of(FRAMES_PER_BAR) {
+            let frame = ctx.frame_ct;
+            if let Some(recorder) = &mut self.recorder {
+                for &(pt, glyph) in &self.staged {
+                    if let Err(e) = recorder.log(frame, &Command::Write(pt, glyph)) {
+                        self.message = e;
+                    }
+                }
+            }
+            self.apply_staged();
+        }
+        let mut errors = self.session.tick();
+        self.field_changed();
+        self.minimap.update(&self.session.ctx.field);
+        match &self.session.changed {
+            Some(changed) => self.dirty.extend(changed),
+            None => self.redraw = true,
+        }
+        if let Some(changes) = &mut self.changes {
+            // what's no longer highlighted needs drawing too
+            self.dirty.extend(changes.points());
+            changes.update(&self.session.ctx.field);
+        }
+        // a failing cell needn't change, so both the cells that stopped
+        // failing and the ones that started need drawing
+        self.dirty.append(&mut self.failed);
+        self.failed.extend(self.session.failed());
+        self.dirty.extend(&self.failed);
+        if let Some(e) = errors.pop() {
+            self.message = e;
+        }
+        for req in std::mem::take(&mut self.session.requests) {
+            self.handle_request(req);
+        }
+        self.track();
+    }
+
+    // nothing is kept when the field is as it was
+    fn record(&mut self) {
+        let ctx = &self.session.ctx;
+        self.history.record(&ctx.field, ctx.frame_ct);
+    }
+
+    // what the last key changed, as the commands that would make the same
+    // change: writes for the cells and play, pause or bpm for the transport
+    fn log_edits(&mut self) {
+        let recorder = match &mut self.recorder {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let ctx = &self.session.ctx;
+        let transport = &self.session.transport;
+        let mut cmds = Vec::new();
+        let undone = self.undone.take();
+        // an undo winds the frame count back, but a replay has no way to,
+        // so its edits go down as of the frame it happened on
+        let frame = undone.as_ref().map_or(ctx.frame_ct, |snap| snap.frame);
+        if let Some(snap) = undone.as_ref().or(self.history.latest()) {
+            cmds.extend(snap.changes(&ctx.field).map(|(pt, content)| Command::Write(pt, content.glyph())));
+        }
+        if transport.bpm != self.recorded.0 {
+            cmds.push(Command::Bpm(transport.bpm));
+        }
+        if transport.paused != self.recorded.1 {
+            cmds.push(if transport.paused { Command::Pause } else { Command::Play });
+        }
+        self.recorded = (transport.bpm, transport.paused);
+        for cmd in cmds {
+            if let Err(e) = recorder.log(frame, &cmd) {
+                self.message = e;
+            }
+        }
+    }
+
+    // with quantize on, what the last key changed while playing is taken
+    // back out of the field to wait for the next bar line. anything waiting
+    // goes in straight away once playback stops or quantize is turned off.
+    fn stage_edits(&mut self) {
+        if !self.quantize || self.session.transport.paused {
+            return self.apply_staged();
+        }
+        let snap = match self.history.latest() {
+            Some(snap) => snap,
+            None => return,
+        };
+        let field = &mut self.session.ctx.field;
+        let changes: Vec<_> = snap.changes(field).collect();
+        for (pt, content) in changes {
+            let _ = field.set(pt, snap.get(pt).map_or('\0', |then| then.glyph()));
+            self.staged.retain(|&(at, _)| at != pt);
+            self.staged.push((pt, content.glyph()));
+        }
+    }
+
+    fn apply_staged(&mut self) {
+        for (pt, glyph) in std::mem::take(&mut self.staged) {
+            let _ = self.session.ctx.field.set(pt, glyph);
+            self.dirty.push(pt);
+        }
+    }
+
+    fn staged_at(&self, pt: Point) -> Option<char> {
+        self.staged.iter().rev().find(|&&(at, _)| at == pt).map(|&(_, glyph)| glyph)
+    }
+
+    // back to the field before the last frame or edit. playback stops, or
+    // the next frame would run straight over it. edits still waiting for a
+    // bar line are the last edit, and are just dropped.
+    fn undo(&mut self) {
+        if !self.staged.is_empty() {
+            self.staged.clear();
+            return self.message = "dropped edits waiting for the bar".to_string();
+        }
+        if self.recorder.is_some() {
+            let ctx = &self.session.ctx;
+            self.undone = Some(Snapshot::take(&ctx.field, ctx.frame_ct, self.history.latest()));
+        }
+        let snap = match self.history.step_back() {
+            Some(snap) => snap,
+            None => return self.message = "nothing to undo".to_string(),
+        };
+        let ctx = &mut self.session.ctx;
+        snap.restore(&mut ctx.field);
+        ctx.frame_ct = snap.frame;
+        self.session.transport.paused = true;
+        self.modified = true;
+        self.redraw = true;
+        self.field_changed();
+        self.minimap.update(&self.session.ctx.field);
+        self.editor.clamp(&self.session.ctx.field);
+    }
+
+    fn move_cursor(&mut self, dx: i32, dy: i32) {
+        self.following = None;
+        self.editor.move_by(&self.session.ctx.field, dx, dy);
+    }
+
+    fn toggle_follow(&mut self) {
+        if self.following.take().is_some() {
+            return;
+        }
+        let at = self.editor.cursor;
+        let op = self.session.ctx.field[at].glyph();
+        match self.session.ctx.rules.opdefs().find(op) {
+            Some(opd) if opd.category == Category::Movement => {
+                self.following = Some((op, at, Direction::from_glyph(opd.operator)));
+            }
+            _ => self.message = "not on a moving operator".to_string(),
+        }
+    }
+
+    // finds where the followed operator went this frame: the way it's
+    // heading first, then anywhere next to where it was
+    fn track(&mut self) {
+        let (op, at, heading) = match self.following {
+            Some(following) => following,
+            None => return,
+        };
+        let ahead = heading.map_or(Point::zero(), Direction::to_point);
+        let field = &self.session.ctx.field;
+        let found = [ahead, Point::zero()].iter()
+                        .map(|&offset| at + offset)
+                        .chain(at.neighbors4())
+                        .find(|&pt| field.get(pt) == Some(op));
+        match found {
+            Some(pt) => {
+                self.following = Some((op, pt, heading));
+                self.editor.cursor = pt;
+                self.viewport.follow(pt, field);
+            }
+            None => {
+                self.following = None;
+                self.message = format!("lost track of {}", op);
+            }
+        }
+    }
+
+    fn screen_to_field(&self, x: usize, y: usize) -> Option<Point> {
+        let pt = self.viewport.to_field(x, y);
+        if self.session.ctx.field.point_in_bounds(pt) { Some(pt) } else { None }
+    }
+
+    fn pan(&mut self, dx: i32, dy: i32) {
+        self.viewport.pan(dx, dy, &self.session.ctx.field);
+    }
+
+    // click places the cursor, dragging selects a block from where the
+    // button went down, and the wheel pans (sideways with shift)
+    fn handle_mouse(&mut self, mouse: Mouse) {
+        match mouse {
+            Mouse::Press(x, y) => {
+                if let Some(pt) = self.screen_to_field(x, y) {
+                    if self.editor.mode == Mode::Visual {
+                        self.editor.mode = Mode::Normal;
+                    }
+                    self.editor.cursor = pt;
+                }
+            }
+            Mouse::Drag(x, y) => {
+                if let Some(pt) = self.screen_to_field(x, y) {
+                    if self.editor.mode == Mode::Normal {
+                        self.editor.start_selection();
+                    }
+                    self.editor.cursor = pt;
+                }
+            }
+            Mouse::Release(..) => {}
+            Mouse::ScrollUp { shift: false } => self.pan(0, -3),
+            Mouse::ScrollDown { shift: false } => self.pan(0, 3),
+            Mouse::ScrollUp { shift: true } => self.pan(-3, 0),
+            Mouse::ScrollDown { shift: true } => self.pan(3, 0),
+        }
+    }
+
+    fn handle_prompt_key(&mut self, key: Key) {
+        let (leader, text) = match &mut self.prompt {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        match key {
+            Key::Esc => self.prompt = None,
+            Key::Backspace if text.is_empty() => self.prompt = None,
+            Key::Backspace => {
+                text.pop();
+            }
+            Key::Char(ch) => text.push(ch),
+            Key::Enter => {
+                let (leader, text) = (*leader, text.clone());
+                self.prompt = None;
+                if leader == '/' && !text.is_empty() {
+                    self.last_search = Some(text.clone());
+                    self.search(&text);
+                }
+                if leader == ':' && !text.trim().is_empty() {
+                    self.run_command(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // a single character searches for that glyph, anything longer for
+    // operators whose long name starts with it
+    fn search(&mut self, query: &str) {
+        let mut chars = query.chars();
+        let glyphs: Vec<char> = match (chars.next(), chars.next()) {
+            (Some(ch), None) => vec![ch],
+            _ => self.session.ctx.rules.opdefs().iter()
+                                     .filter(|opd| opd.long_name.starts_with(query))
+                                     .map(|opd| opd.operator)
+                                     .collect(),
+        };
+        if glyphs.is_empty() {
+            self.message = format!("no operator named {}", query);
+        } else if !self.editor.find_next(&self.session.ctx.field, &glyphs) {
+            self.message = format!("{} not found", query);
+        }
+    }
+
+    fn run_command(&mut self, line: &str) {
+        match self.session.run_command(line) {
+            Ok(Some(req)) => self.handle_request(req),
+            Ok(None) => {}
+            Err(e) => self.message = e,
+        }
+        self.field_changed();
+    }
+
+    fn handle_request(&mut self, req: Request) {
+        match req {
+            Request::Write(path) => {
+                if let Some(path) = path {
+                    self.path = PathBuf::from(path);
+                }
+                self.save();
+            }
+            Request::Quit { force: true } => self.quit = true,
+            Request::Quit { force: false } => self.perform(Action::Quit, 0),
+            Request::Goto(pt) => {
+                let field = &self.session.ctx.field;
+                if !field.point_in_bounds(pt) {
+                    self.message = format!("{} is outside the field", pt);
+                    return;
+                }
+                self.following = None;
+                self.editor.cursor = pt;
+                self.viewport.follow(pt, field);
+            }
+        }
+    }
+
+    // commands can swap the field for a different sized one
+    fn field_changed(&mut self) {
+        let field = &self.session.ctx.field;
+        if !self.minimap.covers(field) {
+            self.redraw = true;
+            self.minimap = Minimap::new(field);
+            self.modified = true;
+            self.editor.clamp(field);
+            self.viewport.follow(self.editor.cursor, field);
+        }
+    }
+
+    fn save_recovery(&self) -> Result<(), String> {
+        project::save_recovery(&self.path, &self.session.ctx.field, &self.session.header())
+    }
+
+    fn save(&mut self) {
+        match project::save(&self.path, &self.session.ctx.field, &self.session.header()) {
+            Ok(()) => {
+                project::remove_recovery(&self.path);
+                self.modified = false;
+                self.message = format!("wrote {}", self.path.display());
+            }
+            Err(e) => self.message = e,
+        }
+    }
+
+    fn handle_key(&mut self, key: Key) {
+        self.redraw = true;
+        self.message.clear();
+        // clicks land on screen positions, which mean nothing on replay
+        if let Some((_, keys)) = &mut self.recording {
+            if !matches!(key, Key::Mouse(_)) {
+                keys.push(key);
+            }
+        }
+        self.handle_edit_key(key);
+        self.minimap.update(&self.session.ctx.field);
+        if !matches!(key, Key::Mouse(_)) {
+            self.viewport.follow(self.editor.cursor, &self.session.ctx.field);
+        }
+    }
+
+    // `Qa` records keys into register a until the next record key, `@a`
+    // plays them back from the cursor
+    fn handle_register_key(&mut self, leader: char, key: Key) {
+        let reg = match key {
+            Key::Char(ch) if ch.is_ascii_alphanumeric() => ch,
+            Key::Esc => return,
+            _ => {
+                self.message = "registers are a-z and 0-9".to_string();
+                return;
+            }
+        };
+        if leader == 'Q' {
+            self.recording = Some((reg, Vec::new()));
+            return;
+        }
+
+        let keys = match self.macros.get(&reg) {
+            Some(keys) => keys.clone(),
+            None => {
+                self.message = format!("register {} is empty", reg);
+                return;
+            }
+        };
+        // a macro that plays itself would never finish
+        if self.replaying {
+            return;
+        }
+        self.replaying = true;
+        for key in keys {
+            self.handle_edit_key(key);
+        }
+        self.replaying = false;
+    }
+
+    // the keys that stopped the recording were recorded too, drop them
+    fn stop_recording(&mut self, chord_len: usize) {
+        if let Some((reg, mut keys)) = self.recording.take() {
+            keys.truncate(keys.len().saturating_sub(chord_len));
+            self.message = format!("recorded {} keys into {}", keys.len(), reg);
+            self.macros.insert(reg, keys);
+        }
+    }
+
+    fn handle_edit_key(&mut self, key: Key) {
+        let force_quit = self.keymap.is_bound(key, Action::ForceQuit);
+        if self.prompt.is_some() && !force_quit {
+            return self.handle_prompt_key(key);
+        }
+        if self.show_help && !force_quit {
+            if let Key::Char('?') | Key::Esc | Key::Char('q') = key {
+                self.show_help = false;
+            }
+            return;
+        }
+        if let Some(leader) = self.pending_register.take() {
+            if !force_quit {
+                return self.handle_register_key(leader, key);
+            }
+        }
+        if let Key::Mouse(mouse) = key {
+            return self.handle_mouse(mouse);
+        }
+
+        let typing = matches!(self.editor.mode, Mode::Replace | Mode::InsertRow | Mode::InsertColumn);
+        if typing && self.chord.is_empty() {
+            let field = &mut self.session.ctx.field;
+            match key {
+                Key::Char(ch) => {
+                    self.editor.type_glyph(field, ch);
+                    self.modified = true;
+                    return;
+                }
+                Key::Backspace => {
+                    self.editor.backspace(field);
+                    self.modified = true;
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        self.chord.push(key);
+        match self.keymap.lookup(&self.chord) {
+            Lookup::Actions(actions) => {
+                let chord_len = self.chord.len();
+                self.chord.clear();
+                let mode = self.editor.mode;
+                if let Some(&action) = actions.iter().find(|a| a.applies_in(mode)) {
+                    self.perform(action, chord_len);
+                }
+            }
+            Lookup::Prefix => {}
+            Lookup::Unbound => self.chord.clear(),
+        }
+    }
+
+    fn perform(&mut self, action: Action, chord_len: usize) {
+        let field = &mut self.session.ctx.field;
+        match action {
+            Action::MoveLeft => self.move_cursor(-1, 0),
+            Action::MoveDown => self.move_cursor(0, 1),
+            Action::MoveUp => self.move_cursor(0, -1),
+            Action::MoveRight => self.move_cursor(1, 0),
+            Action::Replace => self.editor.mode = Mode::Replace,
+            Action::InsertRow => self.editor.mode = Mode::InsertRow,
+            Action::InsertColumn => self.editor.mode = Mode::InsertColumn,
+            Action::Delete => {
+                self.editor.delete(field);
+                self.modified = true;
+            }
+            Action::Select => self.editor.start_selection(),
+            Action::Paste => {
+                if !self.editor.start_paste() {
+                    self.message = "clipboard is empty".to_string();
+                }
+            }
+            Action::Copy => self.editor.copy(field),
+            Action::Cut => {
+                self.editor.cut(field);
+                self.modified = true;
+            }
+            Action::Erase => {
+                self.editor.erase(field);
+                self.modified = true;
+            }
+            Action::Rotate => {
+                self.editor.rotate(field);
+                self.modified = true;
+            }
+            Action::FlipHorizontal => {
+                self.editor.flip(field, Axis::Horizontal);
+                self.modified = true;
+            }
+            Action::FlipVertical => {
+                self.editor.flip(field, Axis::Vertical);
+                self.modified = true;
+            }
+            Action::Commit => {
+                self.editor.commit_paste(field);
+                self.modified = true;
+            }
+            Action::Cancel => self.editor.mode = Mode::Normal,
+            Action::AddCursor => self.editor.toggle_cursor(),
+            Action::ClearCursors => self.editor.clear_cursors(),
+            Action::Record if self.recording.is_some() => self.stop_recording(chord_len),
+            Action::Record => self.pending_register = Some('Q'),
+            Action::PlayMacro => self.pending_register = Some('@'),
+            Action::Follow => self.toggle_follow(),
+            Action::Diff => {
+                self.changes = match self.changes {
+                    Some(_) => None,
+                    None => Some(Changes::new(field)),
+                };
+            }
+            Action::Minimap => self.show_minimap = !self.show_minimap,
+            Action::Quantize => {
+                self.quantize = !self.quantize;
+                self.message = if self.quantize {
+                    "edits made while playing wait for the next bar".to_string()
+                } else {
+                    "edits go in straight away".to_string()
+                };
+            }
+            Action::Grid if self.beat_grid == 0 => {
+                self.message = "no beat grid, set editor.beat_grid in the config".to_string();
+            }
+            Action::Grid => self.show_grid = !self.show_grid,
+            Action::Help => self.show_help = true,
+            Action::Ports => self.show_ports = !self.show_ports,
+            Action::Search => self.prompt = Some(('/', String::new())),
+            Action::Command => self.prompt = Some((':', String::new())),
+            Action::SearchNext => match self.last_search.clone() {
+                Some(query) => self.search(&query),
+                None => self.message = "no previous search".to_string(),
+            },
+            Action::PlayPause => self.session.transport.paused = !self.session.transport.paused,
+            Action::Save => self.save(),
+            Action::Zoom => self.viewport.cycle_zoom(field),
+            Action::Quit => {
+                if self.modified {
+                    self.message = "unsaved changes, save or force quit".to_string();
+                } else {
+                    self.quit = true;
+                }
+            }
+            Action::ForceQuit => self.quit = true,
+            Action::Undo => self.undo(),
+        }
+    }
+
+    fn color(&self, op: char) -> &str {
+        self.theme.sgr(self.session.ctx.rules.opdefs().find(op).map(|opd| opd.category), op)
+    }
+
+    fn status_line(&self, cols: usize) -> String {
+        let cursor = self.editor.cursor;
+        let op = self.session.ctx.field[cursor].glyph();
+        let under = match self.session.ctx.rules.opdefs().find(op) {
+            Some(opd) if opd.ports.is_empty() => format!("{} {}", op, opd.long_name),
+            Some(opd) => format!("{} {} ({})", op, opd.long_name, opd.port_names()),
+            None => String::new(),
+        };
+
+        let zoom = if self.viewport.zoom > 1 {
+            format!(" | zoom {}x", self.viewport.zoom)
+        } else {
+            String::new()
+        };
+        let cursors = match self.editor.cursors().count() {
+            1 => String::new(),
+            n => format!(" ({} cursors)", n),
+        };
+        let recording = match &self.recording {
+            Some((reg, _)) => format!(" recording {}", reg),
+            None => String::new(),
+        };
+        let recording = match self.following {
+            Some((op, _, _)) => format!("{} following {}", recording, op),
+            None => recording,
+        };
+        let recording = match self.staged.len() {
+            _ if !self.quantize => recording,
+            0 => format!("{} quantize", recording),
+            n => format!("{} quantize ({} waiting)", recording, n),
+        };
+        let section = match self.session.arrangement.playing() {
+            Some(name) => format!(" | {}", name),
+            None => String::new(),
+        };
+        let mut ret = format!(" {}{}{} | frame {} | {} bpm{}{} | {}{}{} | {}",
+                              self.editor.mode.name(),
+                              if self.modified { " +" } else { "" },
+                              recording,
+                              self.session.ctx.frame_ct,
+                              self.session.transport.bpm,
+                              if self.session.transport.paused { " paused" } else { "" },
+                              section,
+                              cursor,
+                              cursors,
+                              zoom,
+                              under);
+        if !self.message.is_empty() {
+            ret.push_str(" | ");
+            ret.push_str(&self.message);
+        }
+        ret.chars().take(cols).collect()
+    }
+
+    // every registered operator, so anything added to the table shows up
+    fn render_help(&self, buf: &mut String, cols: usize, rows: usize) {
+        let mut opdefs: Vec<_> = self.session.ctx.rules.opdefs().iter().collect();
+        opdefs.sort_by_key(|opd| (opd.category as u8, opd.operator));
+
+        let mut lines = vec![format!("{} operators (? or esc to close)", opdefs.len())];
+        for category in Category::ALL.iter() {
+            let group: Vec<_> = opdefs.iter().filter(|opd| opd.category == *category).collect();
+            if group.is_empty() {
+                continue;
+            }
+            lines.push(String::new());
+            lines.push(category.name().to_string());
+            for opd in group {
+                let aliases: String = opd.aliases.iter().collect();
+                let line = format!("  {} {:<3} {:<10} {:<37} {}", opd.operator, aliases,
+                                   opd.long_name, opd.doc, opd.tags.join(" "));
+                lines.push(line.trim_end().to_string());
+            }
+        }
+
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) + 4;
+        let width = width.min(cols);
+        let top = rows.saturating_sub(lines.len() + 2) / 2 + 1;
+        let left = cols.saturating_sub(width) / 2 + 1;
+
+        let blank = " ".repeat(width);
+        let _ = write!(buf, "\x1b[0;30;47m\x1b[{};{}H{}", top, left, blank);
+        for (i, line) in lines.iter().enumerate().take(rows.saturating_sub(2)) {
+            let text: String = format!("  {}", line).chars().take(width).collect();
+            let _ = write!(buf, "\x1b[{};{}H{:<w$}", top + 1 + i, left, text, w = width);
+        }
+        let _ = write!(buf, "\x1b[{};{}H{}\x1b[0m", top + 1 + lines.len().min(rows.saturating_sub(2)),
+                       left, blank);
+    }
+
+    // background for the screen column starting at field column x. grid
+    // columns fall every beat_grid frames' worth of cells, and flash while
+    // the clock is on a beat.
+    fn grid_sgr(&self, x: i32) -> Option<&str> {
+        let n = self.beat_grid as i32;
+        if !self.show_grid || n == 0 || x % n >= self.viewport.zoom as i32 {
+            return None;
+        }
+        let sgr = if self.on_beat() { &self.theme.colors.beat } else { &self.theme.colors.grid };
+        if sgr.is_empty() { None } else { Some(sgr) }
+    }
+
+    fn on_beat(&self) -> bool {
+        let n = self.beat_grid as u32;
+        self.show_grid && n > 0 && !self.session.transport.paused
+            && self.session.ctx.frame_ct.is_multiple_of(n)
+    }
+
+    // live values of the ports of the operator under the cursor, for the
+    // bottom right corner: the box's top, left and width, and its lines
+    fn ports_box(&self, cols: usize, rows: usize) -> Option<(usize, usize, usize, Vec<String>)> {
+        let field = &self.session.ctx.field;
+        let at = self.editor.cursor;
+        let op = field[at].glyph();
+        let opd = match self.session.ctx.rules.opdefs().find(op) {
+            Some(opd) if !opd.ports.is_empty() => opd,
+            _ => return None,
+        };
+
+        let mut lines = vec![format!("{} {}", op, opd.long_name)];
+        for port in &opd.ports {
+            // outputs are marked so they don't read as values the op uses
+            let name = if port.output { format!("{} >", port.name) } else { port.name.clone() };
+            let glyph = field.get(at + port.offset).unwrap_or('\0');
+            // with the value operators read it as, when it has one
+            let value = |ch| codec::decode(ch).map_or_else(String::new, |v| format!("  {}", v));
+            lines.push(match (glyph, port.default) {
+                ('\0', Some(ch)) => format!("{:<10} .{}", name, value(ch)),
+                ('\0', None) => format!("{:<10} .", name),
+                (ch, _) => format!("{:<10} {}{}", name, ch, value(ch)),
+            });
+        }
+        let banged = at.neighbors4().iter().any(|&pt| {
+            field.slot(pt).is_some_and(|slot| slot.glyph() == '*')
+        });
+        lines.push(format!("{:<10} {}", "banged", if banged { "yes" } else { "no" }));
+
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) + 2;
+        if width >= cols || lines.len() >= rows {
+            return None;
+        }
+        Some((rows - lines.len() + 1, cols - width + 1, width, lines))
+    }
+
+    // the screen cell showing the block of field cells at `pt`, styled
+    fn render_cell(&self, buf: &mut String, pt: Point) {
+        let field = &self.session.ctx.field;
+        let table = self.session.ctx.rules.opdefs();
+        let preview = self.editor.preview_at(pt).or_else(|| self.staged_at(pt));
+        let op = preview.unwrap_or_else(|| {
+            self.viewport.block_glyph(field, pt, |op| table.find(op).is_some())
+        });
+        let ch = self.theme.glyph(op);
+        let _ = write!(buf, "\x1b[0;{}m", self.color(op));
+        let changed = &self.theme.colors.changed;
+        let changed = self.changes.as_ref()
+                          .filter(|c| !changed.is_empty() && c.is_changed(pt))
+                          .map(|_| changed.as_str());
+        if let Some(bg) = changed.or_else(|| self.grid_sgr(pt.x)) {
+            let _ = write!(buf, "\x1b[{}m", bg);
+        }
+        if self.editor.cursors().any(|c| self.viewport.block_contains(pt, c))
+            || self.editor.is_selected(pt)
+            || self.failed.iter().any(|&f| self.viewport.block_contains(pt, f)) {
+            let _ = write!(buf, "\x1b[7m{}\x1b[0m", ch);
+        } else if preview.is_some() {
+            let _ = write!(buf, "\x1b[4m{}\x1b[0m", ch);
+        } else {
+            buf.push(ch);
+        }
+    }
+
+    // draws the whole screen after anything but frames went by, otherwise
+    // just the cells the frames changed, with the overlays on top again
+    fn render(&mut self) -> io::Result<()> {
+        let (cols, rows) = (self.viewport.cols, self.viewport.rows);
+        let ports = if self.show_ports { self.ports_box(cols, rows) } else { None };
+        let layout = Layout {
+            origin: self.viewport.origin,
+            zoom: self.viewport.zoom,
+            cols,
+            rows,
+            on_beat: self.on_beat(),
+            ports: ports.as_ref().map(|(top, left, width, lines)| (*top, *left, *width, lines.len())),
+        };
+        let field = &self.session.ctx.field;
+        let mut buf = String::new();
+
+        let moved = match &self.drawn {
+            Some(drawn) => Layout { on_beat: layout.on_beat, ..drawn.clone() } != layout,
+            None => true,
+        };
+        if self.redraw || moved {
+            buf.push_str("\x1b[H");
+            for y in 0..rows {
+                for x in 0..cols {
+                    let pt = self.viewport.to_field(x, y);
+                    if !field.point_in_bounds(pt) {
+                        break;
+                    }
+                    self.render_cell(&mut buf, pt);
+                }
+                buf.push_str("\x1b[0m\x1b[K\r\n");
+            }
+            buf.push_str("\x1b[J");
+        } else {
+            let mut cells: Vec<(usize, usize)> = self.dirty.iter()
+                                                     .filter_map(|&pt| self.viewport.to_screen(pt))
+                                                     .filter(|&(x, y)| x < cols && y < rows)
+                                                     .collect();
+            if self.drawn.as_ref().is_some_and(|drawn| drawn.on_beat != layout.on_beat) {
+                for x in (0..cols).filter(|&x| self.grid_sgr(self.viewport.to_field(x, 0).x).is_some()) {
+                    cells.extend((0..rows).map(|y| (x, y))
+                                          .filter(|&(x, y)| field.point_in_bounds(self.viewport.to_field(x, y))));
+                }
+            }
+            cells.sort_unstable();
+            cells.dedup();
+            for (x, y) in cells {
+                let _ = write!(buf, "\x1b[{};{}H", y + 1, x + 1);
+                self.render_cell(&mut buf, self.viewport.to_field(x, y));
+            }
+            buf.push_str("\x1b[0m");
+        }
+
+        // only worth the screen space when the field doesn't fit
+        let (span_w, span_h) = self.viewport.span();
+        let fits = field.slots.width as i32 <= span_w && field.slots.height as i32 <= span_h;
+        if self.show_minimap && !fits && self.minimap.cols < cols && self.minimap.rows < rows {
+            self.minimap.render(&mut buf, 1, cols - self.minimap.cols + 1, &self.viewport);
+        }
+        if let Some((top, left, width, lines)) = ports {
+            for (i, line) in lines.iter().enumerate() {
+                let _ = write!(buf, "\x1b[0;30;47m\x1b[{};{}H {:<w$}\x1b[0m", top + i, left, line,
+                               w = width - 1);
+            }
+        }
+        if self.show_help {
+            self.render_help(&mut buf, cols, rows);
+        }
+
+        match &self.prompt {
+            Some((leader, text)) => {
+                let _ = write!(buf, "\x1b[{};1H{}{}\x1b[K", rows + 1, leader, text);
+            }
+            None => {
+                let _ = write!(buf, "\x1b[{};1H\x1b[7m{}\x1b[K\x1b[0m", rows + 1,
+                               self.status_line(cols));
+            }
+        }
+
+        self.drawn = Some(layout);
+        self.redraw = false;
+        self.dirty.clear();
+
+        let mut out = io::stdout();
+        out.write_all(buf.as_bytes())?;
+        out.flush()
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+
+// note numbers read as steps of a scale other than 12-tet, for sending on
+// their own mpe channels bent to pitch. note 60 stays middle c and every
+// note above or below it is a step of the scale. the scale is an equal
+// division of the octave, `edo:31`, or a scala file:
+//
+//     ! meantone.scl
+//     quarter-comma meantone, 5 steps shown
+//     5
+//     193.157
+//     386.314
+//     503.422
+//     696.579
+//     2/1
+//
+// pitches are cents when there's a `.`, ratios otherwise, and the last one
+// is the period the scale repeats at.
+
+// the note that plays at its own pitch
+const BASE_NOTE: i32 = 60;
+
+// semitones either way a full pitch bend goes, mpe's default for the
+// channels notes play on
+pub const BEND_RANGE: f64 = 48.0;
+
+#[derive(Clone, Debug)]
+pub struct Tuning {
+    // cents above the base for steps 1 to n, the last being the period
+    steps: Vec<f64>,
+}
+
+impl Tuning {
+    // `edo:<n>` or the path to a .scl file
+    pub fn load(spec: &str) -> Result<Self, String> {
+        if let Some(n) = spec.strip_prefix("edo:") {
+            return match n.parse::<u32>() {
+                Ok(n) if (1..=1200).contains(&n) => Ok(Self::edo(n)),
+                _ => Err(format!("tuning: invalid division `{}`, expected 1-1200", n)),
+            };
+        }
+        let src = fs::read_to_string(spec).map_err(|e| format!("tuning {}: {}", spec, e))?;
+        Self::parse_scala(&src).map_err(|e| format!("tuning {}: {}", spec, e))
+    }
+
+    pub fn edo(n: u32) -> Self {
+        Self { steps: (1..=n).map(|i| 1200.0 * i as f64 / n as f64).collect() }
+    }
+
+    pub fn parse_scala(src: &str) -> Result<Self, String> {
+        let mut lines = src.lines().map(str::trim).filter(|l| !l.starts_with('!'));
+        lines.next().ok_or_else(|| "empty scala file".to_string())?;
+        let count = lines.next().unwrap_or("");
+        let count: usize = count.parse().map_err(|_| format!("invalid note count `{}`", count))?;
+        let steps = lines.take(count).map(pitch).collect::<Result<Vec<_>, _>>()?;
+        match steps.last() {
+            _ if steps.len() < count => Err(format!("expected {} pitches, found {}", count, steps.len())),
+            Some(&period) if period > 0.0 => Ok(Self { steps }),
+            _ => Err("the last pitch, the period, has to be above the first".to_string()),
+        }
+    }
+
+    // the 12-tet note nearest a note's pitch, and the bend from it there
+    // as midi's 14 bits, 8192 being none
+    pub fn pitch(&self, note: u8) -> (u8, u16) {
+        let degree = note as i32 - BASE_NOTE;
+        let n = self.steps.len() as i32;
+        let (periods, step) = (degree.div_euclid(n), degree.rem_euclid(n));
+        let period = self.steps[self.steps.len() - 1];
+        let cents = periods as f64 * period + if step == 0 { 0.0 } else { self.steps[step as usize - 1] };
+        let semitones = BASE_NOTE as f64 + cents / 100.0;
+        let nearest = semitones.round().clamp(0.0, 127.0);
+        let bend = 8192.0 + (semitones - nearest) / BEND_RANGE * 8192.0;
+        (nearest as u8, bend.round().clamp(0.0, 16383.0) as u16)
+    }
+}
+
+// a scala pitch: cents, or a ratio that may leave out its `/1`
+fn pitch(line: &str) -> Result<f64, String> {
+    let word = line.split_whitespace().next().unwrap_or("");
+    let invalid = || format!("invalid pitch `{}`", word);
+    if word.contains('.') {
+        return word.parse().map_err(|_| invalid());
+    }
+    let (num, den) = word.split_once('/').unwrap_or((word, "1"));
+    match (num.parse::<f64>(), den.parse::<f64>()) {
+        (Ok(num), Ok(den)) if num > 0.0 && den > 0.0 => Ok(1200.0 * (num / den).log2()),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEANTONE: &str = "! meantone.scl
+!
+quarter-comma meantone, 5 steps shown
+ 5
+ 193.157
+ 386.314 a major third
+ 503.422
+ 696.579
+ 2
+";
+
+    #[test]
+    fn twelve_tet_is_unbent() {
+        let tuning = Tuning::edo(12);
+        for note in 0..128 {
+            assert_eq!(tuning.pitch(note), (note, 8192));
+        }
+    }
+
+    #[test]
+    fn steps_bend_to_their_pitch() {
+        let quarter_tones = Tuning::edo(24);
+        // a quarter tone up rounds to the semitone and bends down to it
+        assert_eq!(quarter_tones.pitch(61), (61, 8107));
+        assert_eq!(quarter_tones.pitch(84), (72, 8192));
+        assert_eq!(quarter_tones.pitch(36), (48, 8192));
+        assert_eq!(quarter_tones.pitch(59), (60, 8107));
+
+        let meantone = Tuning::parse_scala(MEANTONE).unwrap();
+        assert_eq!(meantone.pitch(62), (64, 8169));
+        assert_eq!(meantone.pitch(65), (72, 8192));
+        assert_eq!(meantone.pitch(55), (48, 8192));
+    }
+
+    #[test]
+    fn pitches_past_midi_are_held_at_its_ends() {
+        let tuning = Tuning::edo(5);
+        assert_eq!(tuning.pitch(127).0, 127);
+        assert_eq!(tuning.pitch(0).0, 0);
+    }
+
+    #[test]
+    fn scala_pitches_are_cents_or_ratios() {
+        let tuning = Tuning::parse_scala("fifths\n2\n3/2\n1200.0\n").unwrap();
+        assert_eq!(tuning.steps.len(), 2);
+        assert!((tuning.steps[0] - 701.955).abs() < 0.001);
+        assert_eq!(tuning.steps[1], 1200.0);
+    }
+
+    #[test]
+    fn bad_scala_files_are_refused() {
+        for src in ["", "! only a comment\n", "no count\n", "too few\n3\n100.0\n2/1\n",
+                    "bad pitch\n1\nfoo\n", "no period\n1\n0.0\n", "below zero\n1\n-2/1\n",
+                    "nothing\n0\n"] {
+            assert!(Tuning::parse_scala(src).is_err(), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn divisions_are_checked() {
+        assert!(Tuning::load("edo:31").is_ok());
+        for spec in ["edo:0", "edo:1201", "edo:x", "edo:"] {
+            assert!(Tuning::load(spec).is_err(), "{}", spec);
+        }
+    }
+}
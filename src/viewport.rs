@@ -0,0 +1,120 @@
+use lyza::{CellLike, Field, Point};
+
+// the window of the field shown on screen. at zoom n every screen cell
+// stands for an n×n block of field cells.
+
+pub const ZOOM_LEVELS: [usize; 3] = [1, 2, 4];
+
+pub struct Viewport {
+    pub origin: Point,
+    pub cols: usize,
+    pub rows: usize,
+    pub zoom: usize,
+}
+
+impl Viewport {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            origin: Point::zero(),
+            cols,
+            rows,
+            zoom: 1,
+        }
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize, field: &Field) {
+        self.cols = cols;
+        self.rows = rows;
+        self.clamp(field);
+    }
+
+    pub fn cycle_zoom(&mut self, field: &Field) {
+        let at = ZOOM_LEVELS.iter().position(|&z| z == self.zoom).unwrap_or(0);
+        self.zoom = ZOOM_LEVELS[(at + 1) % ZOOM_LEVELS.len()];
+        self.origin = Point::new(self.origin.x / self.zoom as i32 * self.zoom as i32,
+                                 self.origin.y / self.zoom as i32 * self.zoom as i32);
+        self.clamp(field);
+    }
+
+    // field cells covered horizontally and vertically
+    pub fn span(&self) -> (i32, i32) {
+        ((self.cols * self.zoom) as i32, (self.rows * self.zoom) as i32)
+    }
+
+    pub fn pan(&mut self, dx: i32, dy: i32, field: &Field) {
+        let z = self.zoom as i32;
+        self.origin = self.origin.translate(dx * z, dy * z);
+        self.clamp(field);
+    }
+
+    // scrolls just far enough to bring pt on screen
+    pub fn follow(&mut self, pt: Point, field: &Field) {
+        let (w, h) = self.span();
+        let z = self.zoom as i32;
+        if pt.x < self.origin.x {
+            self.origin.x = pt.x / z * z;
+        } else if pt.x >= self.origin.x + w {
+            self.origin.x = (pt.x - w + z) / z * z;
+        }
+        if pt.y < self.origin.y {
+            self.origin.y = pt.y / z * z;
+        } else if pt.y >= self.origin.y + h {
+            self.origin.y = (pt.y - h + z) / z * z;
+        }
+        self.clamp(field);
+    }
+
+    fn clamp(&mut self, field: &Field) {
+        let (w, h) = self.span();
+        let z = self.zoom as i32;
+        let max_x = (field.slots.width as i32 - w).max(0);
+        let max_y = (field.slots.height as i32 - h).max(0);
+        // round up so the last cells stay reachable at coarse zoom
+        let max_x = (max_x + z - 1) / z * z;
+        let max_y = (max_y + z - 1) / z * z;
+        self.origin.x = self.origin.x.min(max_x).max(0);
+        self.origin.y = self.origin.y.min(max_y).max(0);
+    }
+
+    // top left field cell of the block shown at a screen position
+    pub fn to_field(&self, x: usize, y: usize) -> Point {
+        self.origin.translate((x * self.zoom) as i32, (y * self.zoom) as i32)
+    }
+
+    // the screen position showing a field cell, if it's in view
+    pub fn to_screen(&self, pt: Point) -> Option<(usize, usize)> {
+        if !self.contains(pt) {
+            return None;
+        }
+        let z = self.zoom as i32;
+        Some((((pt.x - self.origin.x) / z) as usize, ((pt.y - self.origin.y) / z) as usize))
+    }
+
+    pub fn contains(&self, pt: Point) -> bool {
+        let (w, h) = self.span();
+        pt.x >= self.origin.x && pt.y >= self.origin.y
+            && pt.x < self.origin.x + w && pt.y < self.origin.y + h
+    }
+
+    // the glyph to draw for the block at `top_left`: an operator if there is
+    // one, otherwise any data, otherwise empty
+    pub fn block_glyph(&self, field: &Field, top_left: Point, is_op: impl Fn(char) -> bool) -> char {
+        let mut data = '\0';
+        for slot in field.slots.view(top_left, (self.zoom, self.zoom)).iter() {
+            let op = slot.glyph();
+            if is_op(op) {
+                return op;
+            }
+            if data == '\0' {
+                data = op;
+            }
+        }
+        data
+    }
+
+    pub fn block_contains(&self, top_left: Point, pt: Point) -> bool {
+        let z = self.zoom as i32;
+        pt.x >= top_left.x && pt.y >= top_left.y
+            && pt.x < top_left.x + z && pt.y < top_left.y + z
+    }
+}
@@ -0,0 +1,336 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use crate::control::{Command, SharedStatus};
+
+// a websocket control surface. every text message is one command line, the
+// same as the fifo takes, plus two queries:
+//
+//     field        the grid as plain text
+//     state        frame, bpm, transport and size as json
+//
+// commands are answered with `ok` or `error: ...`.
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+// payloads past this close the connection
+const MAX_FRAME: usize = 64 * 1024;
+
+pub fn spawn(addr: &str, status: SharedStatus, tx: Sender<Command>) -> Result<(), String> {
+    let listener = TcpListener::bind(addr).map_err(|e| format!("websocket {}: {}", addr, e))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let (status, tx) = (status.clone(), tx.clone());
+            thread::spawn(move || {
+                let _ = serve(stream, &status, &tx);
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn serve(stream: TcpStream, status: &SharedStatus, tx: &Sender<Command>) -> io::Result<()> {
+    handshake(&stream, &stream)?;
+    converse(&stream, &stream, status, tx)
+}
+
+// answers frames until the client closes the connection
+fn converse(mut reader: impl Read, mut writer: impl Write, status: &SharedStatus,
+            tx: &Sender<Command>) -> io::Result<()> {
+    loop {
+        let (opcode, payload) = read_frame(&mut reader)?;
+        match opcode {
+            OP_TEXT => {
+                let line = String::from_utf8_lossy(&payload);
+                let reply = answer(line.trim(), status, tx);
+                write_frame(&mut writer, OP_TEXT, reply.as_bytes())?;
+            }
+            OP_PING => write_frame(&mut writer, OP_PONG, &payload)?,
+            OP_CLOSE => return write_frame(&mut writer, OP_CLOSE, &payload),
+            _ => {}
+        }
+    }
+}
+
+fn answer(line: &str, status: &SharedStatus, tx: &Sender<Command>) -> String {
+    let status = status.lock().unwrap().clone();
+    match line {
+        "field" => return status.grid,
+        "state" => return status.json(),
+        _ => {}
+    }
+
+    let cmd = match line.parse() {
+        Ok(cmd) => cmd,
+        Err(e) => return format!("error: {}", e),
+    };
+    if let Err(e) = status.check(&cmd) {
+        return format!("error: {}", e);
+    }
+    match tx.send(cmd) {
+        Ok(()) => "ok".to_string(),
+        Err(_) => "error: engine stopped".to_string(),
+    }
+}
+
+fn handshake(reader: impl Read, mut writer: impl Write) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            writer.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+            return Err(io::Error::other("not a websocket request"));
+        }
+    };
+    let accept = base64(&sha1(format!("{}{}", key, GUID).as_bytes()));
+    write!(writer,
+           "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Accept: {}\r\n\r\n",
+           accept)
+}
+
+// clients always mask their frames. fragmented messages aren't supported,
+// commands are far smaller than any sensible fragment size.
+fn read_frame(mut stream: impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head)?;
+    let opcode = head[0] & 0x0f;
+    let masked = head[1] & 0x80 != 0;
+
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut ext = [0; 2];
+            stream.read_exact(&mut ext)?;
+            u16::from_be_bytes(ext) as usize
+        }
+        127 => {
+            let mut ext = [0; 8];
+            stream.read_exact(&mut ext)?;
+            u64::from_be_bytes(ext) as usize
+        }
+        n => n as usize,
+    };
+    if len > MAX_FRAME {
+        return Err(io::Error::other("frame too large"));
+    }
+
+    let mut mask = [0; 4];
+    if masked {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((opcode, payload))
+}
+
+fn write_frame(mut stream: impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
+
+// only needed for the handshake's accept key
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for chunk in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([chunk[4 * i], chunk[4 * i + 1], chunk[4 * i + 2], chunk[4 * i + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+
+    let mut ret = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        ret[4 * i..4 * i + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    ret
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut ret = String::new();
+    for group in data.chunks(3) {
+        let n = group.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                ret.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    // a client's frame, masked as clients must
+    fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        write_frame(&mut raw, opcode, payload).unwrap();
+        raw[1] |= 0x80;
+        let at = raw.len() - payload.len();
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        raw.splice(at..at, mask);
+        for (i, byte) in raw[at + 4..].iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        raw
+    }
+
+    #[test]
+    fn handshakes_are_accepted() {
+        let mut out = Vec::new();
+        handshake(&b"GET / HTTP/1.1\r\nHost: x\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"[..],
+                  &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 101 "));
+        assert!(out.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[test]
+    fn requests_without_a_key_are_refused() {
+        let mut out = Vec::new();
+        assert!(handshake(&b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"[..], &mut out).is_err());
+        assert!(out.starts_with(b"HTTP/1.1 400 "));
+        let mut out = Vec::new();
+        assert_eq!(handshake(&b"GET / HTTP/1.1\r\n"[..], &mut out).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn frames_are_unmasked() {
+        // the example from rfc 6455, masked and not
+        let masked = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        assert_eq!(read_frame(&masked[..]).unwrap(), (OP_TEXT, b"Hello".to_vec()));
+        assert_eq!(read_frame(&b"\x81\x05Hello"[..]).unwrap(), (OP_TEXT, b"Hello".to_vec()));
+        assert_eq!(frame(OP_TEXT, b"Hello"), masked);
+    }
+
+    #[test]
+    fn extended_lengths_are_read() {
+        for len in [0, 125, 126, 127, 0xffff, 0x10000] {
+            let payload: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let raw = frame(OP_TEXT, &payload);
+            let ext = match len {
+                0..=125 => 0,
+                126..=0xffff => 2,
+                _ => 8,
+            };
+            assert_eq!(raw.len(), 2 + ext + 4 + len);
+            assert_eq!(read_frame(&raw[..]).unwrap(), (OP_TEXT, payload), "{}", len);
+        }
+    }
+
+    #[test]
+    fn oversized_frames_are_refused() {
+        let raw = frame(OP_TEXT, &vec![0; MAX_FRAME + 1]);
+        assert_eq!(read_frame(&raw[..]).unwrap_err().to_string(), "frame too large");
+        // refused on the length alone, before any of it is sent
+        let head = [0x81, 0xff, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(read_frame(&head[..]).unwrap_err().to_string(), "frame too large");
+        // cut off partway
+        let raw = frame(OP_TEXT, b"Hello");
+        assert_eq!(read_frame(&raw[..raw.len() - 1]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn control_frames_are_answered() {
+        let status = SharedStatus::default();
+        let (tx, rx) = mpsc::channel();
+        let mut input = frame(OP_PING, b"hi");
+        input.extend(frame(0x2, b"binary is ignored"));
+        input.extend(frame(OP_TEXT, b" pause\n"));
+        input.extend(frame(OP_CLOSE, &[0x03, 0xe8]));
+        input.extend(frame(OP_TEXT, b"play"));
+
+        let mut out = Vec::new();
+        converse(&input[..], &mut out, &status, &tx).unwrap();
+        let mut out = &out[..];
+        assert_eq!(read_frame(&mut out).unwrap(), (OP_PONG, b"hi".to_vec()));
+        assert_eq!(read_frame(&mut out).unwrap(), (OP_TEXT, b"ok".to_vec()));
+        assert_eq!(read_frame(&mut out).unwrap(), (OP_CLOSE, vec![0x03, 0xe8]));
+        assert!(out.is_empty());
+        // nothing after the close is read
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [Command::Pause]);
+    }
+}